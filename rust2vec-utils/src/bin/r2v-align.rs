@@ -0,0 +1,177 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use clap::{App, AppSettings, Arg, ArgMatches};
+use rust2vec::align::{Align, SelfLearning};
+use rust2vec::prelude::*;
+use rust2vec_utils::{pairs_as_str, read_dictionary, read_embeddings_view, EmbeddingFormat};
+use stdinout::OrExit;
+
+static DEFAULT_CLAP_SETTINGS: &[AppSettings] = &[
+    AppSettings::DontCollapseArgsInUsage,
+    AppSettings::UnifiedHelpMessage,
+];
+
+struct Config {
+    dictionary_filename: String,
+    output_filename: String,
+    self_learning: Option<SelfLearning>,
+    source_filename: String,
+    source_format: EmbeddingFormat,
+    target_filename: String,
+    target_format: EmbeddingFormat,
+}
+
+// Option constants
+static N_CANDIDATES: &str = "n_candidates";
+static SELF_LEARNING: &str = "self_learning";
+static SOURCE_FORMAT: &str = "source_format";
+static TARGET_FORMAT: &str = "target_format";
+
+// Argument constants
+static DICTIONARY: &str = "DICTIONARY";
+static OUTPUT: &str = "OUTPUT";
+static SOURCE: &str = "SOURCE";
+static TARGET: &str = "TARGET";
+
+fn parse_args() -> ArgMatches<'static> {
+    App::new("r2v-align")
+        .settings(DEFAULT_CLAP_SETTINGS)
+        .arg(
+            Arg::with_name(SOURCE)
+                .help("Source embeddings")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(TARGET)
+                .help("Target embeddings")
+                .index(2)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(DICTIONARY)
+                .help("Seed dictionary with a source and a target word per line")
+                .index(3)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(OUTPUT)
+                .help("Aligned source embeddings (finalfusion)")
+                .index(4)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(N_CANDIDATES)
+                .short("c")
+                .long("candidates")
+                .value_name("N")
+                .help("Induce dictionary pairs from the N most frequent words (default: 10000)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(SOURCE_FORMAT)
+                .short("f")
+                .long("source-format")
+                .value_name("FORMAT")
+                .help("Source format: auto, finalfusion, finalfusion_mmap, word2vec, text, or textdims (default: auto)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(SELF_LEARNING)
+                .short("s")
+                .long("self-learning")
+                .value_name("N")
+                .help("Extend the dictionary using N self-learning iterations (default: 0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(TARGET_FORMAT)
+                .short("t")
+                .long("target-format")
+                .value_name("FORMAT")
+                .help("Target format: auto, finalfusion, finalfusion_mmap, word2vec, text, or textdims (default: auto)")
+                .takes_value(true),
+        )
+        .get_matches()
+}
+
+fn config_from_matches(matches: &ArgMatches) -> Config {
+    // Arguments
+    let source_filename = matches.value_of(SOURCE).unwrap().to_owned();
+    let target_filename = matches.value_of(TARGET).unwrap().to_owned();
+    let dictionary_filename = matches.value_of(DICTIONARY).unwrap().to_owned();
+    let output_filename = matches.value_of(OUTPUT).unwrap().to_owned();
+
+    // Options
+    let source_format = matches
+        .value_of(SOURCE_FORMAT)
+        .map(|v| EmbeddingFormat::try_from(v).or_exit("Cannot parse source format", 1))
+        .unwrap_or(EmbeddingFormat::Auto);
+    let target_format = matches
+        .value_of(TARGET_FORMAT)
+        .map(|v| EmbeddingFormat::try_from(v).or_exit("Cannot parse target format", 1))
+        .unwrap_or(EmbeddingFormat::Auto);
+
+    let iterations = matches
+        .value_of(SELF_LEARNING)
+        .map(|v| {
+            v.parse()
+                .or_exit("Cannot parse number of self-learning iterations", 1)
+        })
+        .unwrap_or(0);
+    let n_candidates = matches
+        .value_of(N_CANDIDATES)
+        .map(|v| v.parse().or_exit("Cannot parse number of candidates", 1))
+        .unwrap_or_else(|| SelfLearning::default().n_candidates);
+    let self_learning = if iterations == 0 {
+        None
+    } else {
+        Some(SelfLearning {
+            iterations,
+            n_candidates,
+        })
+    };
+
+    Config {
+        dictionary_filename,
+        output_filename,
+        self_learning,
+        source_filename,
+        source_format,
+        target_filename,
+        target_format,
+    }
+}
+
+fn main() {
+    let matches = parse_args();
+    let config = config_from_matches(&matches);
+
+    let source = read_embeddings_view(&config.source_filename, config.source_format)
+        .or_exit("Cannot read source embeddings", 1);
+    let target = read_embeddings_view(&config.target_filename, config.target_format)
+        .or_exit("Cannot read target embeddings", 1);
+
+    let dictionary =
+        read_dictionary(&config.dictionary_filename).or_exit("Cannot read dictionary", 1);
+    let pairs = pairs_as_str(&dictionary);
+
+    let (aligned, alignment) = source
+        .align(&target, &pairs, config.self_learning)
+        .or_exit("Cannot align embeddings", 1);
+
+    eprintln!(
+        "Seed pairs: {}/{}, dictionary pairs: {}, self-learning iterations: {}",
+        alignment.n_seed_pairs,
+        pairs.len(),
+        alignment.n_pairs,
+        alignment.iterations
+    );
+
+    let f = File::create(&config.output_filename).or_exit("Cannot create embeddings file", 1);
+    let mut writer = BufWriter::new(f);
+    aligned
+        .write_embeddings(&mut writer)
+        .or_exit("Cannot write embeddings", 1);
+}
@@ -0,0 +1,230 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter};
+use std::process;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use rust2vec::bias::{read_weat_sets, Debias, WordEmbeddingAssociation};
+use rust2vec::prelude::*;
+use rust2vec_utils::{pairs_as_str, read_dictionary, read_embeddings_view, EmbeddingFormat};
+use stdinout::OrExit;
+
+static DEFAULT_CLAP_SETTINGS: &[AppSettings] = &[
+    AppSettings::DontCollapseArgsInUsage,
+    AppSettings::UnifiedHelpMessage,
+];
+
+// Option constants
+static EMBEDDING_FORMAT: &str = "embedding_format";
+static EQUALIZE: &str = "equalize";
+static EXCLUDE: &str = "exclude";
+static N_PERMUTATIONS: &str = "n_permutations";
+
+// Argument constants
+static DEFINITIONAL: &str = "DEFINITIONAL";
+static EMBEDDINGS: &str = "EMBEDDINGS";
+static OUTPUT: &str = "OUTPUT";
+static WEAT_SETS: &str = "WEAT_SETS";
+
+// Subcommand constants
+static DEBIAS: &str = "debias";
+static WEAT: &str = "weat";
+
+fn format_arg() -> Arg<'static, 'static> {
+    Arg::with_name(EMBEDDING_FORMAT)
+        .short("f")
+        .long("format")
+        .value_name("FORMAT")
+        .help("Embedding format: auto, finalfusion, finalfusion_mmap, word2vec, text, or textdims (default: auto)")
+        .takes_value(true)
+}
+
+fn parse_args() -> ArgMatches<'static> {
+    App::new("r2v-bias")
+        .settings(DEFAULT_CLAP_SETTINGS)
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name(WEAT)
+                .about("Measure bias using the Word Embedding Association Test")
+                .settings(DEFAULT_CLAP_SETTINGS)
+                .arg(format_arg())
+                .arg(
+                    Arg::with_name(N_PERMUTATIONS)
+                        .short("p")
+                        .long("permutations")
+                        .value_name("N")
+                        .help("Number of permutations for the p-value (default: 10000)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(EMBEDDINGS)
+                        .help("Embeddings file")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(WEAT_SETS)
+                        .help("Target sets X, Y and attribute sets A, B, separated by empty lines")
+                        .index(2)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(DEBIAS)
+                .about("Remove a bias direction from the embeddings (hard debiasing)")
+                .settings(DEFAULT_CLAP_SETTINGS)
+                .arg(format_arg())
+                .arg(
+                    Arg::with_name(EQUALIZE)
+                        .short("e")
+                        .long("equalize")
+                        .value_name("FILE")
+                        .help("Equality pairs, one pair per line")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(EXCLUDE)
+                        .short("x")
+                        .long("exclude")
+                        .value_name("FILE")
+                        .help("Words that should not be neutralized, one per line")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(EMBEDDINGS)
+                        .help("Embeddings file")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(DEFINITIONAL)
+                        .help("Definitional pairs, one pair per line")
+                        .index(2)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(OUTPUT)
+                        .help("Debiased embeddings (finalfusion)")
+                        .index(3)
+                        .required(true),
+                ),
+        )
+        .get_matches()
+}
+
+fn embedding_format(matches: &ArgMatches) -> EmbeddingFormat {
+    matches
+        .value_of(EMBEDDING_FORMAT)
+        .map(|v| EmbeddingFormat::try_from(v).or_exit("Cannot parse embedding format", 1))
+        .unwrap_or(EmbeddingFormat::Auto)
+}
+
+fn report_missing(missing: &[String]) {
+    if !missing.is_empty() {
+        eprintln!("Missing words ({}): {}", missing.len(), missing.join(" "));
+    }
+}
+
+fn weat(matches: &ArgMatches) {
+    let embeddings = read_embeddings_view(
+        matches.value_of(EMBEDDINGS).unwrap(),
+        embedding_format(matches),
+    )
+    .or_exit("Cannot read embeddings", 1);
+    let n_permutations = matches
+        .value_of(N_PERMUTATIONS)
+        .map(|v| v.parse().or_exit("Cannot parse number of permutations", 1))
+        .unwrap_or(10_000);
+
+    let f = File::open(matches.value_of(WEAT_SETS).unwrap()).or_exit("Cannot open WEAT sets", 1);
+    let sets = read_weat_sets(BufReader::new(f)).or_exit("Cannot read WEAT sets", 1);
+
+    let weat = embeddings
+        .weat(&sets, n_permutations)
+        .or_exit("Cannot perform WEAT test", 1);
+
+    println!("Effect size: {:.4}", weat.effect_size);
+    println!("Test statistic: {:.4}", weat.test_statistic);
+    println!(
+        "p-value: {:.4} ({} permutations)",
+        weat.p_value, weat.n_permutations
+    );
+    report_missing(&weat.missing);
+}
+
+fn read_words(filename: &str) -> Vec<String> {
+    let f = File::open(filename).or_exit("Cannot open word list", 1);
+    BufReader::new(f)
+        .lines()
+        .map(|line| line.or_exit("Cannot read line", 1).trim().to_owned())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+fn debias(matches: &ArgMatches) {
+    let embeddings = read_embeddings_view(
+        matches.value_of(EMBEDDINGS).unwrap(),
+        embedding_format(matches),
+    )
+    .or_exit("Cannot read embeddings", 1);
+
+    let definitional = read_dictionary(matches.value_of(DEFINITIONAL).unwrap())
+        .or_exit("Cannot read definitional pairs", 1);
+    let equalize = matches
+        .value_of(EQUALIZE)
+        .map(|filename| read_dictionary(filename).or_exit("Cannot read equality pairs", 1))
+        .unwrap_or_default();
+    let exclude = matches
+        .value_of(EXCLUDE)
+        .map(read_words)
+        .unwrap_or_default();
+
+    let (debiased, debiasing) = embeddings
+        .debias(
+            &pairs_as_str(&definitional),
+            &pairs_as_str(&equalize),
+            &exclude.iter().map(String::as_str).collect::<Vec<_>>(),
+        )
+        .or_exit("Cannot debias embeddings", 1);
+
+    eprintln!(
+        "Definitional pairs: {}/{}, equalized pairs: {}/{}, neutralized words: {}",
+        debiasing.n_definitional_pairs,
+        definitional.len(),
+        debiasing.n_equalized,
+        equalize.len(),
+        debiasing.n_neutralized
+    );
+    report_missing(&debiasing.missing);
+    if !debiasing.unequalized.is_empty() {
+        eprintln!(
+            "Pairs with the same bias, not equalized ({}): {}",
+            debiasing.unequalized.len(),
+            debiasing
+                .unequalized
+                .iter()
+                .map(|(u, w)| format!("{}/{}", u, w))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+    }
+
+    let f =
+        File::create(matches.value_of(OUTPUT).unwrap()).or_exit("Cannot create embeddings file", 1);
+    let mut writer = BufWriter::new(f);
+    debiased
+        .write_embeddings(&mut writer)
+        .or_exit("Cannot write embeddings", 1);
+}
+
+fn main() {
+    let matches = parse_args();
+
+    match matches.subcommand() {
+        (name, Some(matches)) if name == WEAT => weat(matches),
+        (name, Some(matches)) if name == DEBIAS => debias(matches),
+        _ => {
+            eprintln!("{}", matches.usage());
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+
+use clap::{App, AppSettings, Arg, ArgMatches};
+use rayon::ThreadPoolBuilder;
+use rust2vec::cluster::{Cluster, Clustering, KMeans};
+use rust2vec::prelude::*;
+use rust2vec_utils::{read_embeddings_view, EmbeddingFormat};
+use stdinout::{Input, OrExit, Output};
+
+static DEFAULT_CLAP_SETTINGS: &[AppSettings] = &[
+    AppSettings::DontCollapseArgsInUsage,
+    AppSettings::UnifiedHelpMessage,
+];
+
+struct Config {
+    centroids_filename: Option<String>,
+    embeddings_filename: String,
+    embedding_format: EmbeddingFormat,
+    k_means: KMeans,
+    n_threads: usize,
+    unknown_filename: Option<String>,
+}
+
+// Option constants
+static CENTROIDS: &str = "centroids";
+static EMBEDDING_FORMAT: &str = "embedding_format";
+static N_CLUSTERS: &str = "n_clusters";
+static N_ITERATIONS: &str = "n_iterations";
+static N_THREADS: &str = "n_threads";
+static N_WORDS: &str = "n_words";
+static SPHERICAL: &str = "spherical";
+static UNKNOWN: &str = "unknown";
+
+// Argument constants
+static EMBEDDINGS: &str = "EMBEDDINGS";
+static OUTPUT: &str = "OUTPUT";
+
+fn config_from_matches(matches: &ArgMatches) -> Config {
+    // Arguments
+    let embeddings_filename = matches.value_of(EMBEDDINGS).unwrap().to_owned();
+
+    // Options
+    let centroids_filename = matches.value_of(CENTROIDS).map(ToOwned::to_owned);
+    let embedding_format = matches
+        .value_of(EMBEDDING_FORMAT)
+        .map(|v| EmbeddingFormat::try_from(v).or_exit("Cannot parse embedding format", 1))
+        .unwrap_or(EmbeddingFormat::Auto);
+    let n_clusters = matches
+        .value_of(N_CLUSTERS)
+        .map(|a| a.parse().or_exit("Cannot parse number of clusters", 1))
+        .unwrap_or(100);
+    let n_iterations = matches
+        .value_of(N_ITERATIONS)
+        .map(|a| a.parse().or_exit("Cannot parse number of iterations", 1))
+        .unwrap_or(100);
+    let n_threads = matches
+        .value_of(N_THREADS)
+        .map(|a| a.parse().or_exit("Cannot parse number of threads", 1))
+        .unwrap_or(num_cpus::get() / 2);
+    let n_embeddings = matches
+        .value_of(N_WORDS)
+        .map(|a| a.parse().or_exit("Cannot parse number of words", 1));
+    let unknown_filename = matches.value_of(UNKNOWN).map(ToOwned::to_owned);
+
+    Config {
+        centroids_filename,
+        embeddings_filename,
+        embedding_format,
+        k_means: KMeans {
+            n_clusters,
+            n_iterations,
+            n_embeddings,
+            spherical: matches.is_present(SPHERICAL),
+        },
+        n_threads,
+        unknown_filename,
+    }
+}
+
+fn parse_args() -> ArgMatches<'static> {
+    App::new("r2v-cluster")
+        .settings(DEFAULT_CLAP_SETTINGS)
+        .arg(
+            Arg::with_name(EMBEDDINGS)
+                .help("Embeddings file")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(OUTPUT)
+                .help("Output file for word/cluster pairs")
+                .index(2),
+        )
+        .arg(
+            Arg::with_name(CENTROIDS)
+                .short("c")
+                .long("centroids")
+                .value_name("FILE")
+                .help("Write the cluster centroids to a file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(EMBEDDING_FORMAT)
+                .short("f")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Embedding format: auto, finalfusion, finalfusion_mmap, word2vec, text, or textdims (default: auto)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(N_ITERATIONS)
+                .short("i")
+                .long("iter")
+                .value_name("N")
+                .help("Maximum number of iterations (default: 100)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(N_CLUSTERS)
+                .short("k")
+                .long("clusters")
+                .value_name("K")
+                .help("Number of clusters (default: 100)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(N_WORDS)
+                .short("n")
+                .long("words")
+                .value_name("N")
+                .help("Cluster the N most frequent words (default: all words)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(SPHERICAL)
+                .short("s")
+                .long("spherical")
+                .help("Use spherical k-means (cosine similarity)"),
+        )
+        .arg(
+            Arg::with_name(N_THREADS)
+                .short("t")
+                .long("threads")
+                .value_name("N")
+                .help("Number of threads (default: logical_cpus /2)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(UNKNOWN)
+                .short("u")
+                .long("unknown")
+                .value_name("FILE")
+                .help("Assign the words in this file (one per line) to clusters using their (subword) embeddings")
+                .takes_value(true),
+        )
+        .get_matches()
+}
+
+fn write_centroids(clustering: &Clustering, filename: &str) {
+    let f = File::create(filename).or_exit("Cannot create centroids file", 1);
+    let mut writer = BufWriter::new(f);
+
+    for (idx, centroid) in clustering.centroids.outer_iter().enumerate() {
+        let components = centroid.iter().map(ToString::to_string).collect::<Vec<_>>();
+        writeln!(writer, "{} {}", idx, components.join(" ")).or_exit("Cannot write centroid", 1);
+    }
+}
+
+fn main() {
+    let matches = parse_args();
+    let mut config = config_from_matches(&matches);
+
+    ThreadPoolBuilder::new()
+        .num_threads(config.n_threads)
+        .build_global()
+        .unwrap();
+
+    let embeddings = read_embeddings_view(&config.embeddings_filename, config.embedding_format)
+        .or_exit("Cannot read embeddings", 1);
+
+    // Storage of subword embeddings also contains subword units, only
+    // cluster the embeddings of known words.
+    let n_words = embeddings.vocab().len();
+    config.k_means.n_embeddings = Some(config.k_means.n_embeddings.unwrap_or(n_words).min(n_words));
+
+    let clustering = embeddings
+        .storage()
+        .cluster(config.k_means)
+        .or_exit("Cannot cluster embeddings", 1);
+    eprintln!(
+        "Clustered {} words in {} iterations",
+        clustering.assignments.len(),
+        clustering.iterations
+    );
+
+    let output = Output::from(matches.value_of(OUTPUT));
+    let mut writer = BufWriter::new(output.write().or_exit("Cannot open output for writing", 1));
+
+    for (word, &cluster) in embeddings
+        .vocab()
+        .words()
+        .iter()
+        .zip(&clustering.assignments)
+    {
+        writeln!(writer, "{}\t{}", word, cluster).or_exit("Cannot write cluster", 1);
+    }
+
+    if let Some(filename) = config.unknown_filename {
+        let input = Input::from(Some(filename));
+        let reader = input.buf_read().or_exit("Cannot open unknown words", 1);
+        for line in reader.lines() {
+            let line = line.or_exit("Cannot read line", 1);
+            let word = line.trim();
+            if word.is_empty() {
+                continue;
+            }
+
+            match embeddings.embedding(word) {
+                Some(embedding) => writeln!(
+                    writer,
+                    "{}\t{}",
+                    word,
+                    clustering.assign(embedding.as_view())
+                )
+                .or_exit("Cannot write cluster", 1),
+                None => eprintln!("Word does not have an embedding: {}", word),
+            }
+        }
+    }
+
+    if let Some(filename) = config.centroids_filename {
+        write_centroids(&clustering, &filename);
+    }
+}
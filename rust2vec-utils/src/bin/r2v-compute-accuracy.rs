@@ -1,13 +1,14 @@
 use std::collections::BTreeMap;
-use std::io::BufRead;
-use std::sync::{Arc, Mutex};
+use std::io::{self, BufRead};
+use std::process;
 
 use clap::{App, AppSettings, Arg, ArgMatches};
 use rayon::prelude::*;
 use rayon::ThreadPoolBuilder;
 use rust2vec::prelude::*;
-use rust2vec::similarity::Analogy;
+use rust2vec::similarity::{Analogy, AnalogyObjective, QueryOptions};
 use rust2vec_utils::{read_embeddings_view, EmbeddingFormat};
+use serde::Serialize;
 use stdinout::{Input, OrExit};
 
 static DEFAULT_CLAP_SETTINGS: &[AppSettings] = &[
@@ -24,27 +25,69 @@ fn main() {
         .build_global()
         .unwrap();
 
-    let embeddings =
-        read_embeddings_view(&config.embeddings_filename, EmbeddingFormat::FinalFusion)
-            .or_exit("Cannot read embeddings", 1);
+    let embeddings = read_embeddings_view(&config.embeddings_filename, config.embedding_format)
+        .or_exit("Cannot read embeddings", 1);
 
-    let analogies_file = Input::from(config.analogies_filename);
+    let analogies_file = Input::from(config.analogies_filename.clone());
     let reader = analogies_file
         .buf_read()
         .or_exit("Cannot open analogy file for reading", 1);
 
     let instances = read_analogies(reader);
-    process_analogies(&embeddings, &instances);
+    process_analogies(&embeddings, &config, &instances);
 }
 
 // Option constants
 static EMBEDDINGS: &str = "EMBEDDINGS";
 static ANALOGIES: &str = "ANALOGIES";
+static FORMAT: &str = "format";
+static METHOD: &str = "method";
+static N_CANDIDATES: &str = "n_candidates";
+static OUTPUT: &str = "output";
+static PREDICTIONS: &str = "predictions";
 static THREADS: &str = "threads";
+static VOCAB_LIMIT: &str = "vocab_limit";
 
 fn parse_args() -> ArgMatches<'static> {
     App::new("r2v-compute-accuracy")
         .settings(DEFAULT_CLAP_SETTINGS)
+        .arg(
+            Arg::with_name(FORMAT)
+                .short("f")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Embedding format: auto, finalfusion, finalfusion_mmap, word2vec, text, or textdims (default: auto)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(METHOD)
+                .short("m")
+                .long("method")
+                .value_name("METHOD")
+                .help("Analogy objective: 3cosadd, 3cosmul, or pairdirection (default: 3cosadd)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(N_CANDIDATES)
+                .short("k")
+                .value_name("K")
+                .help("Number of candidates per prediction (default: 5)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(OUTPUT)
+                .short("o")
+                .long("output")
+                .value_name("FORMAT")
+                .help("Output format: text, json, or tsv (default: text)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(PREDICTIONS)
+                .short("p")
+                .long("predictions")
+                .help("Output per-instance predictions (json and tsv output)"),
+        )
         .arg(
             Arg::with_name(THREADS)
                 .long("threads")
@@ -52,6 +95,13 @@ fn parse_args() -> ArgMatches<'static> {
                 .help("Number of threads (default: logical_cpus / 2)")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name(VOCAB_LIMIT)
+                .long("vocab-limit")
+                .value_name("N")
+                .help("Only use questions and candidates from the N most frequent words")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name(EMBEDDINGS)
                 .help("Embedding file")
@@ -62,134 +112,357 @@ fn parse_args() -> ArgMatches<'static> {
         .get_matches()
 }
 
+#[derive(Clone, Copy)]
+enum OutputFormat {
+    Json,
+    Text,
+    Tsv,
+}
+
 struct Config {
     analogies_filename: Option<String>,
     embeddings_filename: String,
+    embedding_format: EmbeddingFormat,
+    n_candidates: usize,
     n_threads: usize,
+    objective: AnalogyObjective,
+    output: OutputFormat,
+    predictions: bool,
+    vocab_limit: Option<usize>,
 }
 
 fn config_from_matches(matches: &ArgMatches) -> Config {
     let embeddings_filename = matches.value_of(EMBEDDINGS).unwrap().to_owned();
     let analogies_filename = matches.value_of(ANALOGIES).map(ToOwned::to_owned);
+    let embedding_format = matches
+        .value_of(FORMAT)
+        .map(|f| EmbeddingFormat::try_from(f).or_exit("Cannot parse embedding format", 1))
+        .unwrap_or(EmbeddingFormat::Auto);
+    let objective = matches
+        .value_of(METHOD)
+        .map(|v| v.parse().or_exit("Cannot parse analogy objective", 1))
+        .unwrap_or(AnalogyObjective::CosAdd);
     let n_threads = matches
         .value_of("threads")
         .map(|v| v.parse().or_exit("Cannot parse number of threads", 1))
         .unwrap_or(num_cpus::get() / 2);
+    let n_candidates = matches
+        .value_of(N_CANDIDATES)
+        .map(|v| v.parse().or_exit("Cannot parse number of candidates", 1))
+        .unwrap_or(5);
+    let output = match matches.value_of(OUTPUT) {
+        Some("text") | None => OutputFormat::Text,
+        Some("json") => OutputFormat::Json,
+        Some("tsv") => OutputFormat::Tsv,
+        Some(output) => {
+            eprintln!("Unknown output format: {}", output);
+            process::exit(1);
+        }
+    };
+    let predictions = matches.is_present(PREDICTIONS);
+    let vocab_limit = matches
+        .value_of(VOCAB_LIMIT)
+        .map(|v| v.parse().or_exit("Cannot parse vocabulary limit", 1));
 
     Config {
         analogies_filename,
         embeddings_filename,
+        embedding_format,
+        n_candidates,
         n_threads,
+        objective,
+        output,
+        predictions,
+        vocab_limit,
     }
 }
 
+#[derive(Default)]
 struct Counts {
     n_correct: usize,
     n_instances: usize,
     n_skipped: usize,
+    reciprocal_rank_sum: f64,
+}
+
+impl Counts {
+    fn add(&mut self, prediction: &Prediction) {
+        if prediction.skipped {
+            self.n_skipped += 1;
+            return;
+        }
+
+        self.n_instances += 1;
+        if prediction.gold_rank == Some(1) {
+            self.n_correct += 1;
+        }
+        if let Some(rank) = prediction.gold_rank {
+            self.reciprocal_rank_sum += 1. / rank as f64;
+        }
+    }
+
+    fn accuracy(&self) -> Option<f64> {
+        if self.n_instances == 0 {
+            return None;
+        }
+
+        Some(self.n_correct as f64 / self.n_instances as f64)
+    }
+
+    fn mrr(&self) -> Option<f64> {
+        if self.n_instances == 0 {
+            return None;
+        }
+
+        Some(self.reciprocal_rank_sum / self.n_instances as f64)
+    }
+
+    fn merge(&mut self, other: &Counts) {
+        self.n_correct += other.n_correct;
+        self.n_instances += other.n_instances;
+        self.n_skipped += other.n_skipped;
+        self.reciprocal_rank_sum += other.reciprocal_rank_sum;
+    }
+}
+
+/// Evaluation results of a section (or of all sections).
+#[derive(Serialize)]
+struct SectionResults<'a> {
+    section: &'a str,
+    n_correct: usize,
+    n_instances: usize,
+    n_skipped: usize,
+    accuracy: Option<f64>,
+    mrr: Option<f64>,
 }
 
-impl Default for Counts {
-    fn default() -> Self {
-        Counts {
-            n_correct: 0,
-            n_instances: 0,
-            n_skipped: 0,
+impl<'a> SectionResults<'a> {
+    fn new(section: &'a str, counts: &Counts) -> Self {
+        SectionResults {
+            section,
+            n_correct: counts.n_correct,
+            n_instances: counts.n_instances,
+            n_skipped: counts.n_skipped,
+            accuracy: counts.accuracy(),
+            mrr: counts.mrr(),
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Serialize)]
+struct Results<'a> {
+    sections: Vec<SectionResults<'a>>,
+    total: SectionResults<'a>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    predictions: Option<&'a [Prediction<'a>]>,
+}
+
+/// The prediction for an analogy instance.
+#[derive(Serialize)]
+struct Prediction<'a> {
+    section: &'a str,
+    query: [&'a str; 3],
+    answer: &'a str,
+
+    /// The answer is not in the vocabulary.
+    skipped: bool,
+
+    /// The highest-ranked candidates.
+    candidates: Vec<String>,
+
+    /// The (1-based) rank of the answer, `None` if the answer could
+    /// not be ranked.
+    gold_rank: Option<usize>,
+}
+
 struct Eval<'a> {
     embeddings: &'a Embeddings<VocabWrap, StorageViewWrap>,
-    section_counts: Arc<Mutex<BTreeMap<String, Counts>>>,
+    n_candidates: usize,
+    objective: AnalogyObjective,
+    options: QueryOptions<'a>,
+    predictions: bool,
+    vocab_limit: bool,
 }
 
 impl<'a> Eval<'a> {
-    fn new(embeddings: &'a Embeddings<VocabWrap, StorageViewWrap>) -> Self {
+    fn new(embeddings: &'a Embeddings<VocabWrap, StorageViewWrap>, config: &Config) -> Self {
+        let options = match config.vocab_limit {
+            Some(limit) => QueryOptions::default().with_vocab_limit(limit),
+            None => QueryOptions::default(),
+        };
+
         Eval {
             embeddings,
-            section_counts: Arc::new(Mutex::new(BTreeMap::new())),
+            n_candidates: config.n_candidates,
+            objective: config.objective,
+            options,
+            predictions: config.predictions,
+            vocab_limit: config.vocab_limit.is_some(),
         }
     }
 
-    /// Evaluate an analogy.
-    fn eval_analogy(&self, instance: &Instance) {
+    /// Check whether an instance should be skipped.
+    fn skip_instance(&self, instance: &Instance) -> bool {
+        let vocab = self.embeddings.vocab();
+
+        // With a vocabulary limit, questions are skipped when any of
+        // their words is outside the limit, as in word2vec's
+        // compute-accuracy.
+        if self.vocab_limit {
+            return [
+                &instance.query.0,
+                &instance.query.1,
+                &instance.query.2,
+                &instance.answer,
+            ]
+            .iter()
+            .any(|word| !self.options.is_candidate(vocab, word));
+        }
+
         // Skip instances where the to-be-predicted word is not in the
         // vocab. This is a shortcoming of the vocab size and not of the
         // embedding model itself.
-        if self.embeddings.vocab().idx(&instance.answer).is_none() {
-            let mut section_counts = self.section_counts.lock().unwrap();
-            let counts = section_counts.entry(instance.section.clone()).or_default();
-            counts.n_skipped += 1;
-            return;
+        vocab.idx(&instance.answer).is_none()
+    }
+
+    /// Evaluate an analogy.
+    fn eval_analogy<'b>(&self, instance: &'b Instance) -> Prediction<'b> {
+        let mut prediction = Prediction {
+            section: &instance.section,
+            query: [&instance.query.0, &instance.query.1, &instance.query.2],
+            answer: &instance.answer,
+            skipped: false,
+            candidates: Vec::new(),
+            gold_rank: None,
+        };
+
+        if self.skip_instance(instance) {
+            prediction.skipped = true;
+            return prediction;
         }
 
-        // If the model is not able to provide a query result, it is counted
+        // Find the rank of the answer without sorting all candidates. If
+        // the model is not able to provide a query result, it is counted
         // as an error.
-        let is_correct = self
-            .embeddings
-            .analogy(&instance.query.0, &instance.query.1, &instance.query.2, 1)
-            .map(|r| r.first().unwrap().word == instance.answer)
-            .unwrap_or(false);
-
-        let mut section_counts = self.section_counts.lock().unwrap();
-        let counts = section_counts.entry(instance.section.clone()).or_default();
-        counts.n_instances += 1;
-        if is_correct {
-            counts.n_correct += 1;
+        prediction.gold_rank = self.embeddings.analogy_rank(
+            &instance.query.0,
+            &instance.query.1,
+            &instance.query.2,
+            &instance.answer,
+            self.objective,
+            &self.options,
+        );
+
+        // The highest-ranked candidates are only needed for predictions.
+        if self.predictions {
+            if let Some(results) = self.embeddings.analogy_with_options(
+                &instance.query.0,
+                &instance.query.1,
+                &instance.query.2,
+                self.n_candidates,
+                self.objective,
+                &self.options,
+            ) {
+                prediction.candidates = results.into_iter().map(|r| r.word.to_owned()).collect();
+            }
         }
+
+        prediction
     }
+}
 
-    /// Print the accuracy for a section.
-    fn print_section_accuracy(&self, section: &str, counts: &Counts) {
-        if counts.n_instances == 0 {
-            eprintln!("{}: no evaluation instances", section);
-            return;
+/// Collect counts per section.
+fn section_counts<'a>(predictions: &[Prediction<'a>]) -> BTreeMap<&'a str, Counts> {
+    let mut section_counts = BTreeMap::new();
+    for prediction in predictions {
+        section_counts
+            .entry(prediction.section)
+            .or_insert_with(Counts::default)
+            .add(prediction);
+    }
+
+    section_counts
+}
+
+fn format_percentage(value: Option<f64>) -> String {
+    value
+        .map(|v| format!("{:.2}", v * 100.))
+        .unwrap_or_else(|| "n/a".to_owned())
+}
+
+fn write_text(results: &Results) {
+    for section in &results.sections {
+        if section.n_instances == 0 {
+            eprintln!("{}: no evaluation instances", section.section);
+            continue;
         }
 
         println!(
-            "{}: {}/{} correct, accuracy: {:.2}, skipped: {}",
-            section,
-            counts.n_correct,
-            counts.n_instances,
-            (counts.n_correct as f64 / counts.n_instances as f64) * 100.,
-            counts.n_skipped,
+            "{}: {}/{} correct, accuracy: {}, MRR: {:.4}, skipped: {}",
+            section.section,
+            section.n_correct,
+            section.n_instances,
+            format_percentage(section.accuracy),
+            section.mrr.unwrap_or(0.),
+            section.n_skipped,
         );
     }
-}
 
-impl<'a> Drop for Eval<'a> {
-    fn drop(&mut self) {
-        let section_counts = self.section_counts.lock().unwrap();
+    let total = &results.total;
+
+    // Print out overall counts.
+    println!(
+        "Total: {}/{} correct, accuracy: {}, MRR: {:.4}",
+        total.n_correct,
+        total.n_instances,
+        format_percentage(total.accuracy),
+        total.mrr.unwrap_or(0.)
+    );
+
+    // Print skip counts.
+    let n_instances_with_skipped = total.n_instances + total.n_skipped;
+    println!(
+        "Skipped: {}/{} ({}%)",
+        total.n_skipped,
+        n_instances_with_skipped,
+        (total.n_skipped as f64 / n_instances_with_skipped as f64) * 100.
+    );
+}
 
-        // Print out counts for all sections.
-        for (section, counts) in section_counts.iter() {
-            self.print_section_accuracy(section, counts);
+fn write_tsv(results: &Results) {
+    if let Some(predictions) = results.predictions {
+        println!("section\tquery1\tquery2\tquery3\tanswer\tskipped\tgold_rank\tcandidates");
+        for prediction in predictions {
+            println!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                prediction.section,
+                prediction.query[0],
+                prediction.query[1],
+                prediction.query[2],
+                prediction.answer,
+                prediction.skipped,
+                prediction
+                    .gold_rank
+                    .map(|rank| rank.to_string())
+                    .unwrap_or_default(),
+                prediction.candidates.join(" ")
+            );
         }
 
-        let n_correct = section_counts.values().map(|c| c.n_correct).sum::<usize>();
-        let n_instances = section_counts
-            .values()
-            .map(|c| c.n_instances)
-            .sum::<usize>();
-        let n_skipped = section_counts.values().map(|c| c.n_skipped).sum::<usize>();
-        let n_instances_with_skipped = n_instances + n_skipped;
-
-        // Print out overall counts.
-        println!(
-            "Total: {}/{} correct, accuracy: {:.2}",
-            n_correct,
-            n_instances,
-            (n_correct as f64 / n_instances as f64) * 100.
-        );
+        return;
+    }
 
-        // Print skip counts.
+    println!("section\tcorrect\tinstances\tskipped\taccuracy\tmrr");
+    for section in results.sections.iter().chain(Some(&results.total)) {
         println!(
-            "Skipped: {}/{} ({}%)",
-            n_skipped,
-            n_instances_with_skipped,
-            (n_skipped as f64 / n_instances_with_skipped as f64) * 100.
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            section.section,
+            section.n_correct,
+            section.n_instances,
+            section.n_skipped,
+            section.accuracy.map(|v| v.to_string()).unwrap_or_default(),
+            section.mrr.map(|v| v.to_string()).unwrap_or_default()
         );
     }
 }
@@ -229,9 +502,44 @@ fn read_analogies(reader: impl BufRead) -> Vec<Instance> {
     instances
 }
 
-fn process_analogies(embeddings: &Embeddings<VocabWrap, StorageViewWrap>, instances: &[Instance]) {
-    let eval = Eval::new(&embeddings);
-    instances
+fn process_analogies(
+    embeddings: &Embeddings<VocabWrap, StorageViewWrap>,
+    config: &Config,
+    instances: &[Instance],
+) {
+    let eval = Eval::new(embeddings, config);
+    let predictions = instances
         .par_iter()
-        .for_each(|instance| eval.eval_analogy(instance));
+        .map(|instance| eval.eval_analogy(instance))
+        .collect::<Vec<_>>();
+
+    let section_counts = section_counts(&predictions);
+    let mut total = Counts::default();
+    for counts in section_counts.values() {
+        total.merge(counts);
+    }
+
+    let results = Results {
+        sections: section_counts
+            .iter()
+            .map(|(section, counts)| SectionResults::new(section, counts))
+            .collect(),
+        total: SectionResults::new("Total", &total),
+        predictions: if config.predictions {
+            Some(&predictions)
+        } else {
+            None
+        },
+    };
+
+    match config.output {
+        OutputFormat::Json => {
+            let stdout = io::stdout();
+            serde_json::to_writer_pretty(stdout.lock(), &results)
+                .or_exit("Cannot write results", 1);
+            println!();
+        }
+        OutputFormat::Text => write_text(&results),
+        OutputFormat::Tsv => write_tsv(&results),
+    }
 }
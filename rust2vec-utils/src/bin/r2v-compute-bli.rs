@@ -0,0 +1,279 @@
+use std::collections::{BTreeMap, HashSet};
+use std::process;
+
+use clap::{App, AppSettings, Arg, ArgMatches};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use rust2vec::align::Csls;
+use rust2vec::prelude::*;
+use rust2vec::similarity::WordSimilarity;
+use rust2vec::vocab::WordIndex;
+use rust2vec_utils::{read_dictionary, read_embeddings_view, EmbeddingFormat};
+use stdinout::OrExit;
+
+static DEFAULT_CLAP_SETTINGS: &[AppSettings] = &[
+    AppSettings::DontCollapseArgsInUsage,
+    AppSettings::UnifiedHelpMessage,
+];
+
+/// Precision is computed at these ranks.
+static RANKS: &[usize] = &[1, 5];
+
+#[derive(Clone, Copy)]
+enum Method {
+    Cosine,
+    Csls,
+}
+
+struct Config {
+    dictionary_filename: String,
+    k: usize,
+    method: Method,
+    n_candidates: usize,
+    n_threads: usize,
+    source_filename: String,
+    source_format: EmbeddingFormat,
+    target_filename: String,
+    target_format: EmbeddingFormat,
+}
+
+// Option constants
+static METHOD: &str = "method";
+static N_CANDIDATES: &str = "n_candidates";
+static NEIGHBORS: &str = "neighbors";
+static SOURCE_FORMAT: &str = "source_format";
+static TARGET_FORMAT: &str = "target_format";
+static THREADS: &str = "threads";
+
+// Argument constants
+static DICTIONARY: &str = "DICTIONARY";
+static SOURCE: &str = "SOURCE";
+static TARGET: &str = "TARGET";
+
+fn parse_args() -> ArgMatches<'static> {
+    App::new("r2v-compute-bli")
+        .settings(DEFAULT_CLAP_SETTINGS)
+        .arg(
+            Arg::with_name(SOURCE)
+                .help("Source embeddings, aligned to the target embeddings")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(TARGET)
+                .help("Target embeddings")
+                .index(2)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(DICTIONARY)
+                .help("Dictionary with a source and a target word per line")
+                .index(3)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(N_CANDIDATES)
+                .short("c")
+                .long("candidates")
+                .value_name("N")
+                .help("Search CSLS neighbors among the N most frequent words (default: 10000)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(SOURCE_FORMAT)
+                .short("f")
+                .long("source-format")
+                .value_name("FORMAT")
+                .help("Source format: auto, finalfusion, finalfusion_mmap, word2vec, text, or textdims (default: auto)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(NEIGHBORS)
+                .short("k")
+                .long("neighbors")
+                .value_name("K")
+                .help("Number of neighbors used by CSLS (default: 10)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(METHOD)
+                .short("m")
+                .long("method")
+                .value_name("METHOD")
+                .help("Retrieval method: cosine or csls (default: csls)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(TARGET_FORMAT)
+                .short("t")
+                .long("target-format")
+                .value_name("FORMAT")
+                .help("Target format: auto, finalfusion, finalfusion_mmap, word2vec, text, or textdims (default: auto)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(THREADS)
+                .long("threads")
+                .value_name("N")
+                .help("Number of threads (default: logical_cpus / 2)")
+                .takes_value(true),
+        )
+        .get_matches()
+}
+
+fn config_from_matches(matches: &ArgMatches) -> Config {
+    // Arguments
+    let source_filename = matches.value_of(SOURCE).unwrap().to_owned();
+    let target_filename = matches.value_of(TARGET).unwrap().to_owned();
+    let dictionary_filename = matches.value_of(DICTIONARY).unwrap().to_owned();
+
+    // Options
+    let source_format = matches
+        .value_of(SOURCE_FORMAT)
+        .map(|v| EmbeddingFormat::try_from(v).or_exit("Cannot parse source format", 1))
+        .unwrap_or(EmbeddingFormat::Auto);
+    let target_format = matches
+        .value_of(TARGET_FORMAT)
+        .map(|v| EmbeddingFormat::try_from(v).or_exit("Cannot parse target format", 1))
+        .unwrap_or(EmbeddingFormat::Auto);
+    let k = matches
+        .value_of(NEIGHBORS)
+        .map(|v| v.parse().or_exit("Cannot parse number of neighbors", 1))
+        .unwrap_or(10);
+    let n_candidates = matches
+        .value_of(N_CANDIDATES)
+        .map(|v| v.parse().or_exit("Cannot parse number of candidates", 1))
+        .unwrap_or(10_000);
+    let method = match matches.value_of(METHOD) {
+        Some("csls") | None => Method::Csls,
+        Some("cosine") => Method::Cosine,
+        Some(method) => {
+            eprintln!("Unknown retrieval method: {}", method);
+            process::exit(1);
+        }
+    };
+    let n_threads = matches
+        .value_of(THREADS)
+        .map(|v| v.parse().or_exit("Cannot parse number of threads", 1))
+        .unwrap_or(num_cpus::get() / 2);
+
+    Config {
+        dictionary_filename,
+        k,
+        method,
+        n_candidates,
+        n_threads,
+        source_filename,
+        source_format,
+        target_filename,
+        target_format,
+    }
+}
+
+fn main() {
+    let matches = parse_args();
+    let config = config_from_matches(&matches);
+
+    ThreadPoolBuilder::new()
+        .num_threads(config.n_threads)
+        .build_global()
+        .unwrap();
+
+    let source = read_embeddings_view(&config.source_filename, config.source_format)
+        .or_exit("Cannot read source embeddings", 1);
+    let target = read_embeddings_view(&config.target_filename, config.target_format)
+        .or_exit("Cannot read target embeddings", 1);
+
+    // A source word can have multiple translations.
+    let mut translations = BTreeMap::new();
+    for (source_word, target_word) in
+        read_dictionary(&config.dictionary_filename).or_exit("Cannot read dictionary", 1)
+    {
+        translations
+            .entry(source_word)
+            .or_insert_with(HashSet::new)
+            .insert(target_word);
+    }
+
+    // Skip source words for which none of the translations is in the
+    // target vocabulary.
+    let n_words = translations.len();
+    let translations = translations
+        .into_iter()
+        .filter(|(_, targets)| {
+            targets
+                .iter()
+                .any(|t| matches!(target.vocab().idx(t), Some(WordIndex::Word(_))))
+        })
+        .collect::<Vec<_>>();
+
+    let csls = match config.method {
+        Method::Csls => {
+            let csls = Csls::new(&source, &target, config.k, config.n_candidates)
+                .or_exit("Cannot initialize CSLS", 1);
+            Some(csls)
+        }
+        Method::Cosine => None,
+    };
+
+    let max_rank = RANKS.iter().cloned().max().unwrap_or(1);
+    let gold_ranks = translations
+        .par_iter()
+        .filter_map(|(source_word, targets)| {
+            let embed = source.embedding(source_word)?;
+            let results = match csls {
+                Some(ref csls) => csls.translate_embedding(embed.as_view(), max_rank),
+                None => cosine_translations(&target, embed.as_view(), max_rank),
+            };
+            Some(
+                results
+                    .iter()
+                    .position(|result| targets.contains(result.word))
+                    .map(|rank| rank + 1),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let n_evaluated = gold_ranks.len();
+    if n_evaluated == 0 {
+        eprintln!("None of the dictionary words could be evaluated");
+        process::exit(1);
+    }
+
+    for &rank in RANKS {
+        let n_correct = gold_ranks
+            .iter()
+            .filter(|gold_rank| gold_rank.map(|r| r <= rank).unwrap_or(false))
+            .count();
+        println!(
+            "P@{}: {:.2} ({}/{})",
+            rank,
+            (n_correct as f64 / n_evaluated as f64) * 100.,
+            n_correct,
+            n_evaluated
+        );
+    }
+
+    let n_skipped = n_words - n_evaluated;
+    println!(
+        "Skipped: {}/{} ({:.2}%)",
+        n_skipped,
+        n_words,
+        (n_skipped as f64 / n_words as f64) * 100.
+    );
+}
+
+fn cosine_translations<'a>(
+    target: &'a Embeddings<VocabWrap, StorageViewWrap>,
+    embed: ndarray::ArrayView1<f32>,
+    limit: usize,
+) -> Vec<WordSimilarity<'a>> {
+    use rust2vec::similarity::EmbeddingSimilarity;
+
+    target
+        .embedding_similarity(embed, &HashSet::new(), limit)
+        .or_exit(
+            "Source and target embeddings have different dimensionalities",
+            1,
+        )
+}
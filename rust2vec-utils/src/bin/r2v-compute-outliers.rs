@@ -0,0 +1,107 @@
+use std::fs::File;
+use std::io::BufReader;
+
+use clap::{App, AppSettings, Arg, ArgMatches};
+use rust2vec::evaluation::{read_outlier_set, EvaluateOutliers, OutlierEvaluation};
+use rust2vec_utils::{read_embeddings_view, EmbeddingFormat};
+use stdinout::OrExit;
+
+static DEFAULT_CLAP_SETTINGS: &[AppSettings] = &[
+    AppSettings::DontCollapseArgsInUsage,
+    AppSettings::UnifiedHelpMessage,
+];
+
+struct Config {
+    embeddings_filename: String,
+    embedding_format: EmbeddingFormat,
+    sets: Vec<String>,
+}
+
+// Option constants
+static FORMAT: &str = "format";
+
+// Argument constants
+static EMBEDDINGS: &str = "EMBEDDINGS";
+static SETS: &str = "SETS";
+
+fn parse_args() -> ArgMatches<'static> {
+    App::new("r2v-compute-outliers")
+        .settings(DEFAULT_CLAP_SETTINGS)
+        .arg(
+            Arg::with_name(FORMAT)
+                .short("f")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Embedding format: auto, finalfusion, finalfusion_mmap, word2vec, text, or textdims (default: auto)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(EMBEDDINGS)
+                .help("Embedding file")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(SETS)
+                .help("Outlier sets with cluster words and outliers, separated by an empty line")
+                .index(2)
+                .multiple(true)
+                .required(true),
+        )
+        .get_matches()
+}
+
+fn config_from_matches(matches: &ArgMatches) -> Config {
+    let embeddings_filename = matches.value_of(EMBEDDINGS).unwrap().to_owned();
+    let sets = matches
+        .values_of(SETS)
+        .unwrap()
+        .map(ToOwned::to_owned)
+        .collect();
+    let embedding_format = matches
+        .value_of(FORMAT)
+        .map(|f| EmbeddingFormat::try_from(f).or_exit("Cannot parse embedding format", 1))
+        .unwrap_or(EmbeddingFormat::Auto);
+
+    Config {
+        embeddings_filename,
+        embedding_format,
+        sets,
+    }
+}
+
+fn print_evaluation(name: &str, eval: &OutlierEvaluation) {
+    match (eval.accuracy(), eval.outlier_position_percentage()) {
+        (Some(accuracy), Some(opp)) => println!(
+            "{}: {}/{} detected, accuracy: {:.2}, OPP: {:.2}, skipped: {}",
+            name,
+            eval.n_detected,
+            eval.n_instances,
+            accuracy * 100.,
+            opp * 100.,
+            eval.n_skipped
+        ),
+        _ => eprintln!("{}: no evaluation instances", name),
+    }
+}
+
+fn main() {
+    let matches = parse_args();
+    let config = config_from_matches(&matches);
+
+    let embeddings = read_embeddings_view(&config.embeddings_filename, config.embedding_format)
+        .or_exit("Cannot read embeddings", 1);
+
+    let mut total = OutlierEvaluation::default();
+    for filename in &config.sets {
+        let f = File::open(filename).or_exit(format!("Cannot open {}", filename), 1);
+        let set = read_outlier_set(BufReader::new(f))
+            .or_exit(format!("Cannot read outlier set {}", filename), 1);
+
+        let eval = embeddings.evaluate_outliers(&[set]);
+        print_evaluation(filename, &eval);
+        total.merge(&eval);
+    }
+
+    print_evaluation("Total", &total);
+}
@@ -0,0 +1,123 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::process;
+
+use clap::{App, AppSettings, Arg, ArgMatches};
+use rust2vec::evaluation::{read_similarity_instances, EvaluateSimilarity};
+use rust2vec_utils::{read_embeddings_view, EmbeddingFormat};
+use stdinout::OrExit;
+
+static DEFAULT_CLAP_SETTINGS: &[AppSettings] = &[
+    AppSettings::DontCollapseArgsInUsage,
+    AppSettings::UnifiedHelpMessage,
+];
+
+struct Config {
+    datasets: Vec<String>,
+    embeddings_filename: String,
+    embedding_format: EmbeddingFormat,
+    score_column: Option<usize>,
+}
+
+// Option constants
+static FORMAT: &str = "format";
+static SCORE_COLUMN: &str = "score_column";
+
+// Argument constants
+static DATASETS: &str = "DATASETS";
+static EMBEDDINGS: &str = "EMBEDDINGS";
+
+fn parse_args() -> ArgMatches<'static> {
+    App::new("r2v-compute-similarity")
+        .settings(DEFAULT_CLAP_SETTINGS)
+        .arg(
+            Arg::with_name(FORMAT)
+                .short("f")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Embedding format: auto, finalfusion, finalfusion_mmap, word2vec, text, or textdims (default: auto)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(SCORE_COLUMN)
+                .short("s")
+                .long("score-column")
+                .value_name("N")
+                .help("Column of the score, starting at 1 (default: first numeric column after the words)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(EMBEDDINGS)
+                .help("Embedding file")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(DATASETS)
+                .help("Word similarity files with two words and a score per line")
+                .index(2)
+                .multiple(true)
+                .required(true),
+        )
+        .get_matches()
+}
+
+fn config_from_matches(matches: &ArgMatches) -> Config {
+    let embeddings_filename = matches.value_of(EMBEDDINGS).unwrap().to_owned();
+    let datasets = matches
+        .values_of(DATASETS)
+        .unwrap()
+        .map(ToOwned::to_owned)
+        .collect();
+    let embedding_format = matches
+        .value_of(FORMAT)
+        .map(|f| EmbeddingFormat::try_from(f).or_exit("Cannot parse embedding format", 1))
+        .unwrap_or(EmbeddingFormat::Auto);
+    let score_column = matches.value_of(SCORE_COLUMN).map(|v| {
+        let column: usize = v.parse().or_exit("Cannot parse score column", 1);
+        if column < 3 {
+            eprintln!("The score column should be at least 3");
+            process::exit(1);
+        }
+        column - 1
+    });
+
+    Config {
+        datasets,
+        embeddings_filename,
+        embedding_format,
+        score_column,
+    }
+}
+
+fn format_correlation(correlation: Option<f64>) -> String {
+    correlation
+        .map(|c| format!("{:.4}", c))
+        .unwrap_or_else(|| "n/a".to_owned())
+}
+
+fn main() {
+    let matches = parse_args();
+    let config = config_from_matches(&matches);
+
+    let embeddings = read_embeddings_view(&config.embeddings_filename, config.embedding_format)
+        .or_exit("Cannot read embeddings", 1);
+
+    for dataset in &config.datasets {
+        let f = File::open(dataset).or_exit(format!("Cannot open {}", dataset), 1);
+        let instances = read_similarity_instances(BufReader::new(f), config.score_column)
+            .or_exit(format!("Cannot read word similarity file {}", dataset), 1);
+
+        let eval = embeddings.evaluate_similarity(&instances);
+
+        println!(
+            "{}: Spearman: {}, Pearson: {}, coverage: {}/{} ({:.2}%)",
+            dataset,
+            format_correlation(eval.spearman),
+            format_correlation(eval.pearson),
+            eval.n_covered,
+            eval.n_instances,
+            eval.coverage() * 100.
+        );
+    }
+}
@@ -1,9 +1,14 @@
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{BufReader, BufWriter, Read};
+use std::path::Path;
+use std::process;
 
 use clap::{App, AppSettings, Arg, ArgMatches};
 use failure::err_msg;
 use rust2vec::prelude::*;
+use rust2vec::projector::WriteProjector;
+use rust2vec::text::{Separator, TextDialect};
+use rust2vec::word2vec::TokenDecoding;
 use rust2vec_utils::EmbeddingFormat;
 use stdinout::OrExit;
 use toml::Value;
@@ -19,14 +24,22 @@ struct Config {
     metadata_filename: Option<String>,
     input_format: EmbeddingFormat,
     output_format: EmbeddingFormat,
+    limit: Option<usize>,
     normalization: bool,
+    text_dialect: TextDialect,
+    token_decoding: TokenDecoding,
 }
 
 // Option constants
+static DECODING: &str = "decoding";
+static ESCAPE: &str = "escape";
 static INPUT_FORMAT: &str = "input_format";
+static LIMIT: &str = "limit";
 static METADATA_FILENAME: &str = "metadata_filename";
 static NO_NORMALIZATION: &str = "no_normalization";
 static OUTPUT_FORMAT: &str = "output_format";
+static SEPARATOR: &str = "separator";
+static STRICT: &str = "strict";
 
 // Argument constants
 static INPUT: &str = "INPUT";
@@ -41,13 +54,39 @@ fn parse_args() -> ArgMatches<'static> {
                 .index(1)
                 .required(true),
         )
-        .arg(Arg::with_name(OUTPUT).help("Output file").index(2))
+        .arg(
+            Arg::with_name(OUTPUT)
+                .help("Output file (output directory for the projector format)")
+                .index(2),
+        )
+        .arg(
+            Arg::with_name(DECODING)
+                .short("d")
+                .long("decoding")
+                .value_name("DECODING")
+                .help("Decoding of word2vec tokens that are not valid UTF-8: strict, lossy, skip, or an encoding such as latin1 (default: strict)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(ESCAPE)
+                .short("e")
+                .long("escape")
+                .help("Escape whitespace and backslashes in tokens of text formats"),
+        )
         .arg(
             Arg::with_name(INPUT_FORMAT)
                 .short("f")
                 .long("from")
                 .value_name("FORMAT")
-                .help("Input format: finalfusion, text, textdims, word2vec (default: word2vec)")
+                .help("Input format: auto, finalfusion, text, textdims, word2vec (default: auto)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(LIMIT)
+                .short("l")
+                .long("limit")
+                .value_name("N")
+                .help("Only write the first N embeddings in the projector format")
                 .takes_value(true),
         )
         .arg(
@@ -64,12 +103,25 @@ fn parse_args() -> ArgMatches<'static> {
                 .long("no-normalization")
                 .help("Do not normalize embeddings during conversion."),
         )
+        .arg(
+            Arg::with_name(SEPARATOR)
+                .short("s")
+                .long("separator")
+                .value_name("SEPARATOR")
+                .help("Field separator of text formats: space or tab (default: space)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(STRICT)
+                .long("strict")
+                .help("Require exactly one separator between fields and the same number of components on every line of text formats"),
+        )
         .arg(
             Arg::with_name(OUTPUT_FORMAT)
                 .short("t")
                 .long("to")
                 .value_name("FORMAT")
-                .help("Output format: finalfusion, text, textdims, word2vec (default: finalfusion)")
+                .help("Output format: finalfusion, projector, text, textdims, word2vec (default: finalfusion)")
                 .takes_value(true),
         )
         .get_matches()
@@ -80,24 +132,55 @@ fn config_from_matches(matches: &ArgMatches) -> Config {
     let input_format = matches
         .value_of(INPUT_FORMAT)
         .map(|v| EmbeddingFormat::try_from(v).or_exit("Cannot parse input format", 1))
-        .unwrap_or(EmbeddingFormat::Word2Vec);
+        .unwrap_or(EmbeddingFormat::Auto);
     let output_filename = matches.value_of(OUTPUT).unwrap().to_owned();
     let output_format = matches
         .value_of(OUTPUT_FORMAT)
         .map(|v| EmbeddingFormat::try_from(v).or_exit("Cannot parse output format", 1))
         .unwrap_or(EmbeddingFormat::FinalFusion);
 
+    let limit = matches
+        .value_of(LIMIT)
+        .map(|v| v.parse().or_exit("Cannot parse limit", 1));
+    if limit.is_some() && output_format != EmbeddingFormat::Projector {
+        eprintln!("--limit is only supported for the projector output format");
+        process::exit(1);
+    }
+
     let metadata_filename = matches.value_of(METADATA_FILENAME).map(ToOwned::to_owned);
 
     let normalization = !matches.is_present(NO_NORMALIZATION);
 
+    let separator = match matches.value_of(SEPARATOR) {
+        Some("space") | None => Separator::Space,
+        Some("tab") => Separator::Tab,
+        Some(separator) => {
+            eprintln!("Unknown separator: {}", separator);
+            process::exit(1);
+        }
+    };
+
+    let text_dialect = TextDialect {
+        separator,
+        escape: matches.is_present(ESCAPE),
+        strict: matches.is_present(STRICT),
+    };
+
+    let token_decoding = matches
+        .value_of(DECODING)
+        .map(|v| v.parse().or_exit("Cannot parse token decoding", 1))
+        .unwrap_or(TokenDecoding::Strict);
+
     Config {
         input_filename,
         output_filename,
         input_format,
         output_format,
+        limit,
         metadata_filename,
         normalization,
+        text_dialect,
+        token_decoding,
     }
 }
 
@@ -107,18 +190,32 @@ fn main() {
 
     let metadata = config.metadata_filename.map(read_metadata).map(Metadata);
 
-    let mut embeddings = read_embeddings(
+    let embeddings = read_embeddings(
         &config.input_filename,
         config.input_format,
         config.normalization,
+        config.text_dialect,
+        config.token_decoding,
     );
 
     // Overwrite metadata if provided, otherwise retain existing metadata.
-    if metadata.is_some() {
-        embeddings.set_metadata(metadata);
-    }
+    let embeddings = if metadata.is_some() {
+        let (_, vocab, storage) = embeddings.into_parts();
+        Embeddings::try_new(metadata, vocab, storage).or_exit("Invalid metadata", 1)
+    } else {
+        embeddings
+    };
 
-    write_embeddings(embeddings, &config.output_filename, config.output_format);
+    if config.output_format == EmbeddingFormat::Projector {
+        write_projector(&embeddings, &config.output_filename, config.limit);
+    } else {
+        write_embeddings(
+            embeddings,
+            &config.output_filename,
+            config.output_format,
+            config.text_dialect,
+        );
+    }
 }
 
 fn read_metadata(filename: impl AsRef<str>) -> Value {
@@ -136,19 +233,45 @@ fn read_embeddings(
     filename: &str,
     embedding_format: EmbeddingFormat,
     normalization: bool,
+    text_dialect: TextDialect,
+    token_decoding: TokenDecoding,
 ) -> Embeddings<VocabWrap, StorageWrap> {
     let f = File::open(filename).or_exit("Cannot open embeddings file", 1);
     let mut reader = BufReader::new(f);
 
     use EmbeddingFormat::*;
     match embedding_format {
+        Auto => {
+            let embedding_format =
+                EmbeddingFormat::detect(filename).or_exit("Cannot detect embedding format", 1);
+            return read_embeddings(
+                filename,
+                embedding_format,
+                normalization,
+                text_dialect,
+                token_decoding,
+            );
+        }
         FinalFusion => ReadEmbeddings::read_embeddings(&mut reader),
         FinalFusionMmap => MmapEmbeddings::mmap_embeddings(&mut reader),
+        Projector => Err(err_msg("Reading from this format is not supported")),
         Word2Vec => {
-            ReadWord2Vec::read_word2vec_binary(&mut reader, normalization).map(Embeddings::into)
+            let (embeddings, n_invalid): (Embeddings<SimpleVocab, NdArray>, _) =
+                ReadWord2Vec::read_word2vec_binary_decoding(
+                    &mut reader,
+                    normalization,
+                    token_decoding,
+                )
+                .or_exit("Cannot read embeddings", 1);
+            if n_invalid != 0 {
+                eprintln!("Tokens that are not valid UTF-8: {}", n_invalid);
+            }
+            Ok(embeddings.into())
         }
-        Text => ReadText::read_text(&mut reader, normalization).map(Embeddings::into),
-        TextDims => ReadTextDims::read_text_dims(&mut reader, normalization).map(Embeddings::into),
+        Text => ReadText::read_text_dialect(&mut reader, normalization, text_dialect)
+            .map(Embeddings::into),
+        TextDims => ReadTextDims::read_text_dims_dialect(&mut reader, normalization, text_dialect)
+            .map(Embeddings::into),
     }
     .or_exit("Cannot read embeddings", 1)
 }
@@ -157,6 +280,7 @@ fn write_embeddings(
     embeddings: Embeddings<VocabWrap, StorageWrap>,
     filename: &str,
     embedding_format: EmbeddingFormat,
+    text_dialect: TextDialect,
 ) {
     let f = File::create(filename).or_exit("Cannot create embeddings file", 1);
     let mut writer = BufWriter::new(f);
@@ -164,10 +288,40 @@ fn write_embeddings(
     use EmbeddingFormat::*;
     match embedding_format {
         FinalFusion => embeddings.write_embeddings(&mut writer),
-        FinalFusionMmap => Err(err_msg("Writing to this format is not supported")),
+        Auto | FinalFusionMmap | Projector => {
+            Err(err_msg("Writing to this format is not supported"))
+        }
         Word2Vec => embeddings.write_word2vec_binary(&mut writer),
-        Text => embeddings.write_text(&mut writer),
-        TextDims => embeddings.write_text_dims(&mut writer),
+        Text => embeddings.write_text_dialect(&mut writer, text_dialect),
+        TextDims => embeddings.write_text_dims_dialect(&mut writer, text_dialect),
     }
     .or_exit("Cannot write embeddings", 1)
 }
+
+fn write_projector(
+    embeddings: &Embeddings<VocabWrap, StorageWrap>,
+    dirname: &str,
+    limit: Option<usize>,
+) {
+    fs::create_dir_all(dirname).or_exit("Cannot create output directory", 1);
+
+    let create = |filename| {
+        let f = File::create(Path::new(dirname).join(filename))
+            .or_exit(format!("Cannot create {}", filename), 1);
+        BufWriter::new(f)
+    };
+    let mut vectors = create("vectors.tsv");
+    let mut metadata = create("metadata.tsv");
+
+    // Add word frequencies to the projector metadata when available.
+    let frequencies = embeddings
+        .metadata()
+        .map(Metadata::frequencies)
+        .transpose()
+        .or_exit("Cannot read frequencies from metadata", 1)
+        .flatten();
+
+    embeddings
+        .write_projector(&mut vectors, &mut metadata, limit, frequencies.as_ref())
+        .or_exit("Cannot write embeddings", 1);
+}
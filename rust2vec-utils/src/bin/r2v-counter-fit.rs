@@ -0,0 +1,179 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::process;
+
+use clap::{App, AppSettings, Arg, ArgMatches};
+use rust2vec::counterfit::{CounterFit, CounterFitting};
+use rust2vec::prelude::*;
+use rust2vec_utils::{pairs_as_str, read_dictionary, read_embeddings_view, EmbeddingFormat};
+use stdinout::OrExit;
+
+static DEFAULT_CLAP_SETTINGS: &[AppSettings] = &[
+    AppSettings::DontCollapseArgsInUsage,
+    AppSettings::UnifiedHelpMessage,
+];
+
+struct Config {
+    antonyms_filename: Option<String>,
+    counter_fitting: CounterFitting,
+    input_filename: String,
+    input_format: EmbeddingFormat,
+    output_filename: String,
+    synonyms_filename: Option<String>,
+}
+
+// Option constants
+static ANTONYMS: &str = "antonyms";
+static EPOCHS: &str = "epochs";
+static INPUT_FORMAT: &str = "input_format";
+static LEARNING_RATE: &str = "learning_rate";
+static RADIUS: &str = "radius";
+static SYNONYMS: &str = "synonyms";
+
+// Argument constants
+static INPUT: &str = "INPUT";
+static OUTPUT: &str = "OUTPUT";
+
+fn config_from_matches(matches: &ArgMatches) -> Config {
+    // Arguments
+    let input_filename = matches.value_of(INPUT).unwrap().to_owned();
+    let output_filename = matches.value_of(OUTPUT).unwrap().to_owned();
+
+    // Options
+    let antonyms_filename = matches.value_of(ANTONYMS).map(ToOwned::to_owned);
+    let synonyms_filename = matches.value_of(SYNONYMS).map(ToOwned::to_owned);
+    if antonyms_filename.is_none() && synonyms_filename.is_none() {
+        eprintln!("At least one of --antonyms and --synonyms is required");
+        process::exit(1);
+    }
+
+    let defaults = CounterFitting::default();
+    let epochs = matches
+        .value_of(EPOCHS)
+        .map(|v| v.parse().or_exit("Cannot parse number of epochs", 1))
+        .unwrap_or(defaults.epochs);
+    let input_format = matches
+        .value_of(INPUT_FORMAT)
+        .map(|v| EmbeddingFormat::try_from(v).or_exit("Cannot parse input format", 1))
+        .unwrap_or(EmbeddingFormat::Auto);
+    let learning_rate = matches
+        .value_of(LEARNING_RATE)
+        .map(|v| v.parse().or_exit("Cannot parse learning rate", 1))
+        .unwrap_or(defaults.learning_rate);
+    let preservation_radius = matches
+        .value_of(RADIUS)
+        .map(|v| v.parse().or_exit("Cannot parse preservation radius", 1))
+        .unwrap_or(defaults.preservation_radius);
+
+    Config {
+        antonyms_filename,
+        counter_fitting: CounterFitting {
+            epochs,
+            learning_rate,
+            preservation_radius,
+            ..defaults
+        },
+        input_filename,
+        input_format,
+        output_filename,
+        synonyms_filename,
+    }
+}
+
+fn parse_args() -> ArgMatches<'static> {
+    App::new("r2v-counter-fit")
+        .settings(DEFAULT_CLAP_SETTINGS)
+        .arg(
+            Arg::with_name(INPUT)
+                .help("Input embeddings")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(OUTPUT)
+                .help("Counter-fitted embeddings (finalfusion)")
+                .index(2)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(ANTONYMS)
+                .short("a")
+                .long("antonyms")
+                .value_name("FILE")
+                .help("Antonym pairs, one pair per line")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(EPOCHS)
+                .short("e")
+                .long("epochs")
+                .value_name("N")
+                .help("Number of epochs (default: 20)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(INPUT_FORMAT)
+                .short("f")
+                .long("from")
+                .value_name("FORMAT")
+                .help("Input format: auto, finalfusion, finalfusion_mmap, text, textdims, word2vec (default: auto)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(LEARNING_RATE)
+                .short("l")
+                .long("lr")
+                .value_name("LR")
+                .help("Learning rate (default: 0.1)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(RADIUS)
+                .short("r")
+                .long("radius")
+                .value_name("DISTANCE")
+                .help("Preserve distances to neighbors within this cosine distance (default: 0.2)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(SYNONYMS)
+                .short("s")
+                .long("synonyms")
+                .value_name("FILE")
+                .help("Synonym pairs, one pair per line")
+                .takes_value(true),
+        )
+        .get_matches()
+}
+
+fn read_pairs(filename: &Option<String>) -> Vec<(String, String)> {
+    match filename {
+        Some(filename) => read_dictionary(filename).or_exit("Cannot read word pairs", 1),
+        None => Vec::new(),
+    }
+}
+
+fn main() {
+    let matches = parse_args();
+    let config = config_from_matches(&matches);
+
+    let embeddings = read_embeddings_view(&config.input_filename, config.input_format)
+        .or_exit("Cannot read embeddings", 1);
+
+    let synonyms = read_pairs(&config.synonyms_filename);
+    let antonyms = read_pairs(&config.antonyms_filename);
+
+    let counter_fitted = embeddings
+        .counter_fit(
+            &pairs_as_str(&synonyms),
+            &pairs_as_str(&antonyms),
+            config.counter_fitting,
+        )
+        .or_exit("Cannot counter-fit embeddings", 1);
+
+    let f = File::create(&config.output_filename).or_exit("Cannot create embeddings file", 1);
+    let mut writer = BufWriter::new(f);
+    counter_fitted
+        .write_embeddings(&mut writer)
+        .or_exit("Cannot write embeddings", 1);
+}
@@ -0,0 +1,78 @@
+use std::io::BufRead;
+
+use clap::{App, AppSettings, Arg, ArgMatches};
+use rust2vec::similarity::DoesntMatch;
+use rust2vec_utils::{read_embeddings_view, EmbeddingFormat};
+use stdinout::{Input, OrExit};
+
+static DEFAULT_CLAP_SETTINGS: &[AppSettings] = &[
+    AppSettings::DontCollapseArgsInUsage,
+    AppSettings::UnifiedHelpMessage,
+];
+
+fn parse_args() -> ArgMatches<'static> {
+    App::new("r2v-doesnt-match")
+        .settings(DEFAULT_CLAP_SETTINGS)
+        .arg(
+            Arg::with_name("format")
+                .short("f")
+                .value_name("FORMAT")
+                .help("Embedding format: auto, finalfusion, finalfusion_mmap, word2vec, text, or textdims (default: auto)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("EMBEDDINGS")
+                .help("Embeddings file")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("INPUT")
+                .help("Input with whitespace-separated words per line")
+                .index(2),
+        )
+        .get_matches()
+}
+
+struct Config {
+    embeddings_filename: String,
+    embedding_format: EmbeddingFormat,
+}
+
+fn config_from_matches<'a>(matches: &ArgMatches<'a>) -> Config {
+    let embeddings_filename = matches.value_of("EMBEDDINGS").unwrap().to_owned();
+
+    let embedding_format = matches
+        .value_of("format")
+        .map(|f| EmbeddingFormat::try_from(f).or_exit("Cannot parse embedding format", 1))
+        .unwrap_or(EmbeddingFormat::Auto);
+
+    Config {
+        embeddings_filename,
+        embedding_format,
+    }
+}
+
+fn main() {
+    let matches = parse_args();
+    let config = config_from_matches(&matches);
+
+    let embeddings = read_embeddings_view(&config.embeddings_filename, config.embedding_format)
+        .or_exit("Cannot read embeddings", 1);
+
+    let input = Input::from(matches.value_of("INPUT"));
+    let reader = input.buf_read().or_exit("Cannot open input for reading", 1);
+
+    for line in reader.lines() {
+        let line = line.or_exit("Cannot read line", 1);
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        if words.is_empty() {
+            continue;
+        }
+
+        match embeddings.doesnt_match(&words) {
+            Some(word) => println!("{}", word),
+            None => eprintln!("None of the words has an embedding: {}", line.trim()),
+        }
+    }
+}
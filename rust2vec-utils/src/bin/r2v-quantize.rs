@@ -51,7 +51,7 @@ fn config_from_matches(matches: &ArgMatches) -> Config {
     let input_format = matches
         .value_of(INPUT_FORMAT)
         .map(|v| EmbeddingFormat::try_from(v).or_exit("Cannot parse input format", 1))
-        .unwrap_or(EmbeddingFormat::Word2Vec);
+        .unwrap_or(EmbeddingFormat::Auto);
     let n_attempts = matches
         .value_of(N_ATTEMPTS)
         .map(|a| a.parse().or_exit("Cannot parse number of attempts", 1))
@@ -141,7 +141,7 @@ fn parse_args() -> ArgMatches<'static> {
                 .short("f")
                 .long("from")
                 .value_name("FORMAT")
-                .help("Input format: finalfusion, text, textdims, word2vec (default: word2vec)")
+                .help("Input format: auto, finalfusion, text, textdims, word2vec (default: auto)")
                 .takes_value(true),
         )
         .arg(
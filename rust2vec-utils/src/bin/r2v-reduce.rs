@@ -0,0 +1,146 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use clap::{App, AppSettings, Arg, ArgMatches};
+use rust2vec::prelude::*;
+use rust2vec::reduce::Reduce;
+use rust2vec_utils::{read_embeddings_view, EmbeddingFormat};
+use stdinout::OrExit;
+
+static DEFAULT_CLAP_SETTINGS: &[AppSettings] = &[
+    AppSettings::DontCollapseArgsInUsage,
+    AppSettings::UnifiedHelpMessage,
+];
+
+struct Config {
+    dims: usize,
+    input_filename: String,
+    input_format: EmbeddingFormat,
+    n_removed: usize,
+    output_filename: String,
+}
+
+// Option constants
+static DIMS: &str = "dims";
+static INPUT_FORMAT: &str = "input_format";
+static N_REMOVED: &str = "n_removed";
+
+// Argument constants
+static INPUT: &str = "INPUT";
+static OUTPUT: &str = "OUTPUT";
+
+fn config_from_matches(matches: &ArgMatches) -> Config {
+    // Arguments
+    let input_filename = matches.value_of(INPUT).unwrap().to_owned();
+    let output_filename = matches.value_of(OUTPUT).unwrap().to_owned();
+
+    // Options
+    let dims = matches
+        .value_of(DIMS)
+        .map(|a| a.parse().or_exit("Cannot parse number of dimensions", 1))
+        .unwrap_or(100);
+    let input_format = matches
+        .value_of(INPUT_FORMAT)
+        .map(|v| EmbeddingFormat::try_from(v).or_exit("Cannot parse input format", 1))
+        .unwrap_or(EmbeddingFormat::Auto);
+    let n_removed = matches
+        .value_of(N_REMOVED)
+        .map(|a| {
+            a.parse()
+                .or_exit("Cannot parse number of removed components", 1)
+        })
+        .unwrap_or(0);
+
+    Config {
+        dims,
+        input_filename,
+        input_format,
+        n_removed,
+        output_filename,
+    }
+}
+
+fn parse_args() -> ArgMatches<'static> {
+    App::new("r2v-reduce")
+        .settings(DEFAULT_CLAP_SETTINGS)
+        .arg(
+            Arg::with_name(INPUT)
+                .help("Input embeddings")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(OUTPUT)
+                .help("Output embeddings (finalfusion)")
+                .index(2)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(DIMS)
+                .short("d")
+                .long("dims")
+                .value_name("N")
+                .help("Number of dimensions (default: 100)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(INPUT_FORMAT)
+                .short("f")
+                .long("from")
+                .value_name("FORMAT")
+                .help("Input format: auto, finalfusion, finalfusion_mmap, text, textdims, word2vec (default: auto)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(N_REMOVED)
+                .short("r")
+                .long("remove")
+                .value_name("N")
+                .help("Remove the N dominant principal components (default: 0)")
+                .takes_value(true),
+        )
+        .get_matches()
+}
+
+fn write_embeddings(embeddings: &Embeddings<VocabWrap, NdArray>, filename: &str) {
+    let f = File::create(filename).or_exit("Cannot create embeddings file", 1);
+    let mut writer = BufWriter::new(f);
+    embeddings
+        .write_embeddings(&mut writer)
+        .or_exit("Cannot write embeddings", 1)
+}
+
+fn main() {
+    let matches = parse_args();
+    let config = config_from_matches(&matches);
+
+    let embeddings = read_embeddings_view(&config.input_filename, config.input_format)
+        .or_exit("Cannot read embeddings", 1);
+
+    // The principal components are computed from the embeddings of
+    // known words, subword units are projected using the same
+    // components.
+    let (reduced, projection) = embeddings
+        .reduce(config.dims, config.n_removed)
+        .or_exit("Cannot compute projection", 1);
+
+    eprintln!(
+        "Explained variance ratio: {:.4}",
+        projection.explained_variance_ratio()
+    );
+
+    let mut metadata = embeddings.metadata().cloned().unwrap_or_default();
+
+    // A post-processing transform cannot be applied to the reduced
+    // embeddings, since its dimensionality differs.
+    if let Some(table) = metadata.0.as_table_mut() {
+        table.remove("post_processing");
+    }
+
+    metadata
+        .insert("projection", projection.to_metadata())
+        .or_exit("Cannot store projection in metadata", 1);
+
+    let reduced_embeddings = Embeddings::new(Some(metadata), embeddings.vocab().clone(), reduced);
+    write_embeddings(&reduced_embeddings, &config.output_filename);
+}
@@ -0,0 +1,147 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use clap::{App, AppSettings, Arg, ArgMatches};
+use rust2vec::prelude::*;
+use rust2vec::retrofit::{read_lexicon, Retrofit, Retrofitting};
+use rust2vec_utils::{read_embeddings_view, EmbeddingFormat};
+use stdinout::OrExit;
+
+static DEFAULT_CLAP_SETTINGS: &[AppSettings] = &[
+    AppSettings::DontCollapseArgsInUsage,
+    AppSettings::UnifiedHelpMessage,
+];
+
+struct Config {
+    input_filename: String,
+    input_format: EmbeddingFormat,
+    lexicon_filename: String,
+    output_filename: String,
+    retrofitting: Retrofitting,
+}
+
+// Option constants
+static ALPHA: &str = "alpha";
+static BETA: &str = "beta";
+static INPUT_FORMAT: &str = "input_format";
+static N_ITERATIONS: &str = "n_iterations";
+
+// Argument constants
+static INPUT: &str = "INPUT";
+static LEXICON: &str = "LEXICON";
+static OUTPUT: &str = "OUTPUT";
+
+fn config_from_matches(matches: &ArgMatches) -> Config {
+    // Arguments
+    let input_filename = matches.value_of(INPUT).unwrap().to_owned();
+    let lexicon_filename = matches.value_of(LEXICON).unwrap().to_owned();
+    let output_filename = matches.value_of(OUTPUT).unwrap().to_owned();
+
+    // Options
+    let defaults = Retrofitting::default();
+    let alpha = matches
+        .value_of(ALPHA)
+        .map(|v| v.parse().or_exit("Cannot parse alpha", 1))
+        .unwrap_or(defaults.alpha);
+    let beta = matches
+        .value_of(BETA)
+        .map(|v| v.parse().or_exit("Cannot parse beta", 1))
+        .unwrap_or(defaults.beta);
+    let input_format = matches
+        .value_of(INPUT_FORMAT)
+        .map(|v| EmbeddingFormat::try_from(v).or_exit("Cannot parse input format", 1))
+        .unwrap_or(EmbeddingFormat::Auto);
+    let iterations = matches
+        .value_of(N_ITERATIONS)
+        .map(|v| v.parse().or_exit("Cannot parse number of iterations", 1))
+        .unwrap_or(defaults.iterations);
+
+    Config {
+        input_filename,
+        input_format,
+        lexicon_filename,
+        output_filename,
+        retrofitting: Retrofitting {
+            alpha,
+            beta,
+            iterations,
+        },
+    }
+}
+
+fn parse_args() -> ArgMatches<'static> {
+    App::new("r2v-retrofit")
+        .settings(DEFAULT_CLAP_SETTINGS)
+        .arg(
+            Arg::with_name(INPUT)
+                .help("Input embeddings")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(LEXICON)
+                .help("Lexicon with a word and its neighbors per line")
+                .index(2)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(OUTPUT)
+                .help("Retrofitted embeddings (finalfusion)")
+                .index(3)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(ALPHA)
+                .short("a")
+                .long("alpha")
+                .value_name("WEIGHT")
+                .help("Weight of the original embeddings (default: 1)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(BETA)
+                .short("b")
+                .long("beta")
+                .value_name("WEIGHT")
+                .help("Weight of the lexicon neighbors (default: 1)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(INPUT_FORMAT)
+                .short("f")
+                .long("from")
+                .value_name("FORMAT")
+                .help("Input format: auto, finalfusion, finalfusion_mmap, text, textdims, word2vec (default: auto)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(N_ITERATIONS)
+                .short("i")
+                .long("iter")
+                .value_name("N")
+                .help("Number of iterations (default: 10)")
+                .takes_value(true),
+        )
+        .get_matches()
+}
+
+fn main() {
+    let matches = parse_args();
+    let config = config_from_matches(&matches);
+
+    let embeddings = read_embeddings_view(&config.input_filename, config.input_format)
+        .or_exit("Cannot read embeddings", 1);
+
+    let f = File::open(&config.lexicon_filename).or_exit("Cannot open lexicon file", 1);
+    let lexicon = read_lexicon(BufReader::new(f)).or_exit("Cannot read lexicon", 1);
+
+    let retrofitted = embeddings
+        .retrofit(&lexicon, config.retrofitting)
+        .or_exit("Cannot retrofit embeddings", 1);
+
+    let f = File::create(&config.output_filename).or_exit("Cannot create embeddings file", 1);
+    let mut writer = BufWriter::new(f);
+    retrofitted
+        .write_embeddings(&mut writer)
+        .or_exit("Cannot write embeddings", 1);
+}
@@ -1,7 +1,8 @@
 use std::io::BufRead;
+use std::process;
 
 use clap::{App, AppSettings, Arg, ArgMatches};
-use rust2vec::similarity::Similarity;
+use rust2vec::similarity::BatchSimilarity;
 use rust2vec_utils::{read_embeddings_view, EmbeddingFormat};
 use stdinout::{Input, OrExit};
 
@@ -13,11 +14,18 @@ static DEFAULT_CLAP_SETTINGS: &[AppSettings] = &[
 fn parse_args() -> ArgMatches<'static> {
     App::new("r2v-similar")
         .settings(DEFAULT_CLAP_SETTINGS)
+        .arg(
+            Arg::with_name("batch_size")
+                .short("b")
+                .value_name("N")
+                .help("Process queries in batches of N words (default: 1)")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("format")
                 .short("f")
                 .value_name("FORMAT")
-                .help("Embedding format: finalfusion, finalfusion_mmap, word2vec, text, or textdims (default: finalfusion)")
+                .help("Embedding format: auto, finalfusion, finalfusion_mmap, word2vec, text, or textdims (default: auto)")
                 .takes_value(true),
         )
         .arg(
@@ -38,18 +46,28 @@ fn parse_args() -> ArgMatches<'static> {
 }
 
 struct Config {
+    batch_size: usize,
     embeddings_filename: String,
     embedding_format: EmbeddingFormat,
     k: usize,
 }
 
 fn config_from_matches<'a>(matches: &ArgMatches<'a>) -> Config {
+    let batch_size = matches
+        .value_of("batch_size")
+        .map(|v| v.parse().or_exit("Cannot parse batch size", 1))
+        .unwrap_or(1);
+    if batch_size == 0 {
+        eprintln!("Batch size should be at least 1");
+        process::exit(1);
+    }
+
     let embeddings_filename = matches.value_of("EMBEDDINGS").unwrap().to_owned();
 
     let embedding_format = matches
         .value_of("format")
         .map(|f| EmbeddingFormat::try_from(f).or_exit("Cannot parse embedding format", 1))
-        .unwrap_or(EmbeddingFormat::FinalFusion);
+        .unwrap_or(EmbeddingFormat::Auto);
 
     let k = matches
         .value_of("neighbors")
@@ -57,6 +75,7 @@ fn config_from_matches<'a>(matches: &ArgMatches<'a>) -> Config {
         .unwrap_or(10);
 
     Config {
+        batch_size,
         embeddings_filename,
         embedding_format,
         k,
@@ -73,19 +92,23 @@ fn main() {
     let input = Input::from(matches.value_of("INPUT"));
     let reader = input.buf_read().or_exit("Cannot open input for reading", 1);
 
-    for line in reader.lines() {
-        let line = line.or_exit("Cannot read line", 1).trim().to_owned();
-        if line.is_empty() {
-            continue;
+    let mut lines = reader.lines();
+    loop {
+        let batch = lines
+            .by_ref()
+            .map(|line| line.or_exit("Cannot read line", 1).trim().to_owned())
+            .filter(|line| !line.is_empty())
+            .take(config.batch_size)
+            .collect::<Vec<_>>();
+        if batch.is_empty() {
+            break;
         }
 
-        let results = match embeddings.similarity(&line, config.k) {
-            Some(results) => results,
-            None => continue,
-        };
-
-        for similar in results {
-            println!("{}\t{}", similar.word, similar.similarity);
+        let queries = batch.iter().map(String::as_str).collect::<Vec<_>>();
+        for results in embeddings.batch_similarity(&queries, config.k) {
+            for similar in results.into_iter().flatten() {
+                println!("{}\t{}", similar.word, similar.similarity);
+            }
         }
     }
 }
@@ -0,0 +1,324 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::process;
+
+use clap::{App, AppSettings, Arg, ArgMatches};
+use rand::{FromEntropy, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use rust2vec::prelude::*;
+use rust2vec::train::{
+    train_subword_using, train_word2vec_using, Loss, Model, SubwordConfig, TrainConfig,
+};
+use stdinout::OrExit;
+
+static DEFAULT_CLAP_SETTINGS: &[AppSettings] = &[
+    AppSettings::DontCollapseArgsInUsage,
+    AppSettings::UnifiedHelpMessage,
+];
+
+struct Config {
+    corpus_filename: String,
+    output_filename: String,
+    seed: Option<u64>,
+    subword_config: Option<SubwordConfig>,
+    train_config: TrainConfig,
+}
+
+// Option constants
+static BUCKETS_EXP: &str = "buckets_exp";
+static CONTEXT_SIZE: &str = "context_size";
+static DIMS: &str = "dims";
+static EPOCHS: &str = "epochs";
+static LEARNING_RATE: &str = "learning_rate";
+static LOSS: &str = "loss";
+static MAX_N: &str = "max_n";
+static MIN_COUNT: &str = "min_count";
+static MIN_N: &str = "min_n";
+static MODEL: &str = "model";
+static N_NEGATIVES: &str = "n_negatives";
+static N_THREADS: &str = "n_threads";
+static SEED: &str = "seed";
+static SUBSAMPLE: &str = "subsample";
+static SUBWORDS: &str = "subwords";
+
+// Argument constants
+static CORPUS: &str = "CORPUS";
+static OUTPUT: &str = "OUTPUT";
+
+fn parse_loss(loss: &str) -> Loss {
+    match loss {
+        "ns" => Loss::NegativeSampling,
+        "hs" => Loss::HierarchicalSoftmax,
+        _ => {
+            eprintln!("Unknown loss: {}", loss);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_model(model: &str) -> Model {
+    match model {
+        "skipgram" => Model::SkipGram,
+        "cbow" => Model::Cbow,
+        _ => {
+            eprintln!("Unknown model: {}", model);
+            process::exit(1);
+        }
+    }
+}
+
+fn config_from_matches(matches: &ArgMatches) -> Config {
+    // Arguments
+    let corpus_filename = matches.value_of(CORPUS).unwrap().to_owned();
+    let output_filename = matches.value_of(OUTPUT).unwrap().to_owned();
+
+    // Options
+    let defaults = TrainConfig::default();
+    let context_size = matches
+        .value_of(CONTEXT_SIZE)
+        .map(|v| v.parse().or_exit("Cannot parse context size", 1))
+        .unwrap_or(defaults.context_size);
+    let dims = matches
+        .value_of(DIMS)
+        .map(|v| v.parse().or_exit("Cannot parse dimensionality", 1))
+        .unwrap_or(defaults.dims);
+    let epochs = matches
+        .value_of(EPOCHS)
+        .map(|v| v.parse().or_exit("Cannot parse number of epochs", 1))
+        .unwrap_or(defaults.epochs);
+    let learning_rate = matches
+        .value_of(LEARNING_RATE)
+        .map(|v| v.parse().or_exit("Cannot parse learning rate", 1))
+        .unwrap_or(defaults.learning_rate);
+    let loss = matches
+        .value_of(LOSS)
+        .map(parse_loss)
+        .unwrap_or(defaults.loss);
+    let min_count = matches
+        .value_of(MIN_COUNT)
+        .map(|v| v.parse().or_exit("Cannot parse minimum count", 1))
+        .unwrap_or(defaults.min_count);
+    let model = matches
+        .value_of(MODEL)
+        .map(parse_model)
+        .unwrap_or(defaults.model);
+    let negative_samples = matches
+        .value_of(N_NEGATIVES)
+        .map(|v| {
+            v.parse()
+                .or_exit("Cannot parse number of negative samples", 1)
+        })
+        .unwrap_or(defaults.negative_samples);
+    let n_threads = matches
+        .value_of(N_THREADS)
+        .map(|v| v.parse().or_exit("Cannot parse number of threads", 1))
+        .unwrap_or(num_cpus::get() / 2)
+        .max(1);
+    let seed = matches
+        .value_of(SEED)
+        .map(|v| v.parse().or_exit("Cannot parse seed", 1));
+    let subsample_threshold = matches
+        .value_of(SUBSAMPLE)
+        .map(|v| v.parse().or_exit("Cannot parse subsampling threshold", 1))
+        .unwrap_or(defaults.subsample_threshold);
+
+    let subword_config = if matches.is_present(SUBWORDS) {
+        let defaults = SubwordConfig::default();
+        let buckets_exp = matches
+            .value_of(BUCKETS_EXP)
+            .map(|v| v.parse().or_exit("Cannot parse bucket exponent", 1))
+            .unwrap_or(defaults.buckets_exp);
+        let max_n = matches
+            .value_of(MAX_N)
+            .map(|v| v.parse().or_exit("Cannot parse maximum n-gram length", 1))
+            .unwrap_or(defaults.max_n);
+        let min_n = matches
+            .value_of(MIN_N)
+            .map(|v| v.parse().or_exit("Cannot parse minimum n-gram length", 1))
+            .unwrap_or(defaults.min_n);
+
+        Some(SubwordConfig {
+            min_n,
+            max_n,
+            buckets_exp,
+        })
+    } else {
+        None
+    };
+
+    Config {
+        corpus_filename,
+        output_filename,
+        seed,
+        subword_config,
+        train_config: TrainConfig {
+            model,
+            loss,
+            dims,
+            context_size,
+            negative_samples,
+            epochs,
+            min_count,
+            subsample_threshold,
+            learning_rate,
+            n_threads,
+        },
+    }
+}
+
+fn parse_args() -> ArgMatches<'static> {
+    App::new("r2v-train")
+        .settings(DEFAULT_CLAP_SETTINGS)
+        .arg(
+            Arg::with_name(CORPUS)
+                .help("Tokenized corpus, one sentence per line")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(OUTPUT)
+                .help("Trained embeddings (finalfusion)")
+                .index(2)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(BUCKETS_EXP)
+                .long("buckets")
+                .value_name("EXP")
+                .help("Hash n-grams into 2^EXP buckets (default: 21)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(CONTEXT_SIZE)
+                .short("c")
+                .long("context")
+                .value_name("N")
+                .help("Context size (default: 5)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(DIMS)
+                .short("d")
+                .long("dims")
+                .value_name("DIMS")
+                .help("Embedding dimensionality (default: 100)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(EPOCHS)
+                .short("e")
+                .long("epochs")
+                .value_name("N")
+                .help("Number of epochs (default: 5)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(LEARNING_RATE)
+                .long("lr")
+                .value_name("LR")
+                .help("Initial learning rate (default: 0.025)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(LOSS)
+                .short("l")
+                .long("loss")
+                .value_name("LOSS")
+                .help("Loss: ns (negative sampling) or hs (hierarchical softmax) (default: ns)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(MAX_N)
+                .long("maxn")
+                .value_name("N")
+                .help("Maximum n-gram length (default: 6)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(MIN_COUNT)
+                .long("mincount")
+                .value_name("N")
+                .help("Minimum word count (default: 5)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(MIN_N)
+                .long("minn")
+                .value_name("N")
+                .help("Minimum n-gram length (default: 3)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(MODEL)
+                .short("m")
+                .long("model")
+                .value_name("MODEL")
+                .help("Model: skipgram or cbow (default: skipgram)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(N_NEGATIVES)
+                .short("n")
+                .long("negatives")
+                .value_name("N")
+                .help("Number of negative samples (default: 5)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(SEED)
+                .long("seed")
+                .value_name("SEED")
+                .help("Random number generator seed, results are only reproducible with one thread")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(SUBSAMPLE)
+                .short("s")
+                .long("subsample")
+                .value_name("THRESHOLD")
+                .help("Subsampling threshold, 0 disables subsampling (default: 1e-3)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(SUBWORDS)
+                .long("subwords")
+                .help("Train a subword model"),
+        )
+        .arg(
+            Arg::with_name(N_THREADS)
+                .short("t")
+                .long("threads")
+                .value_name("N")
+                .help("Number of threads (default: logical_cpus / 2)")
+                .takes_value(true),
+        )
+        .get_matches()
+}
+
+fn main() {
+    let matches = parse_args();
+    let config = config_from_matches(&matches);
+
+    let f = File::create(&config.output_filename).or_exit("Cannot create embeddings file", 1);
+    let mut writer = BufWriter::new(f);
+
+    let mut rng = match config.seed {
+        Some(seed) => XorShiftRng::seed_from_u64(seed),
+        None => XorShiftRng::from_entropy(),
+    };
+
+    match config.subword_config {
+        Some(subword_config) => train_subword_using(
+            &config.corpus_filename,
+            &config.train_config,
+            &subword_config,
+            &mut rng,
+        )
+        .or_exit("Cannot train embeddings", 1)
+        .write_embeddings(&mut writer),
+        None => train_word2vec_using(&config.corpus_filename, &config.train_config, &mut rng)
+            .or_exit("Cannot train embeddings", 1)
+            .write_embeddings(&mut writer),
+    }
+    .or_exit("Cannot write embeddings", 1);
+}
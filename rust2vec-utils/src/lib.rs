@@ -1,14 +1,25 @@
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
 
-use failure::{format_err, Error, ResultExt};
+use failure::{bail, err_msg, format_err, Error, ResultExt};
 
+use rust2vec::compression::{Compression, Decompress};
 use rust2vec::prelude::*;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+const FINALFUSION_MAGIC: [u8; 4] = [b'F', b'i', b'F', b'u'];
+
+const FASTTEXT_MAGIC: [u8; 4] = [0xba, 0x16, 0x4f, 0x2f];
+
+/// Maximum number of bytes to read per line during format detection.
+const DETECT_MAX_LINE_LEN: u64 = 1 << 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum EmbeddingFormat {
+    Auto,
     FinalFusion,
     FinalFusionMmap,
+    Projector,
     Word2Vec,
     Text,
     TextDims,
@@ -19,14 +30,114 @@ impl EmbeddingFormat {
         use EmbeddingFormat::*;
 
         match format.as_ref() {
+            "auto" => Ok(Auto),
             "finalfusion" => Ok(FinalFusion),
             "finalfusion_mmap" => Ok(FinalFusionMmap),
+            "projector" => Ok(Projector),
             "word2vec" => Ok(Word2Vec),
             "text" => Ok(Text),
             "textdims" => Ok(TextDims),
             unknown => Err(format_err!("Unknown embedding format: {}", unknown)),
         }
     }
+
+    /// Detect the format of an embeddings file.
+    ///
+    /// The format is detected from the first lines of the file, which
+    /// are decompressed first when the file is compressed. Detection
+    /// fails for formats that cannot be read, such as fastText binary
+    /// models.
+    pub fn detect(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let f = File::open(path).context("Cannot open embeddings file")?;
+        let mut reader = Decompress::new(BufReader::new(f))?;
+
+        let magic = reader.fill_buf()?;
+        let is_finalfusion = magic.starts_with(&FINALFUSION_MAGIC);
+        let is_fasttext = magic.starts_with(&FASTTEXT_MAGIC);
+
+        if is_finalfusion {
+            if reader.compression() != Compression::None {
+                bail!("Compressed finalfusion files are not supported");
+            }
+            return Ok(EmbeddingFormat::FinalFusion);
+        }
+
+        if is_fasttext {
+            bail!("fastText binary models are not supported");
+        }
+
+        let first_line = read_line_bytes(&mut reader)?;
+        let second_line = read_line_bytes(&mut reader)?;
+
+        if let Some((_, dims)) = parse_shape(&first_line) {
+            // word2vec binary and text files with dimensions share the
+            // header. They are distinguished by the first vector.
+            if is_text_embedding(&second_line, Some(dims)) {
+                return Ok(EmbeddingFormat::TextDims);
+            }
+
+            return Ok(EmbeddingFormat::Word2Vec);
+        }
+
+        if is_text_embedding(&first_line, None) {
+            return Ok(EmbeddingFormat::Text);
+        }
+
+        bail!("Cannot detect embedding format")
+    }
+}
+
+/// Read a line of at most `DETECT_MAX_LINE_LEN` bytes.
+fn read_line_bytes(reader: &mut impl BufRead) -> Result<Vec<u8>, Error> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(DETECT_MAX_LINE_LEN)
+        .read_until(b'\n', &mut line)?;
+    Ok(line)
+}
+
+/// Parse a line of the form *vocab_size n_components*.
+fn parse_shape(line: &[u8]) -> Option<(usize, usize)> {
+    let line = std::str::from_utf8(line).ok()?;
+    let mut parts = line.split_whitespace();
+    let n_words = parts.next()?.parse().ok()?;
+    let dims = parts.next()?.parse().ok()?;
+
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some((n_words, dims))
+}
+
+/// Check whether a line is an embedding in text format.
+///
+/// If `dims` is specified, the embedding must have that number of
+/// components.
+fn is_text_embedding(line: &[u8], dims: Option<usize>) -> bool {
+    let line = match std::str::from_utf8(line) {
+        Ok(line) => line,
+        Err(_) => return false,
+    };
+
+    let mut parts = line.split_whitespace();
+    if parts.next().is_none() {
+        return false;
+    }
+
+    let mut n_components = 0;
+    for part in parts {
+        if part.parse::<f32>().is_err() {
+            return false;
+        }
+        n_components += 1;
+    }
+
+    match dims {
+        Some(dims) => n_components == dims,
+        None => n_components != 0,
+    }
 }
 
 pub fn read_embeddings_view(
@@ -38,8 +149,10 @@ pub fn read_embeddings_view(
 
     use EmbeddingFormat::*;
     let embeddings = match embedding_format {
+        Auto => read_embeddings_view(filename, EmbeddingFormat::detect(filename)?),
         FinalFusion => ReadEmbeddings::read_embeddings(&mut reader),
         FinalFusionMmap => MmapEmbeddings::mmap_embeddings(&mut reader),
+        Projector => Err(err_msg("Reading from this format is not supported")),
         Word2Vec => ReadWord2Vec::read_word2vec_binary(&mut reader, true).map(Embeddings::into),
         Text => ReadText::read_text(&mut reader, true).map(Embeddings::into),
         TextDims => ReadTextDims::read_text_dims(&mut reader, true).map(Embeddings::into),
@@ -48,3 +161,134 @@ pub fn read_embeddings_view(
 
     Ok(embeddings)
 }
+
+/// Read a dictionary of word pairs.
+///
+/// Every non-empty line of the dictionary should contain two words,
+/// separated by whitespace.
+pub fn read_dictionary(filename: &str) -> Result<Vec<(String, String)>, Error> {
+    let f = File::open(filename).context("Cannot open dictionary file")?;
+
+    let mut pairs = Vec::new();
+    for (idx, line) in BufReader::new(f).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let parts = line.split_whitespace().collect::<Vec<_>>();
+        if parts.len() != 2 {
+            bail!("Line {} does not contain a word pair: {}", idx + 1, line);
+        }
+
+        pairs.push((parts[0].to_owned(), parts[1].to_owned()));
+    }
+
+    Ok(pairs)
+}
+
+/// Borrow the words of word pairs, e.g. to pass a dictionary that was
+/// read with `read_dictionary` to the library.
+pub fn pairs_as_str(pairs: &[(String, String)]) -> Vec<(&str, &str)> {
+    pairs
+        .iter()
+        .map(|(u, w)| (u.as_str(), w.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use super::{is_text_embedding, parse_shape, EmbeddingFormat, FASTTEXT_MAGIC};
+
+    fn write_temp(name: &str, data: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("rust2vec-utils-{}-{}", name, std::process::id()));
+        File::create(&path).unwrap().write_all(data).unwrap();
+        path
+    }
+
+    #[test]
+    fn detect_finalfusion() {
+        assert_eq!(
+            EmbeddingFormat::detect("../rust2vec/testdata/similarity.fifu").unwrap(),
+            EmbeddingFormat::FinalFusion
+        );
+    }
+
+    #[test]
+    fn detect_rejects_compressed_finalfusion() {
+        assert!(EmbeddingFormat::detect("testdata/header.fifu.gz").is_err());
+    }
+
+    #[test]
+    fn detect_text() {
+        assert_eq!(
+            EmbeddingFormat::detect("../rust2vec/testdata/similarity.nodims").unwrap(),
+            EmbeddingFormat::Text
+        );
+    }
+
+    #[test]
+    fn detect_text_dims() {
+        assert_eq!(
+            EmbeddingFormat::detect("../rust2vec/testdata/similarity.txt").unwrap(),
+            EmbeddingFormat::TextDims
+        );
+    }
+
+    #[test]
+    fn detect_compressed_text_dims() {
+        assert_eq!(
+            EmbeddingFormat::detect("testdata/similarity.txt.gz").unwrap(),
+            EmbeddingFormat::TextDims
+        );
+    }
+
+    #[test]
+    fn detect_word2vec() {
+        assert_eq!(
+            EmbeddingFormat::detect("../rust2vec/testdata/similarity.bin").unwrap(),
+            EmbeddingFormat::Word2Vec
+        );
+    }
+
+    #[test]
+    fn detect_rejects_fasttext() {
+        let mut data = FASTTEXT_MAGIC.to_vec();
+        data.extend_from_slice(&[0; 16]);
+        let path = write_temp("fasttext", &data);
+        let result = EmbeddingFormat::detect(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn detect_rejects_unknown_format() {
+        let path = write_temp("unknown", b"this is not\nan embedding file\n");
+        let result = EmbeddingFormat::detect(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parse_shape_requires_two_integers() {
+        assert_eq!(parse_shape(b"41 100\n"), Some((41, 100)));
+        assert_eq!(parse_shape(b"41\n"), None);
+        assert_eq!(parse_shape(b"41 100 3\n"), None);
+        assert_eq!(parse_shape(b"Berlin 0.5\n"), None);
+    }
+
+    #[test]
+    fn is_text_embedding_checks_components() {
+        assert!(is_text_embedding(b"Berlin 0.5 -1\n", None));
+        assert!(is_text_embedding(b"Berlin 0.5 -1\n", Some(2)));
+        assert!(!is_text_embedding(b"Berlin 0.5 -1\n", Some(3)));
+        assert!(!is_text_embedding(b"Berlin\n", None));
+        assert!(!is_text_embedding(b"Berlin zero\n", None));
+        assert!(!is_text_embedding(b"Berlin \xff\n", None));
+    }
+}
@@ -0,0 +1,749 @@
+//! Alignment of embedding spaces.
+//!
+//! This module aligns the embeddings of a source space (e.g. of one
+//! language) to a target space (e.g. of another language) using a seed
+//! dictionary of word pairs. The alignment is an orthogonal map that is
+//! found by solving the orthogonal Procrustes problem. Since the map is
+//! orthogonal, distances and similarities within the source space are
+//! preserved.
+//!
+//! The seed dictionary can optionally be extended through self-learning:
+//! after each alignment, the mutual nearest neighbors among the most
+//! frequent source and target words are added to the dictionary and the
+//! map is computed again.
+//!
+//! Translations between aligned spaces can be retrieved with `Csls`,
+//! which reduces the hubness problem of nearest neighbor retrieval.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+
+use failure::{ensure, Error};
+use ndarray::{s, Array1, Array2, ArrayView1, ArrayView2, Axis};
+use ordered_float::NotNan;
+use rayon::prelude::*;
+use toml::Value;
+
+use crate::embeddings::Embeddings;
+use crate::linalg::svd;
+use crate::postprocess::PostProcessing;
+use crate::similarity::{top_k, WordSimilarity};
+use crate::storage::{NdArray, StorageView};
+use crate::util::matrix_to_toml;
+use crate::vocab::Vocab;
+
+/// Number of source embeddings that are compared to the target
+/// embeddings at once during self-learning.
+const BLOCK_SIZE: usize = 1024;
+
+/// Self-learning hyperparameters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SelfLearning {
+    /// The maximum number of self-learning iterations.
+    pub iterations: usize,
+
+    /// The number of most frequent words of each vocabulary that are
+    /// used to induce dictionary pairs.
+    pub n_candidates: usize,
+}
+
+impl Default for SelfLearning {
+    fn default() -> Self {
+        SelfLearning {
+            iterations: 5,
+            n_candidates: 10_000,
+        }
+    }
+}
+
+/// Orthogonal map between two embedding spaces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alignment {
+    /// The orthogonal map. Source embeddings are mapped to the target
+    /// space by multiplying them with this matrix: *x W*.
+    pub mapping: Array2<f32>,
+
+    /// The number of seed dictionary pairs for which both words have
+    /// an embedding.
+    pub n_seed_pairs: usize,
+
+    /// The number of pairs of the dictionary that was used for the
+    /// final map, including pairs induced by self-learning.
+    pub n_pairs: usize,
+
+    /// The number of self-learning iterations that were performed.
+    pub iterations: usize,
+}
+
+impl Alignment {
+    /// Compute the orthogonal map from `source` to `target`.
+    ///
+    /// `pairs` is the seed dictionary, consisting of (source word,
+    /// target word) pairs. Pairs for which either word does not have
+    /// an embedding are ignored. If `self_learning` is specified, the
+    /// dictionary is extended with mutual nearest neighbors.
+    pub fn compute<V1, S1, V2, S2>(
+        source: &Embeddings<V1, S1>,
+        target: &Embeddings<V2, S2>,
+        pairs: &[(&str, &str)],
+        self_learning: Option<SelfLearning>,
+    ) -> Result<Self, Error>
+    where
+        V1: Vocab,
+        S1: StorageView,
+        V2: Vocab,
+        S2: StorageView,
+    {
+        ensure!(
+            source.dims() == target.dims(),
+            "Source and target embeddings have different dimensionalities: {} and {}",
+            source.dims(),
+            target.dims()
+        );
+
+        let (seed_source, seed_target) = seed_matrices(source, target, pairs);
+        let n_seed_pairs = seed_source.rows();
+        ensure!(
+            n_seed_pairs != 0,
+            "None of the word pairs has source and target embeddings"
+        );
+
+        let mut alignment = Alignment {
+            mapping: procrustes(seed_source.view(), seed_target.view()),
+            n_seed_pairs,
+            n_pairs: n_seed_pairs,
+            iterations: 0,
+        };
+
+        let self_learning = match self_learning {
+            Some(self_learning) => self_learning,
+            None => return Ok(alignment),
+        };
+
+        let source_candidates = candidates(source, self_learning.n_candidates);
+        let target_candidates = candidates(target, self_learning.n_candidates);
+
+        let mut induced = HashSet::new();
+        for _ in 0..self_learning.iterations {
+            let mapped = source_candidates.dot(&alignment.mapping);
+            let nn_pairs = mutual_nearest_neighbors(mapped.view(), target_candidates)
+                .into_iter()
+                .collect::<HashSet<_>>();
+
+            // Stop when self-learning does not induce new pairs.
+            if nn_pairs == induced {
+                break;
+            }
+            induced = nn_pairs;
+
+            let mut nn_pairs = induced.iter().cloned().collect::<Vec<_>>();
+            nn_pairs.sort();
+            let (source_idx, target_idx): (Vec<_>, Vec<_>) = nn_pairs.into_iter().unzip();
+
+            let dict_source = ndarray::stack(
+                Axis(0),
+                &[
+                    seed_source.view(),
+                    source_candidates.select(Axis(0), &source_idx).view(),
+                ],
+            )?;
+            let dict_target = ndarray::stack(
+                Axis(0),
+                &[
+                    seed_target.view(),
+                    target_candidates.select(Axis(0), &target_idx).view(),
+                ],
+            )?;
+
+            alignment.mapping = procrustes(dict_source.view(), dict_target.view());
+            alignment.n_pairs = dict_source.rows();
+            alignment.iterations += 1;
+        }
+
+        Ok(alignment)
+    }
+
+    /// Get the alignment as metadata.
+    ///
+    /// The alignment is stored as a table with the map and the
+    /// dictionary statistics.
+    pub fn to_metadata(&self) -> Value {
+        let mut table = toml::value::Table::new();
+        table.insert("mapping".to_owned(), matrix_to_toml(self.mapping.view()));
+        table.insert(
+            "seed_pairs".to_owned(),
+            Value::Integer(self.n_seed_pairs as i64),
+        );
+        table.insert("pairs".to_owned(), Value::Integer(self.n_pairs as i64));
+        table.insert(
+            "self_learning_iterations".to_owned(),
+            Value::Integer(self.iterations as i64),
+        );
+        Value::Table(table)
+    }
+}
+
+/// Alignment of embeddings to another embedding space.
+pub trait Align<V> {
+    /// Align the embeddings to the space of `target`.
+    ///
+    /// This method computes an orthogonal map from the embeddings to
+    /// the target embeddings using the seed dictionary `pairs`
+    /// (see `Alignment::compute`) and returns the mapped embeddings.
+    /// The map is stored in the `alignment` table of the metadata. A
+    /// post-processing transform is mapped to the target space as well.
+    fn align<V2, S2>(
+        &self,
+        target: &Embeddings<V2, S2>,
+        pairs: &[(&str, &str)],
+        self_learning: Option<SelfLearning>,
+    ) -> Result<(Embeddings<V, NdArray>, Alignment), Error>
+    where
+        V2: Vocab,
+        S2: StorageView;
+}
+
+impl<V, S> Align<V> for Embeddings<V, S>
+where
+    V: Clone + Vocab,
+    S: StorageView,
+{
+    fn align<V2, S2>(
+        &self,
+        target: &Embeddings<V2, S2>,
+        pairs: &[(&str, &str)],
+        self_learning: Option<SelfLearning>,
+    ) -> Result<(Embeddings<V, NdArray>, Alignment), Error>
+    where
+        V2: Vocab,
+        S2: StorageView,
+    {
+        let alignment = Alignment::compute(self, target, pairs, self_learning)?;
+
+        let mut metadata = self.metadata().cloned().unwrap_or_default();
+        metadata.insert("alignment", alignment.to_metadata())?;
+
+        // The post-processing transform is applied to embeddings that are
+        // composed in the target space, so it is mapped as well.
+        if let Some(post_processing) = self.post_processing() {
+            let mapped_post_processing = PostProcessing {
+                mean: post_processing
+                    .mean
+                    .as_ref()
+                    .map(|mean| mean.dot(&alignment.mapping)),
+                components: post_processing.components.dot(&alignment.mapping),
+                normalize: post_processing.normalize,
+            };
+            metadata.insert("post_processing", mapped_post_processing.to_metadata())?;
+        }
+
+        // Since the map is linear, subword embeddings can be mapped
+        // as well.
+        let mapped = self.storage().view().dot(&alignment.mapping);
+
+        Ok((
+            Embeddings::new(Some(metadata), self.vocab().clone(), NdArray(mapped)),
+            alignment,
+        ))
+    }
+}
+
+/// Cross-domain similarity local scaling (CSLS) retrieval.
+///
+/// CSLS (Conneau et al., 2018) retrieves translations of source words
+/// in an aligned target space. Nearest neighbor retrieval suffers from
+/// hubness: some target words are the nearest neighbors of many source
+/// words. CSLS penalizes such hubs using the average similarity of a
+/// word to its *k* nearest neighbors in the other space:
+///
+/// *CSLS(x, y) = 2 cos(x, y) - r_T(x) - r_S(y)*
+///
+/// where *r_T(x)* is the average similarity of *x* to its *k* nearest
+/// target neighbors and *r_S(y)* the average similarity of *y* to its
+/// *k* nearest source neighbors. The embeddings should be normalized
+/// and the source embeddings should already be aligned to the target
+/// space.
+///
+/// As in the original formulation, the nearest neighbors are only
+/// searched among the most frequent words of each vocabulary. This
+/// bounds the cost of computing *r_S(y)* for every target word.
+pub struct Csls<'a, V1, S1, V2, S2> {
+    source: &'a Embeddings<V1, S1>,
+    target: &'a Embeddings<V2, S2>,
+    k: usize,
+    n_candidates: usize,
+    target_avg_sims: Array1<f32>,
+}
+
+impl<'a, V1, S1, V2, S2> Csls<'a, V1, S1, V2, S2>
+where
+    V1: Vocab,
+    S1: StorageView + Sync,
+    V2: Vocab,
+    S2: StorageView + Sync,
+{
+    /// Construct CSLS retrieval from `source` to `target`.
+    ///
+    /// The `k` nearest neighbors of a word are searched among the
+    /// `n_candidates` most frequent words of the other vocabulary. This
+    /// precomputes the average similarity of every target word to its
+    /// `k` nearest source words.
+    pub fn new(
+        source: &'a Embeddings<V1, S1>,
+        target: &'a Embeddings<V2, S2>,
+        k: usize,
+        n_candidates: usize,
+    ) -> Result<Self, Error> {
+        ensure!(
+            source.dims() == target.dims(),
+            "Source and target embeddings have different dimensionalities: {} and {}",
+            source.dims(),
+            target.dims()
+        );
+        ensure!(k != 0, "The number of neighbors should be at least 1");
+
+        let source_view = candidates(source, n_candidates);
+        let target_avg_sims = vocab_view(target)
+            .axis_chunks_iter(Axis(0), BLOCK_SIZE)
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .map(|block| {
+                block
+                    .dot(&source_view.t())
+                    .outer_iter()
+                    .map(|sims| mean_top_k(sims, k))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flatten()
+            .collect();
+
+        Ok(Csls {
+            source,
+            target,
+            k,
+            n_candidates,
+            target_avg_sims,
+        })
+    }
+
+    /// Get translation candidates for a source word.
+    ///
+    /// Returns at most `limit` target words, ranked by their CSLS
+    /// scores. `None` is returned if there is no embedding for `word`.
+    pub fn translate(&self, word: &str, limit: usize) -> Option<Vec<WordSimilarity<'a>>> {
+        let embed = self.source.embedding(word)?;
+        Some(self.translate_embedding(embed.as_view(), limit))
+    }
+
+    /// Get translation candidates for a source embedding.
+    ///
+    /// Returns at most `limit` target words, ranked by their CSLS
+    /// scores.
+    pub fn translate_embedding(
+        &self,
+        embed: ArrayView1<f32>,
+        limit: usize,
+    ) -> Vec<WordSimilarity<'a>> {
+        let mut sims = vocab_view(self.target).dot(&embed);
+        let n_candidates = self.n_candidates.min(sims.len());
+        let source_avg_sim = mean_top_k(sims.slice(s![..n_candidates]), self.k);
+
+        sims.zip_mut_with(&self.target_avg_sims, |sim, &target_avg_sim| {
+            *sim = 2. * *sim - source_avg_sim - target_avg_sim
+        });
+
+        top_k(self.target.vocab().words(), sims.view(), limit, |_, _| {
+            false
+        })
+    }
+}
+
+/// Get the embeddings of the words in the vocabulary.
+///
+/// This excludes e.g. subword embeddings.
+fn vocab_view<V, S>(embeddings: &Embeddings<V, S>) -> ArrayView2<'_, f32>
+where
+    V: Vocab,
+    S: StorageView,
+{
+    candidates(embeddings, embeddings.vocab().len())
+}
+
+/// Get the mean of the `k` highest similarities.
+fn mean_top_k(sims: ArrayView1<f32>, k: usize) -> f32 {
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for &sim in sims {
+        heap.push(Reverse(NotNan::new(sim).expect("Encountered NaN")));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+
+    if heap.is_empty() {
+        return 0.;
+    }
+
+    heap.iter().map(|sim| sim.0.into_inner()).sum::<f32>() / heap.len() as f32
+}
+
+/// Solve the orthogonal Procrustes problem.
+///
+/// Finds the orthogonal matrix *W* that minimizes *‖XW - Y‖*, where
+/// the rows of *X* and *Y* are the embeddings of translation pairs.
+/// The solution is *W = UVᵀ*, where *UΣVᵀ* is the SVD of *XᵀY*.
+fn procrustes(source: ArrayView2<f32>, target: ArrayView2<f32>) -> Array2<f32> {
+    let cross = source.mapv(f64::from).t().dot(&target.mapv(f64::from));
+    let (u, _, v) = svd(cross.view());
+    u.dot(&v.t()).mapv(|v| v as f32)
+}
+
+/// Get the source and target embeddings of the seed dictionary.
+fn seed_matrices<V1, S1, V2, S2>(
+    source: &Embeddings<V1, S1>,
+    target: &Embeddings<V2, S2>,
+    pairs: &[(&str, &str)],
+) -> (Array2<f32>, Array2<f32>)
+where
+    V1: Vocab,
+    S1: StorageView,
+    V2: Vocab,
+    S2: StorageView,
+{
+    let embeds = pairs
+        .iter()
+        .filter_map(|&(source_word, target_word)| {
+            Some((
+                source.embedding(source_word)?,
+                target.embedding(target_word)?,
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    let mut source_matrix = Array2::zeros((embeds.len(), source.dims()));
+    let mut target_matrix = Array2::zeros((embeds.len(), target.dims()));
+    for (idx, (source_embed, target_embed)) in embeds.iter().enumerate() {
+        source_matrix.row_mut(idx).assign(&source_embed.as_view());
+        target_matrix.row_mut(idx).assign(&target_embed.as_view());
+    }
+
+    (source_matrix, target_matrix)
+}
+
+/// Get the embeddings of the `n` most frequent words.
+fn candidates<V, S>(embeddings: &Embeddings<V, S>, n: usize) -> ArrayView2<'_, f32>
+where
+    V: Vocab,
+    S: StorageView,
+{
+    let n = n.min(embeddings.vocab().len());
+
+    // ndarray#474
+    #[allow(clippy::deref_addrof)]
+    embeddings.storage().view().slice_move(s![..n, ..])
+}
+
+/// Find the mutual nearest neighbors of two sets of embeddings.
+///
+/// Returns (source index, target index) pairs of embeddings that are
+/// each other's nearest neighbor.
+fn mutual_nearest_neighbors(
+    source: ArrayView2<f32>,
+    target: ArrayView2<f32>,
+) -> Vec<(usize, usize)> {
+    if source.rows() == 0 || target.rows() == 0 {
+        return Vec::new();
+    }
+
+    let mut source_nn = Vec::with_capacity(source.rows());
+    let mut target_nn = vec![(0, f32::NEG_INFINITY); target.rows()];
+
+    for (block_idx, block) in source.axis_chunks_iter(Axis(0), BLOCK_SIZE).enumerate() {
+        let sims = block.dot(&target.t());
+        for (row_idx, row) in sims.outer_iter().enumerate() {
+            let source_idx = block_idx * BLOCK_SIZE + row_idx;
+
+            let mut best = (0, f32::NEG_INFINITY);
+            for (target_idx, &sim) in row.iter().enumerate() {
+                if sim > best.1 {
+                    best = (target_idx, sim);
+                }
+
+                if sim > target_nn[target_idx].1 {
+                    target_nn[target_idx] = (source_idx, sim);
+                }
+            }
+
+            source_nn.push(best.0);
+        }
+    }
+
+    source_nn
+        .into_iter()
+        .enumerate()
+        .filter(|&(source_idx, target_idx)| target_nn[target_idx].0 == source_idx)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array2, Axis};
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    use super::{Align, Alignment, Csls, SelfLearning};
+    use crate::embeddings::Embeddings;
+    use crate::linalg::svd;
+    use crate::postprocess::PostProcess;
+    use crate::storage::{NdArray, StorageView};
+    use crate::test_util::random_embeddings;
+    use crate::vocab::{SimpleVocab, SubwordVocab, Vocab};
+
+    const DIMS: usize = 10;
+
+    const N_WORDS: usize = 50;
+
+    /// Rotate the embeddings with a random orthogonal matrix.
+    fn rotated<V>(
+        embeddings: &Embeddings<V, NdArray>,
+        rng: &mut XorShiftRng,
+    ) -> (Embeddings<V, NdArray>, Array2<f32>)
+    where
+        V: Clone,
+    {
+        let m = Array2::from_shape_fn((DIMS, DIMS), |_| rng.gen_range(-1., 1.));
+        let (u, _, _) = svd(m.view());
+        let rotation = u.mapv(|v| v as f32);
+
+        let storage = embeddings.storage().view().dot(&rotation);
+        (
+            Embeddings::new(None, embeddings.vocab().clone(), NdArray(storage)),
+            rotation,
+        )
+    }
+
+    fn assert_close(a: &Array2<f32>, b: &Array2<f32>) {
+        assert_eq!(a.shape(), b.shape());
+        for (&va, &vb) in a.iter().zip(b.iter()) {
+            assert!((va - vb).abs() < 1e-4, "{} != {}", va, vb);
+        }
+    }
+
+    fn identity_pairs<V>(embeddings: &Embeddings<V, NdArray>) -> Vec<(&str, &str)>
+    where
+        V: Vocab,
+    {
+        embeddings
+            .vocab()
+            .words()
+            .iter()
+            .map(|w| (w.as_str(), w.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn procrustes_recovers_rotation() {
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        let source = random_embeddings(&mut rng, N_WORDS, DIMS);
+        let (target, rotation) = rotated(&source, &mut rng);
+
+        let pairs = identity_pairs(&source);
+        let alignment = Alignment::compute(&source, &target, &pairs, None).unwrap();
+
+        assert_eq!(alignment.n_seed_pairs, N_WORDS);
+        assert_close(&alignment.mapping, &rotation);
+    }
+
+    #[test]
+    fn align_stores_mapping() {
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        let source = random_embeddings(&mut rng, N_WORDS, DIMS);
+        let (target, _) = rotated(&source, &mut rng);
+
+        let mut pairs = identity_pairs(&source);
+        pairs.push(("unknown", "w0"));
+        let (aligned, alignment) = source.align(&target, &pairs, None).unwrap();
+
+        assert_eq!(alignment.n_seed_pairs, N_WORDS);
+        let metadata = aligned.metadata().unwrap();
+        assert_eq!(
+            metadata.0["alignment"]["mapping"].as_array().unwrap().len(),
+            DIMS
+        );
+        assert_eq!(
+            metadata.0["alignment"]["seed_pairs"].as_integer(),
+            Some(N_WORDS as i64)
+        );
+
+        assert_close(
+            &aligned
+                .embedding("w1")
+                .unwrap()
+                .as_view()
+                .insert_axis(Axis(0))
+                .to_owned(),
+            &target
+                .embedding("w1")
+                .unwrap()
+                .as_view()
+                .insert_axis(Axis(0))
+                .to_owned(),
+        );
+    }
+
+    /// Fraction of source words whose nearest mapped neighbor is the
+    /// same word in the target space.
+    fn accuracy(
+        source: &Embeddings<SimpleVocab, NdArray>,
+        target: &Embeddings<SimpleVocab, NdArray>,
+        mapping: &Array2<f32>,
+    ) -> f32 {
+        let sims = source
+            .storage()
+            .view()
+            .dot(mapping)
+            .dot(&target.storage().view().t());
+        let n_correct = sims
+            .outer_iter()
+            .enumerate()
+            .filter(|(idx, row)| {
+                row.iter()
+                    .enumerate()
+                    .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+                    .unwrap()
+                    .0
+                    == *idx
+            })
+            .count();
+        n_correct as f32 / N_WORDS as f32
+    }
+
+    #[test]
+    fn align_maps_post_processing() {
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        let words = (0..N_WORDS)
+            .map(|idx| format!("w{}", idx))
+            .collect::<Vec<_>>();
+        let storage = Array2::from_shape_fn((N_WORDS + 16, DIMS), |_| rng.gen_range(-1., 1.));
+        let mut source = Embeddings::new(None, SubwordVocab::new(words, 3, 6, 4), NdArray(storage));
+        source.post_process(true, 1, true).unwrap();
+        let (target, rotation) = rotated(&source, &mut rng);
+
+        let pairs = identity_pairs(&source);
+        let (aligned, _) = source.align(&target, &pairs, None).unwrap();
+        assert!(aligned.post_processing().is_some());
+
+        // Composed embeddings are the mapped post-processed embeddings.
+        for word in &["unknown", "w0x", "foobar"] {
+            let check = source
+                .embedding(word)
+                .unwrap()
+                .as_view()
+                .dot(&rotation)
+                .insert_axis(Axis(0));
+            let embed = aligned.embedding(word).unwrap().as_view().to_owned();
+            assert_close(&embed.insert_axis(Axis(0)), &check);
+        }
+    }
+
+    #[test]
+    fn self_learning_induces_pairs() {
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        let source = random_embeddings(&mut rng, N_WORDS, DIMS);
+        let (target, _) = rotated(&source, &mut rng);
+
+        // The seed dictionary is too small to determine the rotation.
+        let pairs = identity_pairs(&source);
+        let pairs = &pairs[..DIMS / 2];
+        let alignment = Alignment::compute(&source, &target, pairs, None).unwrap();
+        let seed_accuracy = accuracy(&source, &target, &alignment.mapping);
+
+        let self_learning = SelfLearning {
+            iterations: 10,
+            n_candidates: N_WORDS,
+        };
+        let alignment = Alignment::compute(&source, &target, pairs, Some(self_learning)).unwrap();
+        let accuracy = accuracy(&source, &target, &alignment.mapping);
+
+        assert!(alignment.iterations > 0);
+        assert!(alignment.n_pairs > alignment.n_seed_pairs);
+        assert!(accuracy > seed_accuracy);
+    }
+
+    #[test]
+    fn csls_translates() {
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        let source = random_embeddings(&mut rng, N_WORDS, DIMS);
+        let (target, rotation) = rotated(&source, &mut rng);
+        let aligned = Embeddings::new(
+            None,
+            source.vocab().clone(),
+            NdArray(source.storage().view().dot(&rotation)),
+        );
+
+        let csls = Csls::new(&aligned, &target, 3, N_WORDS).unwrap();
+        for word in source.vocab().words() {
+            let result = csls.translate(word, 5).unwrap();
+            assert_eq!(result.len(), 5);
+            assert_eq!(result[0].word, word);
+        }
+
+        assert!(csls.translate("unknown", 5).is_none());
+    }
+
+    #[test]
+    fn csls_scores() {
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        let source = random_embeddings(&mut rng, N_WORDS, DIMS);
+        let target = random_embeddings(&mut rng, N_WORDS, DIMS);
+
+        let k = 4;
+        let mean_top_k = |mut sims: Vec<f32>| {
+            sims.sort_by(|a, b| b.partial_cmp(a).unwrap());
+            sims[..k].iter().sum::<f32>() / k as f32
+        };
+
+        let n_candidates = 20;
+        let csls = Csls::new(&source, &target, k, n_candidates).unwrap();
+        let query = source.embedding("w0").unwrap();
+        let result = csls.translate("w0", 3).unwrap();
+        for word_similarity in result {
+            let target_embed = target.embedding(word_similarity.word).unwrap();
+            let source_avg_sim = mean_top_k(
+                target
+                    .storage()
+                    .view()
+                    .outer_iter()
+                    .take(n_candidates)
+                    .map(|embed| embed.dot(&query.as_view()))
+                    .collect(),
+            );
+            let target_avg_sim = mean_top_k(
+                source
+                    .storage()
+                    .view()
+                    .outer_iter()
+                    .take(n_candidates)
+                    .map(|embed| embed.dot(&target_embed.as_view()))
+                    .collect(),
+            );
+            let check =
+                2. * query.as_view().dot(&target_embed.as_view()) - source_avg_sim - target_avg_sim;
+            assert!((*word_similarity.similarity - check).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn rejects_dimensionality_mismatch() {
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        let source = random_embeddings(&mut rng, N_WORDS, DIMS);
+        let target = Embeddings::new(
+            None,
+            SimpleVocab::new(vec!["w0".to_owned()]),
+            NdArray(Array2::zeros((1, DIMS + 1))),
+        );
+
+        assert!(Alignment::compute(&source, &target, &[("w0", "w0")], None).is_err());
+    }
+}
@@ -0,0 +1,646 @@
+//! Measurement and removal of biases.
+//!
+//! This module implements the Word Embedding Association Test (WEAT)
+//! of Caliskan et al. (2017). WEAT measures the differential
+//! association of two sets of target words (e.g. career and family
+//! words) with two sets of attribute words (e.g. male and female
+//! names).
+//!
+//! This module also implements hard debiasing (Bolukbasi et al., 2016).
+//! A bias direction is computed from definitional pairs (such as
+//! *she*/*he*). The bias direction is then removed from the embeddings
+//! of neutral words (*neutralize*), and the words of equality pairs
+//! (such as *grandmother*/*grandfather*) are made equidistant to all
+//! neutral words (*equalize*).
+
+use std::collections::HashSet;
+use std::io::BufRead;
+
+use failure::{ensure, Error};
+use ndarray::{Array1, Array2, ArrayView1};
+use rand::seq::SliceRandom;
+use rand::{FromEntropy, Rng};
+use rand_xorshift::XorShiftRng;
+use toml::Value;
+
+use crate::embeddings::Embeddings;
+use crate::linalg::principal_directions;
+use crate::storage::{NdArray, Storage, StorageView};
+use crate::util::{l2_normalize, vector_to_toml};
+use crate::vocab::{Vocab, WordIndex};
+
+/// Target and attribute word sets of a WEAT test.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WeatSets {
+    /// The first target set (*X*).
+    pub targets_x: Vec<String>,
+
+    /// The second target set (*Y*).
+    pub targets_y: Vec<String>,
+
+    /// The first attribute set (*A*).
+    pub attributes_a: Vec<String>,
+
+    /// The second attribute set (*B*).
+    pub attributes_b: Vec<String>,
+}
+
+/// Read WEAT word sets.
+///
+/// The file contains four sets in the order *X*, *Y*, *A*, *B*. The
+/// words of a set are listed one per line, sets are separated by one
+/// or more empty lines. Lines starting with `#` are ignored.
+pub fn read_weat_sets(reader: impl BufRead) -> Result<WeatSets, Error> {
+    let mut sets = vec![Vec::new()];
+
+    for line in reader.lines() {
+        let line = line?;
+        let word = line.trim();
+
+        if word.starts_with('#') {
+            continue;
+        }
+
+        if word.is_empty() {
+            if !sets.last().unwrap().is_empty() {
+                sets.push(Vec::new());
+            }
+
+            continue;
+        }
+
+        sets.last_mut().unwrap().push(word.to_owned());
+    }
+
+    if sets.last().unwrap().is_empty() {
+        sets.pop();
+    }
+
+    ensure!(
+        sets.len() == 4,
+        "WEAT file should contain 4 word sets, found: {}",
+        sets.len()
+    );
+
+    let attributes_b = sets.pop().unwrap();
+    let attributes_a = sets.pop().unwrap();
+    let targets_y = sets.pop().unwrap();
+    let targets_x = sets.pop().unwrap();
+
+    Ok(WeatSets {
+        targets_x,
+        targets_y,
+        attributes_a,
+        attributes_b,
+    })
+}
+
+/// Result of a WEAT test.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Weat {
+    /// The effect size.
+    pub effect_size: f64,
+
+    /// The test statistic *s(X, Y, A, B)*.
+    pub test_statistic: f64,
+
+    /// The one-sided p-value of the permutation test.
+    pub p_value: f64,
+
+    /// The number of permutations that were used to compute the
+    /// p-value.
+    pub n_permutations: usize,
+
+    /// Words without an embedding, which were not used in the test.
+    pub missing: Vec<String>,
+}
+
+/// Word Embedding Association Test.
+pub trait WordEmbeddingAssociation {
+    /// Perform a WEAT test.
+    ///
+    /// The p-value is estimated using `n_permutations` random
+    /// partitions of the target words. Words without an embedding are
+    /// not used in the test and are reported as missing.
+    fn weat(&self, sets: &WeatSets, n_permutations: usize) -> Result<Weat, Error> {
+        self.weat_using(sets, n_permutations, &mut XorShiftRng::from_entropy())
+    }
+
+    /// Perform a WEAT test using the provided RNG.
+    ///
+    /// The RNG is used to sample partitions of the target words.
+    fn weat_using<R>(
+        &self,
+        sets: &WeatSets,
+        n_permutations: usize,
+        rng: &mut R,
+    ) -> Result<Weat, Error>
+    where
+        R: Rng;
+}
+
+impl<V, S> WordEmbeddingAssociation for Embeddings<V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    fn weat_using<R>(
+        &self,
+        sets: &WeatSets,
+        n_permutations: usize,
+        rng: &mut R,
+    ) -> Result<Weat, Error>
+    where
+        R: Rng,
+    {
+        let mut missing = Vec::new();
+        let mut lookup = |words: &[String], name: &str| -> Result<Vec<Array1<f32>>, Error> {
+            let mut embeds = Vec::with_capacity(words.len());
+            for word in words {
+                match self.embedding(word) {
+                    Some(embed) => {
+                        let mut embed = embed.into_owned();
+                        l2_normalize(embed.view_mut());
+                        embeds.push(embed);
+                    }
+                    None => missing.push(word.clone()),
+                }
+            }
+
+            ensure!(
+                !embeds.is_empty(),
+                "No word of set {} has an embedding",
+                name
+            );
+
+            Ok(embeds)
+        };
+
+        let x = lookup(&sets.targets_x, "X")?;
+        let y = lookup(&sets.targets_y, "Y")?;
+        let a = lookup(&sets.attributes_a, "A")?;
+        let b = lookup(&sets.attributes_b, "B")?;
+
+        // Associations of the target words with the attributes.
+        let associations = x
+            .iter()
+            .chain(y.iter())
+            .map(|w| association(w.view(), &a, &b))
+            .collect::<Vec<_>>();
+        let (assoc_x, assoc_y) = associations.split_at(x.len());
+
+        let effect_size = (mean(assoc_x) - mean(assoc_y)) / sample_std(&associations);
+        ensure!(
+            effect_size.is_finite(),
+            "Cannot compute effect size, associations do not vary"
+        );
+
+        let test_statistic = assoc_x.iter().sum::<f64>() - assoc_y.iter().sum::<f64>();
+
+        // Permutation test: the fraction of partitions of X ∪ Y into
+        // sets of sizes |X| and |Y| with a larger test statistic.
+        let mut permuted = associations.clone();
+        let mut n_larger = 0;
+        for _ in 0..n_permutations {
+            permuted.shuffle(rng);
+            let (perm_x, perm_y) = permuted.split_at(x.len());
+            let statistic = perm_x.iter().sum::<f64>() - perm_y.iter().sum::<f64>();
+            if statistic > test_statistic {
+                n_larger += 1;
+            }
+        }
+
+        let p_value = if n_permutations == 0 {
+            1.
+        } else {
+            n_larger as f64 / n_permutations as f64
+        };
+
+        Ok(Weat {
+            effect_size,
+            test_statistic,
+            p_value,
+            n_permutations,
+            missing,
+        })
+    }
+}
+
+/// Compute the association *s(w, A, B)* of a word with two attribute
+/// sets.
+///
+/// The embeddings should be normalized.
+fn association(w: ArrayView1<f32>, a: &[Array1<f32>], b: &[Array1<f32>]) -> f64 {
+    let mean_sim = |attributes: &[Array1<f32>]| {
+        attributes
+            .iter()
+            .map(|attr| w.dot(attr) as f64)
+            .sum::<f64>()
+            / attributes.len() as f64
+    };
+
+    mean_sim(a) - mean_sim(b)
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn sample_std(values: &[f64]) -> f64 {
+    let mean = mean(values);
+    let ss = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>();
+    (ss / (values.len() as f64 - 1.)).sqrt()
+}
+
+/// Result of hard debiasing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Debiasing {
+    /// The bias direction.
+    pub direction: Array1<f32>,
+
+    /// The number of definitional pairs for which both words have an
+    /// embedding.
+    pub n_definitional_pairs: usize,
+
+    /// The number of neutralized words.
+    pub n_neutralized: usize,
+
+    /// The number of equalized pairs.
+    pub n_equalized: usize,
+
+    /// Equality pairs that were not equalized, because both words have
+    /// the same bias.
+    pub unequalized: Vec<(String, String)>,
+
+    /// Words of definitional and equality pairs that are not in the
+    /// vocabulary.
+    pub missing: Vec<String>,
+}
+
+impl Debiasing {
+    /// Get the debiasing as metadata.
+    pub fn to_metadata(&self) -> Value {
+        let mut table = toml::value::Table::new();
+        table.insert(
+            "direction".to_owned(),
+            vector_to_toml(self.direction.view()),
+        );
+        table.insert(
+            "definitional_pairs".to_owned(),
+            Value::Integer(self.n_definitional_pairs as i64),
+        );
+        table.insert(
+            "neutralized_words".to_owned(),
+            Value::Integer(self.n_neutralized as i64),
+        );
+        table.insert(
+            "equalized_pairs".to_owned(),
+            Value::Integer(self.n_equalized as i64),
+        );
+        Value::Table(table)
+    }
+}
+
+/// Hard debiasing of embeddings.
+pub trait Debias<V> {
+    /// Remove a bias from the embeddings.
+    ///
+    /// The bias direction is the first principal component of the
+    /// differences between the words of the `definitional` pairs.
+    /// All known words, except for the words in `exclude` and the
+    /// words of the `definitional` and `equalize` pairs, are
+    /// neutralized. The words of
+    /// the `equalize` pairs are then equalized, pairs of words with the
+    /// same bias are skipped. The embeddings of known
+    /// words are normalized. Since the bias direction is removed from
+    /// subword embeddings as well, embeddings of unknown words are
+    /// also neutral.
+    ///
+    /// Post-processed embeddings are rejected, because the
+    /// post-processing transform of embeddings of unknown words would
+    /// reintroduce the bias.
+    ///
+    /// The debiasing is stored in the `debiasing` table of the metadata.
+    fn debias(
+        &self,
+        definitional: &[(&str, &str)],
+        equalize: &[(&str, &str)],
+        exclude: &[&str],
+    ) -> Result<(Embeddings<V, NdArray>, Debiasing), Error>;
+}
+
+impl<V, S> Debias<V> for Embeddings<V, S>
+where
+    V: Clone + Vocab,
+    S: StorageView,
+{
+    fn debias(
+        &self,
+        definitional: &[(&str, &str)],
+        equalize: &[(&str, &str)],
+        exclude: &[&str],
+    ) -> Result<(Embeddings<V, NdArray>, Debiasing), Error> {
+        ensure!(
+            self.post_processing().is_none(),
+            "Cannot debias post-processed embeddings"
+        );
+
+        let n_words = self.vocab().len();
+        let mut storage = self.storage().view().to_owned();
+        for embed in storage.outer_iter_mut().take(n_words) {
+            l2_normalize(embed);
+        }
+
+        let mut missing = Vec::new();
+        let definitional = pair_indices(self.vocab(), definitional, &mut missing);
+        let equalize = pair_indices(self.vocab(), equalize, &mut missing);
+
+        ensure!(
+            !definitional.is_empty(),
+            "None of the definitional pairs is in the vocabulary"
+        );
+
+        // Compute the bias direction from the centered pairs.
+        let mut centered = Array2::zeros((definitional.len() * 2, storage.cols()));
+        for (idx, &(u, w)) in definitional.iter().enumerate() {
+            let center = (&storage.row(u) + &storage.row(w)) / 2.;
+            centered
+                .row_mut(idx * 2)
+                .assign(&(&storage.row(u) - &center));
+            centered
+                .row_mut(idx * 2 + 1)
+                .assign(&(&storage.row(w) - &center));
+        }
+        let direction = principal_directions(centered.view(), 1).1.row(0).to_owned();
+
+        // Neutralize.
+        let skip = exclude
+            .iter()
+            .filter_map(|word| word_idx(self.vocab(), word))
+            .chain(
+                definitional
+                    .iter()
+                    .chain(equalize.iter())
+                    .flat_map(|&(u, w)| vec![u, w]),
+            )
+            .collect::<HashSet<_>>();
+        let mut n_neutralized = 0;
+        for (idx, mut embed) in storage.outer_iter_mut().enumerate() {
+            if skip.contains(&idx) {
+                continue;
+            }
+
+            let bias = embed.dot(&direction);
+            embed.scaled_add(-bias, &direction);
+
+            if idx < n_words {
+                l2_normalize(embed);
+                n_neutralized += 1;
+            }
+        }
+
+        // Equalize.
+        let mut unequalized = Vec::new();
+        for &(u, w) in &equalize {
+            let mean = (&storage.row(u) + &storage.row(w)) / 2.;
+            let mean_bias = &direction * mean.dot(&direction);
+
+            // The words are moved apart along the bias direction, which
+            // is not possible when they have the same bias.
+            let mut offset = &direction * storage.row(u).dot(&direction) - &mean_bias;
+            if l2_normalize(offset.view_mut()) == 0. {
+                let words = self.vocab().words();
+                unequalized.push((words[u].clone(), words[w].clone()));
+                continue;
+            }
+
+            let nu = &mean - &mean_bias;
+            offset *= (1. - nu.dot(&nu)).max(0.).sqrt();
+            storage.row_mut(u).assign(&(&nu + &offset));
+            storage.row_mut(w).assign(&(&nu - &offset));
+        }
+
+        let debiasing = Debiasing {
+            direction,
+            n_definitional_pairs: definitional.len(),
+            n_neutralized,
+            n_equalized: equalize.len() - unequalized.len(),
+            unequalized,
+            missing,
+        };
+
+        let mut metadata = self.metadata().cloned().unwrap_or_default();
+        metadata.insert("debiasing", debiasing.to_metadata())?;
+
+        Ok((
+            Embeddings::new(Some(metadata), self.vocab().clone(), NdArray(storage)),
+            debiasing,
+        ))
+    }
+}
+
+fn word_idx(vocab: &impl Vocab, word: &str) -> Option<usize> {
+    match vocab.idx(word) {
+        Some(WordIndex::Word(idx)) => Some(idx),
+        _ => None,
+    }
+}
+
+/// Convert word pairs to pairs of word indices.
+///
+/// Words that are not in the vocabulary are added to `missing`.
+fn pair_indices(
+    vocab: &impl Vocab,
+    pairs: &[(&str, &str)],
+    missing: &mut Vec<String>,
+) -> Vec<(usize, usize)> {
+    let mut indices = Vec::with_capacity(pairs.len());
+    for &(u, w) in pairs {
+        let u_idx = word_idx(vocab, u);
+        let w_idx = word_idx(vocab, w);
+        match (u_idx, w_idx) {
+            (Some(u_idx), Some(w_idx)) if u_idx != w_idx => indices.push((u_idx, w_idx)),
+            _ => missing.extend(
+                [(u, u_idx), (w, w_idx)]
+                    .iter()
+                    .filter(|(_, idx)| idx.is_none())
+                    .map(|(word, _)| (*word).to_owned()),
+            ),
+        }
+    }
+
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use ndarray::arr2;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use super::{read_weat_sets, Debias, WeatSets, WordEmbeddingAssociation};
+    use crate::postprocess::PostProcess;
+    use crate::storage::StorageView;
+    use crate::test_util::embeddings_from_rows;
+    use crate::util::l2_normalize;
+
+    fn to_strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|&w| w.to_owned()).collect()
+    }
+
+    #[test]
+    fn read_weat_sets_test() {
+        let sets = read_weat_sets(Cursor::new(
+            "# targets\nrose\ntulip\n\n\nspider\nmoth\n\npleasant\n\nawful\nugly\n",
+        ))
+        .unwrap();
+
+        assert_eq!(sets.targets_x, to_strings(&["rose", "tulip"]));
+        assert_eq!(sets.targets_y, to_strings(&["spider", "moth"]));
+        assert_eq!(sets.attributes_a, to_strings(&["pleasant"]));
+        assert_eq!(sets.attributes_b, to_strings(&["awful", "ugly"]));
+
+        assert!(read_weat_sets(Cursor::new("rose\n\nspider\n\npleasant\n")).is_err());
+    }
+
+    #[test]
+    fn weat_biased() {
+        let embeddings = embeddings_from_rows(
+            &[
+                "rose", "tulip", "daisy", "spider", "moth", "wasp", "nice", "awful",
+            ],
+            arr2(&[
+                [1., 0.1, 0.],
+                [0.9, 0.2, 0.1],
+                [0.8, 0., 0.2],
+                [0.1, 1., 0.],
+                [0., 0.9, 0.1],
+                [0.2, 0.8, 0.],
+                [1., 0., 0.],
+                [0., 1., 0.],
+            ]),
+        );
+
+        let mut sets = WeatSets {
+            targets_x: to_strings(&["rose", "tulip", "daisy", "lily"]),
+            targets_y: to_strings(&["spider", "moth", "wasp"]),
+            attributes_a: to_strings(&["nice"]),
+            attributes_b: to_strings(&["awful"]),
+        };
+
+        let weat = embeddings
+            .weat_using(&sets, 1000, &mut XorShiftRng::seed_from_u64(42))
+            .unwrap();
+        assert!(weat.effect_size > 1.5);
+        assert!(weat.test_statistic > 0.);
+        assert!(weat.p_value < 0.1);
+        assert_eq!(weat.n_permutations, 1000);
+        assert_eq!(weat.missing, to_strings(&["lily"]));
+
+        // Swapping the attributes reverses the effect.
+        std::mem::swap(&mut sets.attributes_a, &mut sets.attributes_b);
+        let weat = embeddings
+            .weat_using(&sets, 1000, &mut XorShiftRng::seed_from_u64(42))
+            .unwrap();
+        assert!(weat.effect_size < -1.5);
+        assert!(weat.p_value > 0.9);
+
+        sets.attributes_a = to_strings(&["lovely"]);
+        assert!(embeddings.weat(&sets, 10).is_err());
+    }
+
+    #[test]
+    fn debias_neutralize_equalize() {
+        let mut storage = arr2(&[
+            [1., 0.5, 0.],
+            [-1., 0.5, 0.],
+            [0.6, 0.5, 0.5],
+            [-0.2, 0.8, 0.1],
+            [0.3, 0.1, 1.],
+            [0.5, 0.2, 1.],
+        ]);
+        for embed in storage.outer_iter_mut() {
+            l2_normalize(embed);
+        }
+        let embeddings = embeddings_from_rows(
+            &["she", "he", "nurse", "grandfather", "grandmother", "queen"],
+            storage,
+        );
+
+        let (debiased, debiasing) = embeddings
+            .debias(
+                &[("she", "he"), ("her", "his")],
+                &[("grandmother", "grandfather")],
+                &["queen"],
+            )
+            .unwrap();
+
+        assert_eq!(debiasing.n_definitional_pairs, 1);
+        assert_eq!(debiasing.n_equalized, 1);
+        assert_eq!(debiasing.missing, to_strings(&["her", "his"]));
+        assert!(debiasing.direction[0].abs() > 0.99);
+
+        let view = debiased.storage().view();
+
+        // The neutralized word has no bias.
+        assert!(view.row(2).dot(&debiasing.direction).abs() < 1e-5);
+
+        // The equalized words are equidistant to the neutral word.
+        let sim_grandmother = view.row(4).dot(&view.row(2));
+        let sim_grandfather = view.row(3).dot(&view.row(2));
+        assert!((sim_grandmother - sim_grandfather).abs() < 1e-5);
+
+        // Excluded words are not changed.
+        assert_eq!(view.row(5), embeddings.storage().view().row(5));
+
+        // Embeddings are normalized.
+        for embed in view.outer_iter() {
+            assert!((embed.dot(&embed) - 1.).abs() < 1e-5);
+        }
+
+        assert!(debiased.metadata().unwrap().0["debiasing"]["direction"].is_array());
+        assert!(embeddings.debias(&[("her", "his")], &[], &[]).is_err());
+    }
+
+    #[test]
+    fn debias_skips_pairs_with_same_bias() {
+        let mut storage = arr2(&[
+            [1., 0.5, 0.],
+            [-1., 0.5, 0.],
+            [0.5, 0.8, 0.],
+            [0.5, 0., 0.8],
+        ]);
+        for embed in storage.outer_iter_mut() {
+            l2_normalize(embed);
+        }
+        let embeddings = embeddings_from_rows(&["she", "he", "table", "desk"], storage);
+
+        let (debiased, debiasing) = embeddings
+            .debias(&[("she", "he")], &[("table", "desk")], &[])
+            .unwrap();
+
+        assert_eq!(debiasing.n_equalized, 0);
+        assert_eq!(
+            debiasing.unequalized,
+            vec![("table".to_owned(), "desk".to_owned())]
+        );
+
+        // The words are not collapsed.
+        let view = debiased.storage().view();
+        assert_eq!(view.row(2), embeddings.storage().view().row(2));
+        assert_eq!(view.row(3), embeddings.storage().view().row(3));
+    }
+
+    #[test]
+    fn debias_rejects_post_processed() {
+        let mut embeddings = embeddings_from_rows(
+            &["she", "he", "nurse"],
+            arr2(&[[1., 0.5, 0.], [-1., 0.5, 0.], [0.6, 0.5, 0.5]]),
+        );
+        embeddings.post_process(true, 0, true).unwrap();
+
+        assert!(embeddings.debias(&[("she", "he")], &[], &[]).is_err());
+    }
+}
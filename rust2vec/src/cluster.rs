@@ -0,0 +1,323 @@
+//! k-means clustering of embeddings.
+//!
+//! This module clusters the embeddings of a storage using k-means. The
+//! clustering can use Euclidean distances or, in spherical k-means, cosine
+//! similarities. The k-means iterations are performed by reductive,
+//! centroids are initialized using randomly chosen embeddings.
+
+use failure::{ensure, Error};
+use ndarray::{s, Array2, ArrayView1, ArrayView2, Axis};
+use rand::{FromEntropy, Rng};
+use rand_xorshift::XorShiftRng;
+use rayon::prelude::*;
+use reductive::kmeans::{InitialCentroids, KMeansIteration, RandomInstanceCentroids};
+
+use crate::storage::StorageView;
+use crate::util::l2_normalize;
+
+/// Number of embeddings that are assigned to clusters at once.
+const ASSIGN_BLOCK_SIZE: usize = 1024;
+
+/// k-means hyperparameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KMeans {
+    /// The number of clusters.
+    pub n_clusters: usize,
+
+    /// The maximum number of iterations.
+    pub n_iterations: usize,
+
+    /// Cluster only the first *n* embeddings.
+    ///
+    /// Since vocabularies are typically sorted by frequency, this
+    /// clusters the embeddings of the *n* most frequent words.
+    pub n_embeddings: Option<usize>,
+
+    /// Use spherical k-means, which clusters embeddings by their cosine
+    /// similarities.
+    pub spherical: bool,
+}
+
+impl Default for KMeans {
+    fn default() -> Self {
+        KMeans {
+            n_clusters: 100,
+            n_iterations: 100,
+            n_embeddings: None,
+            spherical: false,
+        }
+    }
+}
+
+/// A k-means clustering.
+#[derive(Clone, Debug)]
+pub struct Clustering {
+    /// The cluster centroids.
+    pub centroids: Array2<f32>,
+
+    /// The cluster of each clustered embedding.
+    pub assignments: Vec<usize>,
+
+    /// The number of iterations that were performed.
+    pub iterations: usize,
+
+    /// The clustering is spherical.
+    pub spherical: bool,
+}
+
+impl Clustering {
+    /// Assign an embedding to its nearest cluster.
+    ///
+    /// This can be used to assign embeddings that were not clustered,
+    /// such as embeddings of unknown words that are constructed from
+    /// subword units.
+    pub fn assign(&self, embed: ArrayView1<f32>) -> usize {
+        assign_block(
+            self.centroids.view(),
+            embed.insert_axis(Axis(0)),
+            self.spherical,
+        )[0]
+    }
+}
+
+/// Trait for k-means clustering of embedding matrices.
+pub trait Cluster {
+    /// Cluster the embeddings.
+    fn cluster(&self, k_means: KMeans) -> Result<Clustering, Error> {
+        self.cluster_using(k_means, &mut XorShiftRng::from_entropy())
+    }
+
+    /// Cluster the embeddings using the provided RNG.
+    ///
+    /// The RNG is used to choose the initial centroids.
+    fn cluster_using<R>(&self, k_means: KMeans, rng: &mut R) -> Result<Clustering, Error>
+    where
+        R: Rng;
+}
+
+impl<S> Cluster for S
+where
+    S: StorageView,
+{
+    fn cluster_using<R>(&self, k_means: KMeans, rng: &mut R) -> Result<Clustering, Error>
+    where
+        R: Rng,
+    {
+        let view = self.view();
+        let n_embeddings = k_means
+            .n_embeddings
+            .unwrap_or_else(|| view.rows())
+            .min(view.rows());
+        ensure!(
+            k_means.n_clusters > 0,
+            "The number of clusters should be positive"
+        );
+        ensure!(
+            k_means.n_clusters < n_embeddings,
+            "Cannot create {} clusters from {} embeddings",
+            k_means.n_clusters,
+            n_embeddings
+        );
+
+        let view = view.slice(s![..n_embeddings, ..]);
+        let normalized;
+        let data = if k_means.spherical {
+            let mut copy = view.to_owned();
+            for row in copy.outer_iter_mut() {
+                l2_normalize(row);
+            }
+            normalized = copy;
+            normalized.view()
+        } else {
+            view
+        };
+
+        let mut centroids =
+            RandomInstanceCentroids::new(rng).initial_centroids(data, Axis(0), k_means.n_clusters);
+
+        let mut iterations = 0;
+        let mut prev_loss = f32::INFINITY;
+        while iterations < k_means.n_iterations {
+            iterations += 1;
+
+            let loss = data.kmeans_iteration(Axis(0), centroids.view_mut());
+            if k_means.spherical {
+                for centroid in centroids.outer_iter_mut() {
+                    l2_normalize(centroid);
+                }
+            }
+
+            // The clustering has converged when the loss does not decrease.
+            if loss >= prev_loss {
+                break;
+            }
+            prev_loss = loss;
+        }
+
+        let assignments = assign(centroids.view(), data, k_means.spherical);
+
+        Ok(Clustering {
+            centroids,
+            assignments,
+            iterations,
+            spherical: k_means.spherical,
+        })
+    }
+}
+
+/// Assign embeddings to their nearest centroids.
+fn assign(centroids: ArrayView2<f32>, data: ArrayView2<f32>, spherical: bool) -> Vec<usize> {
+    let block_starts: Vec<_> = (0..data.rows()).step_by(ASSIGN_BLOCK_SIZE).collect();
+    block_starts
+        .into_par_iter()
+        .flat_map(|start| {
+            let end = (start + ASSIGN_BLOCK_SIZE).min(data.rows());
+            assign_block(centroids, data.slice(s![start..end, ..]), spherical)
+        })
+        .collect()
+}
+
+fn assign_block(centroids: ArrayView2<f32>, block: ArrayView2<f32>, spherical: bool) -> Vec<usize> {
+    let dots = block.dot(&centroids.t());
+
+    // For Euclidean distances, the nearest centroid minimizes
+    // |c|² - 2 x·c. For cosine similarities, the nearest (normalized)
+    // centroid maximizes x·c.
+    let centroid_norms = centroids.map_axis(Axis(1), |c| c.dot(&c));
+    dots.outer_iter()
+        .map(|dots| {
+            let scores = dots.iter().zip(centroid_norms.iter()).map(|(&dot, &norm)| {
+                if spherical {
+                    -dot
+                } else {
+                    norm - 2. * dot
+                }
+            });
+
+            scores
+                .enumerate()
+                .fold((0, f32::INFINITY), |(best, best_score), (idx, score)| {
+                    if score < best_score {
+                        (idx, score)
+                    } else {
+                        (best, best_score)
+                    }
+                })
+                .0
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{arr1, Array2};
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    use super::{Cluster, KMeans};
+    use crate::storage::NdArray;
+
+    /// Sample points around the given centers.
+    fn blobs(centers: &[[f32; 2]], n_per_center: usize, spread: f32) -> NdArray {
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        let mut data = Array2::zeros((centers.len() * n_per_center, 2));
+        for (idx, mut row) in data.outer_iter_mut().enumerate() {
+            let center = centers[idx % centers.len()];
+            row[0] = center[0] + rng.gen_range(-spread, spread);
+            row[1] = center[1] + rng.gen_range(-spread, spread);
+        }
+
+        NdArray(data)
+    }
+
+    fn check_clusters(assignments: &[usize], n_centers: usize) {
+        // Points of the same center should be in the same cluster, points
+        // of different centers in different clusters.
+        for (idx, &cluster) in assignments.iter().enumerate() {
+            for (other_idx, &other_cluster) in assignments.iter().enumerate() {
+                assert_eq!(
+                    idx % n_centers == other_idx % n_centers,
+                    cluster == other_cluster
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cluster_euclidean() {
+        let centers = [[0., 0.], [10., 10.], [-10., 10.]];
+        let storage = blobs(&centers, 20, 1.);
+
+        let k_means = KMeans {
+            n_clusters: 3,
+            ..KMeans::default()
+        };
+        let clustering = storage
+            .cluster_using(k_means, &mut XorShiftRng::seed_from_u64(1))
+            .unwrap();
+
+        assert_eq!(clustering.assignments.len(), 60);
+        check_clusters(&clustering.assignments, 3);
+
+        // Centroids are close to the centers.
+        for (idx, center) in centers.iter().enumerate() {
+            let centroid = clustering.centroids.row(clustering.assignments[idx]);
+            assert!((centroid[0] - center[0]).abs() < 1.);
+            assert!((centroid[1] - center[1]).abs() < 1.);
+        }
+
+        assert_eq!(
+            clustering.assign(arr1(&[9., 11.]).view()),
+            clustering.assignments[1]
+        );
+    }
+
+    #[test]
+    fn cluster_spherical() {
+        // The clusters differ in direction, but not in length.
+        let centers = [[1., 0.], [0., 1.], [10., 0.1], [0.1, 10.]];
+        let storage = blobs(&centers, 10, 0.05);
+
+        let k_means = KMeans {
+            n_clusters: 2,
+            spherical: true,
+            ..KMeans::default()
+        };
+        let clustering = storage
+            .cluster_using(k_means, &mut XorShiftRng::seed_from_u64(1))
+            .unwrap();
+
+        for (idx, &cluster) in clustering.assignments.iter().enumerate() {
+            assert_eq!(cluster, clustering.assignments[idx % 2]);
+        }
+        assert_ne!(clustering.assignments[0], clustering.assignments[1]);
+
+        // Centroids are normalized.
+        for centroid in clustering.centroids.outer_iter() {
+            assert!((centroid.dot(&centroid) - 1.).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn cluster_most_frequent() {
+        let storage = blobs(&[[0., 0.], [10., 10.]], 10, 1.);
+
+        let k_means = KMeans {
+            n_clusters: 2,
+            n_embeddings: Some(6),
+            ..KMeans::default()
+        };
+        let clustering = storage
+            .cluster_using(k_means, &mut XorShiftRng::seed_from_u64(1))
+            .unwrap();
+        assert_eq!(clustering.assignments.len(), 6);
+        check_clusters(&clustering.assignments, 2);
+
+        let k_means = KMeans {
+            n_clusters: 7,
+            n_embeddings: Some(6),
+            ..KMeans::default()
+        };
+        assert!(storage.cluster(k_means).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+//! Transparent decompression of embedding files.
+//!
+//! Embeddings in the word2vec and text formats are often distributed
+//! in compressed form. This module provides the `Decompress` reader,
+//! which detects gzip, xz, and zstd compression using the magic bytes
+//! at the start of a stream and decompresses the stream on the fly.
+//! Uncompressed streams are passed through as-is.
+//!
+//! The text and word2vec readers in this crate use `Decompress`
+//! internally, so compressed files can be read directly:
+//!
+//! ```
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use rust2vec::compression::{Compression, Decompress};
+//!
+//! let reader = BufReader::new(File::open("testdata/similarity.txt").unwrap());
+//! let decompress = Decompress::new(reader).unwrap();
+//! assert_eq!(decompress.compression(), Compression::None);
+//! ```
+
+use std::io::{self, BufRead, BufReader, Read};
+
+use failure::Error;
+use flate2::bufread::MultiGzDecoder;
+use xz2::bufread::XzDecoder;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+const XZ_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression formats.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Zstd,
+    None,
+}
+
+impl Compression {
+    /// Detect the compression format of a stream.
+    ///
+    /// The format is detected using the magic bytes at the start of
+    /// the stream. The bytes are inspected through the buffer of the
+    /// reader, so no data is consumed.
+    pub fn detect<R>(read: &mut R) -> Result<Self, Error>
+    where
+        R: BufRead,
+    {
+        let buf = read.fill_buf()?;
+
+        let compression = if buf.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else if buf.starts_with(&XZ_MAGIC) {
+            Compression::Xz
+        } else if buf.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        };
+
+        Ok(compression)
+    }
+}
+
+enum DecompressInner<R>
+where
+    R: BufRead,
+{
+    Gzip(BufReader<MultiGzDecoder<R>>),
+    Xz(BufReader<XzDecoder<R>>),
+    Zstd(BufReader<zstd::stream::read::Decoder<R>>),
+    None(R),
+}
+
+/// Decompressing reader.
+///
+/// This reader wraps a buffered reader and decompresses its data
+/// if it is compressed with gzip, xz, or zstd.
+pub struct Decompress<R>
+where
+    R: BufRead,
+{
+    inner: DecompressInner<R>,
+}
+
+impl<R> Decompress<R>
+where
+    R: BufRead,
+{
+    /// Construct a decompressing reader.
+    ///
+    /// The compression format is detected using `Compression::detect`.
+    pub fn new(mut read: R) -> Result<Self, Error> {
+        let inner = match Compression::detect(&mut read)? {
+            Compression::Gzip => DecompressInner::Gzip(BufReader::new(MultiGzDecoder::new(read))),
+            Compression::Xz => {
+                DecompressInner::Xz(BufReader::new(XzDecoder::new_multi_decoder(read)))
+            }
+            Compression::Zstd => DecompressInner::Zstd(BufReader::new(
+                zstd::stream::read::Decoder::with_buffer(read)?,
+            )),
+            Compression::None => DecompressInner::None(read),
+        };
+
+        Ok(Decompress { inner })
+    }
+
+    /// Get the compression format of the wrapped reader.
+    pub fn compression(&self) -> Compression {
+        match self.inner {
+            DecompressInner::Gzip(_) => Compression::Gzip,
+            DecompressInner::Xz(_) => Compression::Xz,
+            DecompressInner::Zstd(_) => Compression::Zstd,
+            DecompressInner::None(_) => Compression::None,
+        }
+    }
+}
+
+impl<R> Read for Decompress<R>
+where
+    R: BufRead,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            DecompressInner::Gzip(inner) => inner.read(buf),
+            DecompressInner::Xz(inner) => inner.read(buf),
+            DecompressInner::Zstd(inner) => inner.read(buf),
+            DecompressInner::None(inner) => inner.read(buf),
+        }
+    }
+}
+
+impl<R> BufRead for Decompress<R>
+where
+    R: BufRead,
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match &mut self.inner {
+            DecompressInner::Gzip(inner) => inner.fill_buf(),
+            DecompressInner::Xz(inner) => inner.fill_buf(),
+            DecompressInner::Zstd(inner) => inner.fill_buf(),
+            DecompressInner::None(inner) => inner.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match &mut self.inner {
+            DecompressInner::Gzip(inner) => inner.consume(amt),
+            DecompressInner::Xz(inner) => inner.consume(amt),
+            DecompressInner::Zstd(inner) => inner.consume(amt),
+            DecompressInner::None(inner) => inner.consume(amt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufReader, Cursor, Read, Write};
+
+    use flate2::write::GzEncoder;
+    use xz2::write::XzEncoder;
+
+    use super::{Compression, Decompress};
+    use crate::embeddings::Embeddings;
+    use crate::storage::StorageView;
+    use crate::text::ReadText;
+    use crate::vocab::Vocab;
+    use crate::word2vec::ReadWord2Vec;
+
+    fn read_testdata(filename: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        File::open(filename)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        data
+    }
+
+    fn compress(data: &[u8], compression: Compression) -> Vec<u8> {
+        match compression {
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::Xz => {
+                let mut encoder = XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::Zstd => zstd::stream::encode_all(Cursor::new(data), 0).unwrap(),
+            Compression::None => data.to_owned(),
+        }
+    }
+
+    #[test]
+    fn detect_compression() {
+        let data = read_testdata("testdata/similarity.txt");
+        for &compression in &[
+            Compression::Gzip,
+            Compression::Xz,
+            Compression::Zstd,
+            Compression::None,
+        ] {
+            let mut cursor = Cursor::new(compress(&data, compression));
+            assert_eq!(Compression::detect(&mut cursor).unwrap(), compression);
+        }
+    }
+
+    #[test]
+    fn decompress_roundtrip() {
+        let data = read_testdata("testdata/similarity.bin");
+        for &compression in &[
+            Compression::Gzip,
+            Compression::Xz,
+            Compression::Zstd,
+            Compression::None,
+        ] {
+            let mut decompress =
+                Decompress::new(Cursor::new(compress(&data, compression))).unwrap();
+            assert_eq!(decompress.compression(), compression);
+
+            let mut decompressed = Vec::new();
+            decompress.read_to_end(&mut decompressed).unwrap();
+            assert_eq!(decompressed, data);
+        }
+    }
+
+    #[test]
+    fn read_compressed_text() {
+        let check_embeds = Embeddings::read_text(
+            &mut BufReader::new(File::open("testdata/similarity.nodims").unwrap()),
+            false,
+        )
+        .unwrap();
+
+        let data = read_testdata("testdata/similarity.nodims");
+        let embeds =
+            Embeddings::read_text(&mut Cursor::new(compress(&data, Compression::Gzip)), false)
+                .unwrap();
+
+        assert_eq!(embeds.vocab().words(), check_embeds.vocab().words());
+        assert_eq!(embeds.storage().view(), check_embeds.storage().view());
+    }
+
+    #[test]
+    fn read_compressed_word2vec() {
+        let check_embeds = Embeddings::read_word2vec_binary(
+            &mut BufReader::new(File::open("testdata/similarity.bin").unwrap()),
+            false,
+        )
+        .unwrap();
+
+        let data = read_testdata("testdata/similarity.bin");
+        let embeds = Embeddings::read_word2vec_binary(
+            &mut Cursor::new(compress(&data, Compression::Zstd)),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(embeds.vocab().words(), check_embeds.vocab().words());
+        assert_eq!(embeds.storage().view(), check_embeds.storage().view());
+    }
+}
@@ -0,0 +1,396 @@
+//! Counter-fitting of embeddings to linguistic constraints.
+//!
+//! Counter-fitting (Mrkšić et al., 2016) injects synonymy and antonymy
+//! constraints into embeddings. The embeddings are optimized with the
+//! following objective:
+//!
+//! * Antonym repel: antonyms should have a cosine distance of at least
+//!   *δ*.
+//! * Synonym attract: synonyms should have a cosine distance of at most
+//!   *γ*.
+//! * Vector space preservation: the distance between a word and a word
+//!   that was within radius *ρ* in the original space should not
+//!   increase.
+//!
+//! The objective is minimized using stochastic gradient descent.
+
+use std::collections::HashSet;
+
+use failure::{ensure, Error};
+use ndarray::{s, Array2, ArrayView2};
+use rand::seq::SliceRandom;
+use rand::{FromEntropy, Rng};
+use rand_xorshift::XorShiftRng;
+use rayon::prelude::*;
+use toml::Value;
+
+use crate::embeddings::Embeddings;
+use crate::storage::{NdArray, StorageView};
+use crate::util::l2_normalize;
+use crate::vocab::{Vocab, WordIndex};
+
+/// Counter-fitting hyperparameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CounterFitting {
+    /// Minimum cosine distance between antonyms (δ).
+    pub antonym_margin: f32,
+
+    /// Maximum cosine distance between synonyms (γ).
+    pub synonym_margin: f32,
+
+    /// Radius of the neighborhoods that are preserved (ρ).
+    pub preservation_radius: f32,
+
+    /// Weight of the antonym repel term.
+    pub antonym_weight: f32,
+
+    /// Weight of the synonym attract term.
+    pub synonym_weight: f32,
+
+    /// Weight of the vector space preservation term.
+    pub preservation_weight: f32,
+
+    /// The SGD learning rate.
+    pub learning_rate: f32,
+
+    /// The number of epochs.
+    pub epochs: usize,
+}
+
+impl Default for CounterFitting {
+    fn default() -> Self {
+        CounterFitting {
+            antonym_margin: 1.0,
+            synonym_margin: 0.0,
+            preservation_radius: 0.2,
+            antonym_weight: 1.0,
+            synonym_weight: 1.0,
+            preservation_weight: 1.0,
+            learning_rate: 0.1,
+            epochs: 20,
+        }
+    }
+}
+
+impl CounterFitting {
+    /// Get the hyperparameters as metadata.
+    pub fn to_metadata(&self) -> Value {
+        let mut table = toml::value::Table::new();
+        for &(key, value) in &[
+            ("antonym_margin", self.antonym_margin),
+            ("synonym_margin", self.synonym_margin),
+            ("preservation_radius", self.preservation_radius),
+            ("antonym_weight", self.antonym_weight),
+            ("synonym_weight", self.synonym_weight),
+            ("preservation_weight", self.preservation_weight),
+            ("learning_rate", self.learning_rate),
+        ] {
+            table.insert(key.to_owned(), Value::Float(value as f64));
+        }
+        table.insert("epochs".to_owned(), Value::Integer(self.epochs as i64));
+        Value::Table(table)
+    }
+}
+
+/// A constraint between two embeddings.
+#[derive(Clone, Copy, Debug)]
+enum Constraint {
+    /// Antonym repel.
+    Repel(usize, usize),
+
+    /// Synonym attract.
+    Attract(usize, usize),
+
+    /// Vector space preservation, with the original distance.
+    Preserve(usize, usize, f32),
+}
+
+/// Counter-fitting of embeddings.
+pub trait CounterFit<V> {
+    /// Counter-fit the embeddings to synonym and antonym pairs.
+    fn counter_fit(
+        &self,
+        synonyms: &[(&str, &str)],
+        antonyms: &[(&str, &str)],
+        counter_fitting: CounterFitting,
+    ) -> Result<Embeddings<V, NdArray>, Error> {
+        self.counter_fit_using(
+            synonyms,
+            antonyms,
+            counter_fitting,
+            &mut XorShiftRng::from_entropy(),
+        )
+    }
+
+    /// Counter-fit the embeddings using the provided RNG.
+    ///
+    /// The embeddings of known words are normalized, subword
+    /// embeddings are not changed. Pairs with a word that is not in the
+    /// vocabulary are ignored. The RNG is used to shuffle the
+    /// constraints in every epoch. The hyperparameters are stored in
+    /// the `counter_fitting` table of the metadata.
+    fn counter_fit_using<R>(
+        &self,
+        synonyms: &[(&str, &str)],
+        antonyms: &[(&str, &str)],
+        counter_fitting: CounterFitting,
+        rng: &mut R,
+    ) -> Result<Embeddings<V, NdArray>, Error>
+    where
+        R: Rng;
+}
+
+impl<V, S> CounterFit<V> for Embeddings<V, S>
+where
+    V: Clone + Vocab,
+    S: StorageView,
+{
+    fn counter_fit_using<R>(
+        &self,
+        synonyms: &[(&str, &str)],
+        antonyms: &[(&str, &str)],
+        counter_fitting: CounterFitting,
+        rng: &mut R,
+    ) -> Result<Embeddings<V, NdArray>, Error>
+    where
+        R: Rng,
+    {
+        ensure!(
+            counter_fitting.learning_rate > 0.,
+            "The learning rate should be positive"
+        );
+
+        let n_words = self.vocab().len();
+        let mut storage = self.storage().view().to_owned();
+        for embed in storage.outer_iter_mut().take(n_words) {
+            l2_normalize(embed);
+        }
+
+        let synonyms = pair_indices(self.vocab(), synonyms);
+        let antonyms = pair_indices(self.vocab(), antonyms);
+
+        let mut constraints = Vec::new();
+        constraints.extend(antonyms.iter().map(|&(u, w)| Constraint::Repel(u, w)));
+        constraints.extend(synonyms.iter().map(|&(u, w)| Constraint::Attract(u, w)));
+        if counter_fitting.preservation_weight != 0. {
+            let words = synonyms
+                .iter()
+                .chain(antonyms.iter())
+                .flat_map(|&(u, w)| vec![u, w])
+                .collect::<HashSet<_>>();
+            constraints.extend(preservation_constraints(
+                storage.slice(s![..n_words, ..]),
+                words,
+                counter_fitting.preservation_radius,
+            ));
+        }
+
+        for _ in 0..counter_fitting.epochs {
+            constraints.shuffle(rng);
+            for &constraint in &constraints {
+                sgd_step(&mut storage, constraint, &counter_fitting);
+            }
+
+            for embed in storage.outer_iter_mut().take(n_words) {
+                l2_normalize(embed);
+            }
+        }
+
+        let mut metadata = self.metadata().cloned().unwrap_or_default();
+        metadata.insert("counter_fitting", counter_fitting.to_metadata())?;
+
+        Ok(Embeddings::new(
+            Some(metadata),
+            self.vocab().clone(),
+            NdArray(storage),
+        ))
+    }
+}
+
+/// Perform a gradient step for a single constraint.
+///
+/// The embeddings are normalized, so the cosine distance is
+/// *1 - u · w*.
+fn sgd_step(storage: &mut Array2<f32>, constraint: Constraint, counter_fitting: &CounterFitting) {
+    let (u, w, scale) = match constraint {
+        Constraint::Repel(u, w) => {
+            let dist = 1. - storage.row(u).dot(&storage.row(w));
+            if dist >= counter_fitting.antonym_margin {
+                return;
+            }
+
+            (u, w, -counter_fitting.antonym_weight)
+        }
+        Constraint::Attract(u, w) => {
+            let dist = 1. - storage.row(u).dot(&storage.row(w));
+            if dist <= counter_fitting.synonym_margin {
+                return;
+            }
+
+            (u, w, counter_fitting.synonym_weight)
+        }
+        Constraint::Preserve(u, w, original_dist) => {
+            let dist = 1. - storage.row(u).dot(&storage.row(w));
+            if dist <= original_dist {
+                return;
+            }
+
+            (u, w, counter_fitting.preservation_weight)
+        }
+    };
+
+    // The gradient of the distance with respect to u is -w and vice
+    // versa. Attraction moves the embeddings towards each other,
+    // repulsion moves them apart.
+    let step = counter_fitting.learning_rate * scale;
+    let embed_u = storage.row(u).to_owned();
+    let embed_w = storage.row(w).to_owned();
+    storage.row_mut(u).scaled_add(step, &embed_w);
+    storage.row_mut(w).scaled_add(step, &embed_u);
+}
+
+/// Find the vector space preservation constraints.
+///
+/// For each of the given words, all words that are within the given
+/// cosine distance are found.
+fn preservation_constraints(
+    embeddings: ArrayView2<f32>,
+    words: HashSet<usize>,
+    radius: f32,
+) -> Vec<Constraint> {
+    words
+        .into_par_iter()
+        .flat_map(|idx| {
+            let sims = embeddings.dot(&embeddings.row(idx));
+            sims.indexed_iter()
+                .filter(|&(other, &sim)| other != idx && 1. - sim < radius)
+                .map(|(other, &sim)| Constraint::Preserve(idx, other, 1. - sim))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Convert word pairs to pairs of word indices.
+fn pair_indices(vocab: &impl Vocab, pairs: &[(&str, &str)]) -> Vec<(usize, usize)> {
+    let word_idx = |word: &str| match vocab.idx(word) {
+        Some(WordIndex::Word(idx)) => Some(idx),
+        _ => None,
+    };
+
+    pairs
+        .iter()
+        .filter_map(|&(u, w)| Some((word_idx(u)?, word_idx(w)?)))
+        .filter(|&(u, w)| u != w)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{arr2, Array2, ArrayView1};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use super::{CounterFit, CounterFitting};
+    use crate::embeddings::Embeddings;
+    use crate::storage::{NdArray, StorageView};
+    use crate::test_util::embeddings_from_rows;
+    use crate::util::l2_normalize;
+    use crate::vocab::SimpleVocab;
+
+    fn test_embeddings() -> Embeddings<SimpleVocab, NdArray> {
+        let mut storage: Array2<f32> = arr2(&[
+            [1., 0.2, 0.],
+            [0.9, 0.4, 0.],
+            [0.2, 1., 0.],
+            [0.6, 0.1, 0.6],
+            [0., 0.1, 1.],
+            [0.1, 0., 1.],
+        ]);
+        for embed in storage.outer_iter_mut() {
+            l2_normalize(embed);
+        }
+
+        embeddings_from_rows(
+            &[
+                "cheap",
+                "expensive",
+                "inexpensive",
+                "pricey",
+                "table",
+                "desk",
+            ],
+            storage,
+        )
+    }
+
+    fn cosine(embeddings: &Embeddings<SimpleVocab, NdArray>, u: usize, w: usize) -> f32 {
+        let view = embeddings.storage().view();
+        let norm = |v: ArrayView1<f32>| v.dot(&v).sqrt();
+        view.row(u).dot(&view.row(w)) / (norm(view.row(u)) * norm(view.row(w)))
+    }
+
+    #[test]
+    fn counter_fit_constraints() {
+        let embeddings = test_embeddings();
+        let synonyms = [("cheap", "inexpensive"), ("expensive", "pricey")];
+        let antonyms = [("cheap", "expensive"), ("inexpensive", "unknown")];
+
+        let counter_fitted = embeddings
+            .counter_fit_using(
+                &synonyms,
+                &antonyms,
+                CounterFitting::default(),
+                &mut XorShiftRng::seed_from_u64(42),
+            )
+            .unwrap();
+
+        // Antonyms are moved apart, synonyms are moved together.
+        assert!(cosine(&counter_fitted, 0, 1) < cosine(&embeddings, 0, 1));
+        assert!(cosine(&counter_fitted, 0, 2) > cosine(&embeddings, 0, 2));
+        assert!(cosine(&counter_fitted, 1, 3) > cosine(&embeddings, 1, 3));
+
+        // Unconstrained words are not changed.
+        let diff = &counter_fitted.storage().view().row(4) - &embeddings.storage().view().row(4);
+        assert!(diff.iter().all(|v| v.abs() < 1e-6));
+
+        // Embeddings are normalized.
+        for embed in counter_fitted.storage().view().outer_iter() {
+            assert!((embed.dot(&embed) - 1.).abs() < 1e-5);
+        }
+
+        let metadata = counter_fitted.metadata().unwrap();
+        assert_eq!(
+            metadata.0["counter_fitting"]["epochs"].as_integer(),
+            Some(20)
+        );
+    }
+
+    #[test]
+    fn counter_fit_preserves_neighborhood() {
+        let embeddings = test_embeddings();
+        let antonyms = [("table", "expensive")];
+
+        // "desk" is a neighbor of "table" and is pulled along.
+        let counter_fitted = embeddings
+            .counter_fit_using(
+                &[],
+                &antonyms,
+                CounterFitting::default(),
+                &mut XorShiftRng::seed_from_u64(42),
+            )
+            .unwrap();
+        assert!(cosine(&counter_fitted, 4, 5) > 0.9);
+        assert_ne!(
+            counter_fitted.storage().view().row(5),
+            embeddings.storage().view().row(5)
+        );
+
+        let counter_fitting = CounterFitting {
+            learning_rate: 0.,
+            ..CounterFitting::default()
+        };
+        assert!(embeddings
+            .counter_fit(&[], &antonyms, counter_fitting)
+            .is_err());
+    }
+}
@@ -14,6 +14,7 @@ use crate::io::{
     MmapEmbeddings, ReadEmbeddings, WriteEmbeddings,
 };
 use crate::metadata::Metadata;
+use crate::postprocess::{post_processing_from_metadata, PostProcessing};
 use crate::storage::{
     CowArray, CowArray1, MmapArray, NdArray, QuantizedArray, Storage, StorageViewWrap, StorageWrap,
 };
@@ -25,23 +26,57 @@ use crate::vocab::{SimpleVocab, SubwordVocab, Vocab, VocabWrap, WordIndex};
 /// This data structure stores word embeddings (also known as *word vectors*)
 /// and provides some useful methods on the embeddings, such as similarity
 /// and analogy queries.
+///
+/// If the metadata contains a post-processing transform (see
+/// `PostProcess`), the transform is applied to embeddings that are
+/// composed from subword units.
 #[derive(Debug)]
 pub struct Embeddings<V, S> {
     metadata: Option<Metadata>,
+    post_processing: Option<PostProcessing>,
     storage: S,
     vocab: V,
 }
 
 impl<V, S> Embeddings<V, S> {
     /// Construct an embeddings from a vocabulary and storage.
+    ///
+    /// The metadata is not validated, a post-processing transform that
+    /// cannot be read from the metadata is ignored. Use `try_new` to
+    /// construct embeddings with metadata from an untrusted source.
     pub fn new(metadata: Option<Metadata>, vocab: V, storage: S) -> Self {
+        let post_processing = post_processing_from_metadata(metadata.as_ref()).unwrap_or(None);
+
         Embeddings {
             metadata,
+            post_processing,
             vocab,
             storage,
         }
     }
 
+    /// Construct an embeddings from a vocabulary and storage.
+    ///
+    /// Returns an error if the metadata contains an invalid
+    /// post-processing transform or a transform of which the
+    /// dimensionality differs from the storage.
+    pub fn try_new(metadata: Option<Metadata>, vocab: V, storage: S) -> Result<Self, Error>
+    where
+        S: Storage,
+    {
+        let post_processing = post_processing_from_metadata(metadata.as_ref())?;
+        if let Some(ref post_processing) = post_processing {
+            post_processing.check_dims(storage.shape().1)?;
+        }
+
+        Ok(Embeddings {
+            metadata,
+            post_processing,
+            vocab,
+            storage,
+        })
+    }
+
     /// Decompose embeddings in its vocabulary and storage.
     pub fn into_parts(self) -> (Option<Metadata>, V, S) {
         (self.metadata, self.vocab, self.storage)
@@ -59,17 +94,32 @@ impl<V, S> Embeddings<V, S> {
 
     /// Set metadata.
     ///
-    /// Returns the previously-stored metadata.
+    /// Returns the previously-stored metadata. The post-processing
+    /// transform is updated from the new metadata. As in `new`, the
+    /// metadata is not validated, use `try_new` to validate metadata.
     pub fn set_metadata(&mut self, mut metadata: Option<Metadata>) -> Option<Metadata> {
+        self.post_processing = post_processing_from_metadata(metadata.as_ref()).unwrap_or(None);
         mem::swap(&mut self.metadata, &mut metadata);
         metadata
     }
 
+    /// Get the post-processing transform.
+    ///
+    /// Returns `None` if the embeddings were not post-processed.
+    pub fn post_processing(&self) -> Option<&PostProcessing> {
+        self.post_processing.as_ref()
+    }
+
     /// Get the embedding storage.
     pub fn storage(&self) -> &S {
         &self.storage
     }
 
+    /// Get the embedding storage mutably.
+    pub(crate) fn storage_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
     /// Get the vocabulary.
     pub fn vocab(&self) -> &V {
         &self.vocab
@@ -99,6 +149,10 @@ where
 
                 l2_normalize(embed.view_mut());
 
+                if let Some(ref post_processing) = self.post_processing {
+                    post_processing.apply(embed.view_mut());
+                }
+
                 Some(CowArray::Owned(embed))
             }
         }
@@ -161,7 +215,7 @@ impl<V, S> MmapEmbeddings for Embeddings<V, S>
 where
     Self: Sized,
     V: ReadChunk,
-    S: MmapChunk,
+    S: MmapChunk + Storage,
 {
     fn mmap_embeddings(read: &mut BufReader<File>) -> Result<Self, Error> {
         let header = Header::read_chunk(read)?;
@@ -177,18 +231,14 @@ where
         let vocab = V::read_chunk(read)?;
         let storage = S::mmap_chunk(read)?;
 
-        Ok(Embeddings {
-            metadata,
-            vocab,
-            storage,
-        })
+        Self::try_new(metadata, vocab, storage)
     }
 }
 
 impl<V, S> ReadEmbeddings for Embeddings<V, S>
 where
     V: ReadChunk,
-    S: ReadChunk,
+    S: ReadChunk + Storage,
 {
     fn read_embeddings<R>(read: &mut R) -> Result<Self, Error>
     where
@@ -207,11 +257,7 @@ where
         let vocab = V::read_chunk(read)?;
         let storage = S::read_chunk(read)?;
 
-        Ok(Embeddings {
-            metadata,
-            vocab,
-            storage,
-        })
+        Self::try_new(metadata, vocab, storage)
     }
 }
 
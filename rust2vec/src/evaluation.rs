@@ -0,0 +1,675 @@
+//! Intrinsic evaluation of embeddings.
+//!
+//! This module evaluates embeddings on word similarity benchmarks, such
+//! as WordSim-353, SimLex-999, and MEN. Such benchmarks consist of word
+//! pairs with human similarity judgements. The embeddings are evaluated
+//! by correlating the cosine similarities of the word pairs with the
+//! human judgements.
+//!
+//! The module also implements outlier detection following the protocol
+//! of the 8-8-8 dataset (Camacho-Collados & Navigli, 2016). Each set in
+//! this dataset consists of a cluster of related words and a number of
+//! outliers. An outlier is detected when removing it from the cluster
+//! results in a more compact cluster than removing any of the cluster
+//! words.
+
+use std::cmp::Ordering;
+use std::io::BufRead;
+
+use failure::{format_err, Error};
+use ndarray::{Array2, ArrayView1, Axis};
+
+use crate::embeddings::Embeddings;
+use crate::storage::Storage;
+use crate::util::l2_normalize;
+use crate::vocab::Vocab;
+
+/// A word pair with a gold-standard similarity score.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimilarityInstance {
+    pub word1: String,
+    pub word2: String,
+    pub score: f32,
+}
+
+/// Read word similarity instances.
+///
+/// Every line should contain two words and a score. Columns are
+/// separated by tabs if the line contains a tab, otherwise by commas if
+/// the line contains a comma, and by whitespace otherwise.
+///
+/// `score_column` is the zero-based column of the score. If it is
+/// `None`, the score column is the first numeric column after the two
+/// words in the first instance. This reads both the common three-column
+/// layout and layouts with additional columns before the score, such as
+/// SimLex-999. Other columns are ignored.
+///
+/// When the score column is detected, leading lines without a score,
+/// such as headers, are skipped. With an explicit score column, every
+/// line should have a score in that column. Empty lines and lines
+/// starting with `#` are always skipped.
+pub fn read_similarity_instances(
+    reader: impl BufRead,
+    score_column: Option<usize>,
+) -> Result<Vec<SimilarityInstance>, Error> {
+    let mut instances = Vec::new();
+    let mut score_column = score_column;
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields = split_columns(line);
+
+        let column = match score_column {
+            Some(column) => column,
+            None => match (2..fields.len()).find(|&column| fields[column].parse::<f32>().is_ok()) {
+                Some(column) => column,
+                // Header or other leading line without a score.
+                None if instances.is_empty() => continue,
+                None => {
+                    return Err(format_err!(
+                        "Line {} does not contain a score: {}",
+                        idx + 1,
+                        line
+                    ))
+                }
+            },
+        };
+
+        let score = match fields.get(column).map(|score| score.parse()) {
+            Some(Ok(score)) => score,
+            Some(Err(_)) => {
+                return Err(format_err!(
+                    "Cannot parse score on line {}: {}",
+                    idx + 1,
+                    fields[column]
+                ));
+            }
+            None => {
+                return Err(format_err!(
+                    "Line {} does not contain two words and a score: {}",
+                    idx + 1,
+                    line
+                ));
+            }
+        };
+
+        // Use the same layout for all instances.
+        score_column = Some(column);
+
+        instances.push(SimilarityInstance {
+            word1: fields[0].to_owned(),
+            word2: fields[1].to_owned(),
+            score,
+        });
+    }
+
+    Ok(instances)
+}
+
+/// Split a line of a word similarity file into columns.
+fn split_columns(line: &str) -> Vec<&str> {
+    if line.contains('\t') {
+        line.split('\t').map(str::trim).collect()
+    } else if line.contains(',') {
+        line.split(',').map(str::trim).collect()
+    } else {
+        line.split_whitespace().collect()
+    }
+}
+
+/// Results of a word similarity evaluation.
+#[derive(Clone, Debug)]
+pub struct SimilarityEvaluation {
+    /// The number of instances.
+    pub n_instances: usize,
+
+    /// The number of instances for which both words have an embedding.
+    pub n_covered: usize,
+
+    /// Pearson's correlation coefficient of the covered instances.
+    ///
+    /// `None` if the correlation is undefined, e.g. when fewer than
+    /// two instances are covered.
+    pub pearson: Option<f64>,
+
+    /// Spearman's rank correlation coefficient of the covered instances.
+    ///
+    /// `None` if the correlation is undefined, e.g. when fewer than
+    /// two instances are covered.
+    pub spearman: Option<f64>,
+}
+
+impl SimilarityEvaluation {
+    /// Get the fraction of instances for which both words have an
+    /// embedding.
+    pub fn coverage(&self) -> f64 {
+        if self.n_instances == 0 {
+            return 0.;
+        }
+
+        self.n_covered as f64 / self.n_instances as f64
+    }
+}
+
+/// Trait for word similarity evaluation.
+pub trait EvaluateSimilarity {
+    /// Evaluate the embeddings on word similarity instances.
+    ///
+    /// Instances where at least one of the words does not have an
+    /// embedding are not used to compute the correlations. Embeddings
+    /// of unknown words are computed from subword units when the
+    /// vocabulary supports them.
+    fn evaluate_similarity(&self, instances: &[SimilarityInstance]) -> SimilarityEvaluation;
+}
+
+impl<V, S> EvaluateSimilarity for Embeddings<V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    fn evaluate_similarity(&self, instances: &[SimilarityInstance]) -> SimilarityEvaluation {
+        let mut gold = Vec::with_capacity(instances.len());
+        let mut predicted = Vec::with_capacity(instances.len());
+
+        for instance in instances {
+            let embed1 = match self.embedding(&instance.word1) {
+                Some(embed) => embed,
+                None => continue,
+            };
+            let embed2 = match self.embedding(&instance.word2) {
+                Some(embed) => embed,
+                None => continue,
+            };
+
+            gold.push(instance.score as f64);
+            predicted.push(cosine(embed1.as_view(), embed2.as_view()) as f64);
+        }
+
+        SimilarityEvaluation {
+            n_instances: instances.len(),
+            n_covered: gold.len(),
+            pearson: pearson(&gold, &predicted),
+            spearman: spearman(&gold, &predicted),
+        }
+    }
+}
+
+/// An outlier detection set.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutlierSet {
+    /// Words that belong together.
+    pub cluster: Vec<String>,
+
+    /// Words that do not belong to the cluster.
+    pub outliers: Vec<String>,
+}
+
+/// Read an outlier detection set.
+///
+/// The cluster words and the outliers are listed one per line, the
+/// cluster and the outliers are separated by an empty line.
+pub fn read_outlier_set(reader: impl BufRead) -> Result<OutlierSet, Error> {
+    let mut cluster = Vec::new();
+    let mut outliers = Vec::new();
+
+    let mut lines = reader.lines();
+    for line in lines.by_ref() {
+        let line = line?;
+        let word = line.trim();
+
+        if word.is_empty() {
+            if !cluster.is_empty() {
+                break;
+            }
+
+            continue;
+        }
+
+        cluster.push(word.to_owned());
+    }
+
+    for line in lines {
+        let line = line?;
+        let word = line.trim();
+        if !word.is_empty() {
+            outliers.push(word.to_owned());
+        }
+    }
+
+    if cluster.is_empty() || outliers.is_empty() {
+        return Err(format_err!(
+            "Outlier set should contain cluster words and outliers"
+        ));
+    }
+
+    Ok(OutlierSet { cluster, outliers })
+}
+
+/// Results of an outlier detection evaluation.
+#[derive(Clone, Debug, Default)]
+pub struct OutlierEvaluation {
+    /// The number of evaluated outliers.
+    pub n_instances: usize,
+
+    /// The number of outliers that were not evaluated, because they
+    /// do not have an embedding.
+    pub n_skipped: usize,
+
+    /// The number of outliers that were detected.
+    pub n_detected: usize,
+
+    /// The sum of the outlier positions, normalized by the cluster
+    /// sizes.
+    pub outlier_position_sum: f64,
+}
+
+impl OutlierEvaluation {
+    /// Get the outlier detection accuracy.
+    pub fn accuracy(&self) -> Option<f64> {
+        if self.n_instances == 0 {
+            return None;
+        }
+
+        Some(self.n_detected as f64 / self.n_instances as f64)
+    }
+
+    /// Get the outlier position percentage (OPP).
+    ///
+    /// This is the average position of the outlier among the set
+    /// elements ordered by compactness, relative to the cluster size.
+    /// The outlier position percentage is 1 when all outliers are
+    /// detected.
+    pub fn outlier_position_percentage(&self) -> Option<f64> {
+        if self.n_instances == 0 {
+            return None;
+        }
+
+        Some(self.outlier_position_sum / self.n_instances as f64)
+    }
+
+    /// Add the results of another evaluation.
+    pub fn merge(&mut self, other: &OutlierEvaluation) {
+        self.n_instances += other.n_instances;
+        self.n_skipped += other.n_skipped;
+        self.n_detected += other.n_detected;
+        self.outlier_position_sum += other.outlier_position_sum;
+    }
+}
+
+/// Trait for outlier detection evaluation.
+pub trait EvaluateOutliers {
+    /// Evaluate the embeddings on outlier detection sets.
+    ///
+    /// Every outlier of a set is evaluated separately against the
+    /// cluster of the set. Cluster words without an embedding are
+    /// removed from the cluster, outliers without an embedding are
+    /// skipped.
+    fn evaluate_outliers(&self, sets: &[OutlierSet]) -> OutlierEvaluation;
+}
+
+impl<V, S> EvaluateOutliers for Embeddings<V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    fn evaluate_outliers(&self, sets: &[OutlierSet]) -> OutlierEvaluation {
+        let mut eval = OutlierEvaluation::default();
+
+        for set in sets {
+            let cluster = set
+                .cluster
+                .iter()
+                .filter_map(|word| self.embedding(word))
+                .collect::<Vec<_>>();
+
+            for outlier in &set.outliers {
+                let outlier = match self.embedding(outlier) {
+                    Some(embed) if !cluster.is_empty() => embed,
+                    _ => {
+                        eval.n_skipped += 1;
+                        continue;
+                    }
+                };
+
+                let mut embeds = Array2::zeros((cluster.len() + 1, self.dims()));
+                for (mut row, embed) in embeds
+                    .outer_iter_mut()
+                    .zip(cluster.iter().chain(Some(&outlier)))
+                {
+                    row.assign(&embed.as_view());
+                    l2_normalize(row);
+                }
+
+                let compactness = pseudo_inverted_compactness(embeds.dot(&embeds.t()));
+
+                // The outlier position is the number of set elements whose
+                // removal results in a less compact cluster.
+                let outlier_compactness = compactness[cluster.len()];
+                let position = compactness[..cluster.len()]
+                    .iter()
+                    .filter(|&&c| c < outlier_compactness)
+                    .count();
+
+                eval.n_instances += 1;
+                if position == cluster.len() {
+                    eval.n_detected += 1;
+                }
+                eval.outlier_position_sum += position as f64 / cluster.len() as f64;
+            }
+        }
+
+        eval
+    }
+}
+
+/// Compute the compactness of a set after removing each element.
+///
+/// `sims` is the matrix of pairwise similarities of the set elements.
+/// Returns for each element the average pairwise similarity of the
+/// other elements.
+fn pseudo_inverted_compactness(sims: Array2<f32>) -> Vec<f64> {
+    let n = sims.rows();
+    if n < 3 {
+        return vec![0.; n];
+    }
+
+    // Sum of the similarities of all ordered pairs of distinct elements.
+    let total = sims.iter().map(|&v| v as f64).sum::<f64>()
+        - sims.diag().iter().map(|&v| v as f64).sum::<f64>();
+
+    let n_pairs = ((n - 1) * (n - 2)) as f64;
+    sims.axis_iter(Axis(0))
+        .enumerate()
+        .map(|(idx, row)| {
+            let row_sum = row.iter().map(|&v| v as f64).sum::<f64>() - row[idx] as f64;
+            (total - 2. * row_sum) / n_pairs
+        })
+        .collect()
+}
+
+/// Compute Pearson's correlation coefficient.
+///
+/// Returns `None` if the slices have different lengths, contain fewer
+/// than two values, or if one of them has zero variance.
+pub fn pearson(x: &[f64], y: &[f64]) -> Option<f64> {
+    if x.len() != y.len() || x.len() < 2 {
+        return None;
+    }
+
+    let x_mean = mean(x);
+    let y_mean = mean(y);
+
+    let mut cov = 0.;
+    let mut x_var = 0.;
+    let mut y_var = 0.;
+    for (&xv, &yv) in x.iter().zip(y) {
+        cov += (xv - x_mean) * (yv - y_mean);
+        x_var += (xv - x_mean).powi(2);
+        y_var += (yv - y_mean).powi(2);
+    }
+
+    if x_var == 0. || y_var == 0. {
+        return None;
+    }
+
+    Some(cov / (x_var * y_var).sqrt())
+}
+
+/// Compute Spearman's rank correlation coefficient.
+///
+/// Tied values are assigned the average of their ranks. Returns `None`
+/// under the same conditions as `pearson`.
+pub fn spearman(x: &[f64], y: &[f64]) -> Option<f64> {
+    pearson(&ranks(x), &ranks(y))
+}
+
+fn cosine(u: ArrayView1<f32>, v: ArrayView1<f32>) -> f32 {
+    let norms = u.dot(&u).sqrt() * v.dot(&v).sqrt();
+    if norms == 0. {
+        return 0.;
+    }
+
+    u.dot(&v) / norms
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Get the (1-based) ranks of values, averaging the ranks of ties.
+fn ranks(values: &[f64]) -> Vec<f64> {
+    let mut order = (0..values.len()).collect::<Vec<_>>();
+    order.sort_by(|&i, &j| values[i].partial_cmp(&values[j]).unwrap_or(Ordering::Equal));
+
+    let mut ranks = vec![0.; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && values[order[end]] == values[order[start]] {
+            end += 1;
+        }
+
+        // Ranks start..end are tied, assign the average rank.
+        let rank = (start + end + 1) as f64 / 2.;
+        for &idx in &order[start..end] {
+            ranks[idx] = rank;
+        }
+
+        start = end;
+    }
+
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufReader, Cursor};
+
+    use ndarray::arr2;
+
+    use super::{
+        cosine, pearson, pseudo_inverted_compactness, ranks, read_outlier_set,
+        read_similarity_instances, spearman, EvaluateOutliers, EvaluateSimilarity, OutlierSet,
+        SimilarityInstance,
+    };
+    use crate::embeddings::Embeddings;
+    use crate::word2vec::ReadWord2Vec;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+
+    fn instance(word1: &str, word2: &str, score: f32) -> SimilarityInstance {
+        SimilarityInstance {
+            word1: word1.to_owned(),
+            word2: word2.to_owned(),
+            score,
+        }
+    }
+
+    #[test]
+    fn correlations() {
+        let x = [1., 2., 3., 4., 5.];
+        assert_close(pearson(&x, &[2., 4., 6., 8., 10.]).unwrap(), 1.);
+        assert_close(pearson(&x, &[5., 4., 3., 2., 1.]).unwrap(), -1.);
+        assert_close(pearson(&x, &[2., 1., 4., 3., 5.]).unwrap(), 0.8);
+
+        // Spearman only considers the order.
+        assert_close(spearman(&x, &[1., 8., 27., 64., 125.]).unwrap(), 1.);
+        assert_close(spearman(&x, &[2., 1., 4., 3., 5.]).unwrap(), 0.8);
+
+        assert_eq!(pearson(&x, &[1., 1., 1., 1., 1.]), None);
+        assert_eq!(pearson(&[1.], &[1.]), None);
+    }
+
+    #[test]
+    fn ranks_ties() {
+        assert_eq!(ranks(&[3., 1., 3., 2.]), vec![3.5, 1., 3.5, 2.]);
+    }
+
+    #[test]
+    fn read_instances() {
+        let data = "Word 1,Word 2,Human (mean)\n\
+                    tiger,cat,7.35\n\
+                    \n\
+                    # comment\n\
+                    book,paper,7.46\n";
+        let instances = read_similarity_instances(Cursor::new(data), None).unwrap();
+        assert_eq!(
+            instances,
+            vec![
+                instance("tiger", "cat", 7.35),
+                instance("book", "paper", 7.46)
+            ]
+        );
+
+        // Once the layout is known, lines without a score are errors.
+        let data = "tiger cat 7.35\n\
+                    book paper\n";
+        assert!(read_similarity_instances(Cursor::new(data), None).is_err());
+        let data = "tiger cat 7.35\n\
+                    book paper high\n";
+        assert!(read_similarity_instances(Cursor::new(data), None).is_err());
+    }
+
+    #[test]
+    fn read_instances_simlex() {
+        // SimLex-999 has a part-of-speech column before the score.
+        let data = "# SimLex-999\n\
+                    word1\tword2\tPOS\tSimLex999\tconc(w1)\n\
+                    old\tnew\tA\t1.58\t2.72\n\
+                    smart\tintelligent\tA\t9.2\t1.75\n";
+        let check = vec![
+            instance("old", "new", 1.58),
+            instance("smart", "intelligent", 9.2),
+        ];
+
+        let instances = read_similarity_instances(Cursor::new(data), None).unwrap();
+        assert_eq!(instances, check);
+
+        // Headers are only skipped when the score column is detected.
+        assert!(read_similarity_instances(Cursor::new(data), Some(3)).is_err());
+
+        let data = "old\tnew\tA\t1.58\t2.72\n\
+                    smart\tintelligent\tA\t9.2\t1.75\n";
+        let instances = read_similarity_instances(Cursor::new(data), Some(3)).unwrap();
+        assert_eq!(instances, check);
+
+        let instances = read_similarity_instances(Cursor::new(data), Some(4)).unwrap();
+        assert_eq!(
+            instances,
+            vec![
+                instance("old", "new", 2.72),
+                instance("smart", "intelligent", 1.75)
+            ]
+        );
+    }
+
+    #[test]
+    fn evaluate_similarity() {
+        let f = File::open("testdata/similarity.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_word2vec_binary(&mut reader, true).unwrap();
+
+        let pairs = [
+            ("Berlin", "Potsdam"),
+            ("Berlin", "Hamburg"),
+            ("Stuttgart", "Karlsruhe"),
+            ("Stuttgart", "Mannheim"),
+            ("Potsdam", "Leipzig"),
+        ];
+
+        // Use the cosine similarities as scores, so that the correlations
+        // should be perfect.
+        let mut instances = pairs
+            .iter()
+            .map(|&(w1, w2)| {
+                let sim = cosine(
+                    embeddings.embedding(w1).unwrap().as_view(),
+                    embeddings.embedding(w2).unwrap().as_view(),
+                );
+                instance(w1, w2, sim)
+            })
+            .collect::<Vec<_>>();
+        instances.push(instance("Berlin", "Foobar", 10.));
+
+        let eval = embeddings.evaluate_similarity(&instances);
+        assert_eq!(eval.n_instances, 6);
+        assert_eq!(eval.n_covered, 5);
+        assert_close(eval.coverage(), 5. / 6.);
+        assert_close(eval.pearson.unwrap(), 1.);
+        assert_close(eval.spearman.unwrap(), 1.);
+
+        // Reversing the scores should give a perfect negative correlation.
+        for instance in &mut instances {
+            instance.score = -instance.score;
+        }
+        let eval = embeddings.evaluate_similarity(&instances);
+        assert_close(eval.spearman.unwrap(), -1.);
+    }
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|&w| w.to_owned()).collect()
+    }
+
+    #[test]
+    fn compactness() {
+        let sims = arr2(&[
+            [1.0, 0.8, 0.6, 0.1],
+            [0.8, 1.0, 0.7, 0.2],
+            [0.6, 0.7, 1.0, 0.0],
+            [0.1, 0.2, 0.0, 1.0],
+        ]);
+        let compactness = pseudo_inverted_compactness(sims);
+
+        assert_close(compactness[0], (0.7 + 0.2 + 0.0) / 3.);
+        assert_close(compactness[3], (0.8 + 0.6 + 0.7) / 3.);
+    }
+
+    #[test]
+    fn read_outliers() {
+        let data = "Polen\nDänemark\n\nHannover\nBremen\n\n";
+        let set = read_outlier_set(Cursor::new(data)).unwrap();
+        assert_eq!(
+            set,
+            OutlierSet {
+                cluster: strings(&["Polen", "Dänemark"]),
+                outliers: strings(&["Hannover", "Bremen"]),
+            }
+        );
+
+        assert!(read_outlier_set(Cursor::new("Polen\nDänemark\n")).is_err());
+    }
+
+    #[test]
+    fn evaluate_outliers() {
+        let f = File::open("testdata/analogy.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_word2vec_binary(&mut reader, true).unwrap();
+
+        let set = OutlierSet {
+            cluster: strings(&[
+                "Polen",
+                "Dänemark",
+                "Schweden",
+                "Russland",
+                "Litauen",
+                "Foo",
+            ]),
+            outliers: strings(&["Hannover", "Bar"]),
+        };
+
+        let eval = embeddings.evaluate_outliers(&[set]);
+        assert_eq!(eval.n_instances, 1);
+        assert_eq!(eval.n_skipped, 1);
+        assert_eq!(eval.n_detected, 1);
+        assert_close(eval.accuracy().unwrap(), 1.);
+        assert_close(eval.outlier_position_percentage().unwrap(), 1.);
+    }
+}
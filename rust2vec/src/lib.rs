@@ -5,15 +5,39 @@
 //! format, which has several benefits over the word2vec and GloVe
 //! formats.
 
+pub mod align;
+
+pub mod bias;
+
+pub mod cluster;
+
+pub mod compression;
+
+pub mod counterfit;
+
 #[deprecated(note = "rust2vec is superseded by the finalfusion crate")]
 pub mod embeddings;
 
+pub mod evaluation;
+
 pub mod io;
 
+pub(crate) mod linalg;
+
 pub mod metadata;
 
+pub mod postprocess;
+
 pub mod prelude;
 
+pub mod projector;
+
+pub mod reduce;
+
+pub mod retrofit;
+
+pub mod sentence;
+
 pub mod similarity;
 
 pub mod storage;
@@ -22,11 +46,18 @@ pub(crate) mod subword;
 
 pub mod text;
 
+pub mod train;
+
 pub(crate) mod util;
 
 pub mod vocab;
 
+pub mod wmd;
+
 pub mod word2vec;
 
 #[cfg(test)]
 mod tests;
+
+#[cfg(test)]
+pub(crate) mod test_util;
@@ -0,0 +1,337 @@
+//! Dense linear algebra routines.
+//!
+//! This module implements the decompositions that are needed for
+//! transforming embedding spaces, such that we do not have to
+//! depend on a LAPACK implementation. The routines use Jacobi rotations,
+//! which are simple and accurate, but only efficient for matrices with
+//! a moderate number of columns, such as covariance matrices of
+//! embeddings.
+
+use std::cmp::Ordering;
+
+use ndarray::{s, stack, Array1, Array2, ArrayView1, ArrayView2, Axis};
+
+/// Maximum number of Jacobi sweeps.
+const MAX_SWEEPS: usize = 100;
+
+/// Relative tolerance used to determine convergence.
+const EPSILON: f64 = 1e-12;
+
+/// Number of rows that are converted to double precision at once when
+/// computing *XᵀX*.
+const GRAM_BLOCK_SIZE: usize = 1024;
+
+/// Compute the eigendecomposition of a symmetric matrix.
+///
+/// Returns the eigenvalues in descending order and a matrix with the
+/// corresponding eigenvectors as its columns.
+pub fn symmetric_eigen(a: ArrayView2<f64>) -> (Array1<f64>, Array2<f64>) {
+    assert_eq!(a.rows(), a.cols(), "Matrix is not square");
+
+    let n = a.rows();
+    let mut a = a.to_owned();
+    let mut v = Array2::eye(n);
+
+    let norm = a.iter().map(|v| v * v).sum::<f64>();
+
+    for _ in 0..MAX_SWEEPS {
+        let off_diagonal = a
+            .indexed_iter()
+            .filter(|((row, col), _)| row != col)
+            .map(|(_, v)| v * v)
+            .sum::<f64>();
+        if off_diagonal <= EPSILON * EPSILON * norm {
+            break;
+        }
+
+        for p in 0..n {
+            for q in p + 1..n {
+                let apq = a[[p, q]];
+                if apq == 0. {
+                    continue;
+                }
+
+                let theta = (a[[q, q]] - a[[p, p]]) / (2. * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+                let c = 1. / (t * t + 1.).sqrt();
+                let s = t * c;
+
+                rotate_cols(&mut a, p, q, c, s);
+                rotate_rows(&mut a, p, q, c, s);
+                rotate_cols(&mut v, p, q, c, s);
+            }
+        }
+    }
+
+    let eigenvalues = a.diag().to_owned();
+    sort_descending(eigenvalues, v)
+}
+
+/// Compute the principal directions of the rows of a matrix.
+///
+/// Returns the `n` largest eigenvalues of *XᵀX* and the corresponding
+/// eigenvectors as the rows of a matrix. The rows of *X* are not
+/// centered, center them first or use `centered_principal_directions`
+/// to obtain the principal components of the data.
+pub fn principal_directions(x: ArrayView2<f32>, n: usize) -> (Array1<f64>, Array2<f32>) {
+    eigen_directions(gram_matrix(x, None), n)
+}
+
+/// Compute the principal directions of the centered rows of a matrix.
+///
+/// This function is equivalent to `principal_directions` on the rows
+/// of *X* minus `mean`, without constructing the centered matrix.
+pub fn centered_principal_directions(
+    x: ArrayView2<f32>,
+    mean: ArrayView1<f32>,
+    n: usize,
+) -> (Array1<f64>, Array2<f32>) {
+    eigen_directions(gram_matrix(x, Some(mean)), n)
+}
+
+/// Compute *XᵀX* in double precision, optionally centering the rows.
+///
+/// The rows are processed in blocks, so that only a block of *X* is
+/// converted to double precision at a time.
+fn gram_matrix(x: ArrayView2<f32>, mean: Option<ArrayView1<f32>>) -> Array2<f64> {
+    let mut gram = Array2::zeros((x.cols(), x.cols()));
+    for start in (0..x.rows()).step_by(GRAM_BLOCK_SIZE) {
+        let end = (start + GRAM_BLOCK_SIZE).min(x.rows());
+        let mut block = x.slice(s![start..end, ..]).mapv(f64::from);
+        if let Some(mean) = mean {
+            block -= &mean.mapv(f64::from);
+        }
+        gram += &block.t().dot(&block);
+    }
+
+    gram
+}
+
+/// Get the `n` largest eigenvalues and the corresponding eigenvectors
+/// (as rows) of a symmetric matrix.
+fn eigen_directions(a: Array2<f64>, n: usize) -> (Array1<f64>, Array2<f32>) {
+    let (values, vectors) = symmetric_eigen(a.view());
+
+    let n = n.min(values.len());
+    (
+        values.slice(s![..n]).to_owned(),
+        vectors.slice(s![.., ..n]).t().mapv(|v| v as f32),
+    )
+}
+
+/// Compute the singular value decomposition of a matrix.
+///
+/// The matrix should have at least as many rows as columns. Returns
+/// *U*, the singular values in descending order, and *V*, such that
+/// *A = U diag(s) Vᵀ*. *U* and *V* have orthonormal columns, even when
+/// *A* is rank-deficient.
+pub fn svd(a: ArrayView2<f64>) -> (Array2<f64>, Array1<f64>, Array2<f64>) {
+    assert!(
+        a.rows() >= a.cols(),
+        "Matrix should have at least as many rows as columns"
+    );
+
+    let n = a.cols();
+    let mut u = a.to_owned();
+    let mut v = Array2::eye(n);
+
+    // Columns with a smaller squared norm are considered to be zero.
+    let zero_norm = EPSILON * EPSILON * u.iter().map(|v| v * v).sum::<f64>();
+
+    // One-sided Jacobi: orthogonalize the columns of A.
+    for _ in 0..MAX_SWEEPS {
+        let mut converged = true;
+
+        for p in 0..n {
+            for q in p + 1..n {
+                let alpha = u.column(p).dot(&u.column(p));
+                let beta = u.column(q).dot(&u.column(q));
+                let gamma = u.column(p).dot(&u.column(q));
+
+                if alpha <= zero_norm
+                    || beta <= zero_norm
+                    || gamma.abs() <= EPSILON * (alpha * beta).sqrt()
+                {
+                    continue;
+                }
+                converged = false;
+
+                let zeta = (beta - alpha) / (2. * gamma);
+                let t = zeta.signum() / (zeta.abs() + (zeta * zeta + 1.).sqrt());
+                let c = 1. / (t * t + 1.).sqrt();
+                let s = t * c;
+
+                rotate_cols(&mut u, p, q, c, s);
+                rotate_cols(&mut v, p, q, c, s);
+            }
+        }
+
+        if converged {
+            break;
+        }
+    }
+
+    let singular_values = u.map_axis(Axis(0), |col| col.dot(&col).sqrt());
+
+    // Sort the columns of U and V by their singular values.
+    let uv = stack(Axis(0), &[u.view(), v.view()]).expect("Cannot stack U and V");
+    let (singular_values, uv) = sort_descending(singular_values, uv);
+    let (u, v) = uv.view().split_at(Axis(0), a.rows());
+    let (mut u, v) = (u.to_owned(), v.to_owned());
+
+    let tolerance = EPSILON * singular_values.get(0).cloned().unwrap_or(0.) * n as f64;
+    let mut rank = 0;
+    for (mut col, &sv) in u.gencolumns_mut().into_iter().zip(&singular_values) {
+        if sv > tolerance {
+            col /= sv;
+            rank += 1;
+        }
+    }
+
+    complete_orthonormal(&mut u, rank);
+
+    (u, singular_values, v)
+}
+
+/// Replace the columns `rank..` of `m` by orthonormal vectors.
+///
+/// The first `rank` columns must be orthonormal. The remaining columns
+/// are completed using Gram-Schmidt orthogonalization of the standard
+/// basis vectors.
+fn complete_orthonormal(m: &mut Array2<f64>, rank: usize) {
+    let mut col = rank;
+    for basis in 0..m.rows() {
+        if col == m.cols() {
+            break;
+        }
+
+        let mut candidate = Array1::zeros(m.rows());
+        candidate[basis] = 1.;
+        for prev in 0..col {
+            let prev = m.column(prev);
+            let proj = prev.dot(&candidate);
+            candidate.scaled_add(-proj, &prev);
+        }
+
+        let norm = candidate.dot(&candidate).sqrt();
+        if norm > 1e-6 {
+            candidate /= norm;
+            m.column_mut(col).assign(&candidate);
+            col += 1;
+        }
+    }
+}
+
+/// Apply the Jacobi rotation *(c, s)* to columns `p` and `q`.
+fn rotate_cols(m: &mut Array2<f64>, p: usize, q: usize, c: f64, s: f64) {
+    for mut row in m.genrows_mut() {
+        let (mp, mq) = (row[p], row[q]);
+        row[p] = c * mp - s * mq;
+        row[q] = s * mp + c * mq;
+    }
+}
+
+/// Apply the Jacobi rotation *(c, s)* to rows `p` and `q`.
+fn rotate_rows(m: &mut Array2<f64>, p: usize, q: usize, c: f64, s: f64) {
+    for mut col in m.gencolumns_mut() {
+        let (mp, mq) = (col[p], col[q]);
+        col[p] = c * mp - s * mq;
+        col[q] = s * mp + c * mq;
+    }
+}
+
+/// Sort values in descending order, permuting the columns of `m`
+/// accordingly.
+fn sort_descending(values: Array1<f64>, m: Array2<f64>) -> (Array1<f64>, Array2<f64>) {
+    let mut order = (0..values.len()).collect::<Vec<_>>();
+    order.sort_by(|&i, &j| values[j].partial_cmp(&values[i]).unwrap_or(Ordering::Equal));
+
+    (values.select(Axis(0), &order), m.select(Axis(1), &order))
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{arr2, Array1, Array2, Axis};
+
+    use super::{
+        centered_principal_directions, principal_directions, svd, symmetric_eigen, GRAM_BLOCK_SIZE,
+    };
+
+    fn assert_close(a: &Array2<f64>, b: &Array2<f64>) {
+        assert_eq!(a.shape(), b.shape());
+        for (&va, &vb) in a.iter().zip(b.iter()) {
+            assert!((va - vb).abs() < 1e-8, "{} != {}", va, vb);
+        }
+    }
+
+    fn diag(values: &Array1<f64>) -> Array2<f64> {
+        let mut m = Array2::zeros((values.len(), values.len()));
+        m.diag_mut().assign(values);
+        m
+    }
+
+    #[test]
+    fn symmetric_eigen_reconstructs() {
+        let a = arr2(&[
+            [4., 1., -2., 2.],
+            [1., 2., 0., 1.],
+            [-2., 0., 3., -2.],
+            [2., 1., -2., -1.],
+        ]);
+
+        let (values, vectors) = symmetric_eigen(a.view());
+
+        assert!(values.windows(2).into_iter().all(|w| w[0] >= w[1]));
+        assert_close(&vectors.t().dot(&vectors), &Array2::eye(4));
+        assert_close(&vectors.dot(&diag(&values)).dot(&vectors.t()), &a);
+    }
+
+    #[test]
+    fn svd_reconstructs() {
+        let a = arr2(&[[1., 2., 3.], [4., 5., 6.], [7., 8., 10.], [-1., 0., 1.]]);
+
+        let (u, s, v) = svd(a.view());
+
+        assert!(s.windows(2).into_iter().all(|w| w[0] >= w[1]));
+        assert_close(&u.t().dot(&u), &Array2::eye(3));
+        assert_close(&v.t().dot(&v), &Array2::eye(3));
+        assert_close(&u.dot(&diag(&s)).dot(&v.t()), &a);
+    }
+
+    #[test]
+    fn svd_rank_deficient() {
+        let a = arr2(&[[1., 2., 3.], [2., 4., 6.], [1., 1., 1.]]);
+
+        let (u, s, v) = svd(a.view());
+
+        assert!(s[2].abs() < 1e-8);
+        assert_close(&u.t().dot(&u), &Array2::eye(3));
+        assert_close(&v.t().dot(&v), &Array2::eye(3));
+        assert_close(&u.dot(&diag(&s)).dot(&v.t()), &a);
+    }
+
+    #[test]
+    fn principal_directions_blockwise() {
+        // Use enough rows to span multiple blocks.
+        let x = Array2::from_shape_fn((2 * GRAM_BLOCK_SIZE + 10, 3), |(row, col)| {
+            ((row * (col + 1)) % 7) as f32 + col as f32
+        });
+        let mean = x.mean_axis(Axis(0));
+
+        let (values, directions) = principal_directions((&x - &mean).view(), 2);
+        let (centered_values, centered_directions) =
+            centered_principal_directions(x.view(), mean.view(), 2);
+
+        let gram = (&x - &mean).mapv(f64::from);
+        let gram = gram.t().dot(&gram);
+        let (check_values, _) = symmetric_eigen(gram.view());
+
+        for ((&v, &cv), &check) in values.iter().zip(&centered_values).zip(&check_values) {
+            assert!((v - check).abs() < 1e-6 * check);
+            assert!((cv - check).abs() < 1e-6 * check);
+        }
+        for (&d, &cd) in directions.iter().zip(centered_directions.iter()) {
+            assert!((d - cd).abs() < 1e-4);
+        }
+    }
+}
@@ -1,9 +1,10 @@
 //! Metadata
 
+use std::collections::HashMap;
 use std::io::{Read, Seek, Write};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use failure::{ensure, err_msg, Error};
+use failure::{ensure, err_msg, format_err, Error};
 use toml::Value;
 
 use crate::io::{
@@ -17,6 +18,58 @@ use crate::io::{
 #[derive(Clone, Debug, PartialEq)]
 pub struct Metadata(pub Value);
 
+impl Metadata {
+    /// Get the word frequencies.
+    ///
+    /// Word frequencies are stored in the `frequencies` table, which
+    /// maps words to their (integer) frequencies:
+    ///
+    /// ```toml
+    /// [frequencies]
+    /// the = 1061396
+    /// "New York" = 4120
+    /// ```
+    ///
+    /// Returns `None` if the metadata does not contain frequencies.
+    pub fn frequencies(&self) -> Result<Option<HashMap<&str, u64>>, Error> {
+        let table = match self.0.get("frequencies") {
+            Some(table) => table
+                .as_table()
+                .ok_or_else(|| err_msg("Frequencies are not a table"))?,
+            None => return Ok(None),
+        };
+
+        let mut frequencies = HashMap::with_capacity(table.len());
+        for (word, freq) in table {
+            let freq = freq
+                .as_integer()
+                .filter(|&freq| freq >= 0)
+                .ok_or_else(|| format_err!("Invalid frequency for word: {}", word))?;
+            frequencies.insert(word.as_str(), freq as u64);
+        }
+
+        Ok(Some(frequencies))
+    }
+
+    /// Insert a top-level entry.
+    ///
+    /// An existing entry with the same key is replaced and returned.
+    /// Fails when the metadata is not a table.
+    pub fn insert(&mut self, key: impl Into<String>, value: Value) -> Result<Option<Value>, Error> {
+        let table = self
+            .0
+            .as_table_mut()
+            .ok_or_else(|| err_msg("Metadata is not a table"))?;
+        Ok(table.insert(key.into(), value))
+    }
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Metadata(Value::Table(Default::default()))
+    }
+}
+
 impl ReadChunk for Metadata {
     fn read_chunk<R>(read: &mut R) -> Result<Self, Error>
     where
@@ -83,7 +136,7 @@ mod tests {
     use std::io::{Cursor, Read, Seek, SeekFrom};
 
     use byteorder::{LittleEndian, ReadBytesExt};
-    use toml::{toml, toml_internal};
+    use toml::{toml, toml_internal, Value};
 
     use super::Metadata;
     use crate::io::private::{ReadChunk, WriteChunk};
@@ -122,6 +175,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn metadata_frequencies() {
+        let metadata = Metadata(toml! {
+            [frequencies]
+            the = 10
+            "New York" = 2
+        });
+
+        let frequencies = metadata.frequencies().unwrap().unwrap();
+        assert_eq!(frequencies.len(), 2);
+        assert_eq!(frequencies["the"], 10);
+        assert_eq!(frequencies["New York"], 2);
+
+        assert!(test_metadata().frequencies().unwrap().is_none());
+    }
+
+    #[test]
+    fn metadata_insert() {
+        let mut metadata = Metadata::default();
+        assert!(metadata
+            .insert("description", test_metadata().0["description"].clone())
+            .unwrap()
+            .is_none());
+        assert!(metadata
+            .insert(
+                "hyperparameters",
+                test_metadata().0["hyperparameters"].clone()
+            )
+            .unwrap()
+            .is_none());
+        assert_eq!(metadata, test_metadata());
+
+        let mut metadata = Metadata(Value::Integer(42));
+        assert!(metadata.insert("dims", Value::Integer(300)).is_err());
+    }
+
     #[test]
     fn metadata_write_read_roundtrip() {
         let check_metadata = test_metadata();
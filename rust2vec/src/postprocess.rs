@@ -0,0 +1,405 @@
+//! Post-processing of embeddings.
+//!
+//! This module implements simple post-processing transforms that
+//! often improve the quality of embeddings (Mu & Viswanath, 2018):
+//!
+//! * Centering: subtracting the mean embedding.
+//! * Removal of the top *D* principal components, which mostly encode
+//!   word frequency.
+//! * Re-normalization of the embeddings to unit length.
+//!
+//! The transforms are applied in this order and in place. When
+//! embeddings are post-processed, the transform is stored in the
+//! `post_processing` table of the metadata. Embeddings of unknown
+//! words that are composed from subword units are computed from
+//! untransformed subword embeddings, so `Embeddings::embedding`
+//! applies the stored transform to such embeddings at query time.
+
+use failure::{ensure, err_msg, Error};
+use ndarray::{s, Array1, Array2, ArrayView2, ArrayViewMut1, ArrayViewMut2, Axis};
+use toml::Value;
+
+use crate::embeddings::Embeddings;
+use crate::linalg::{centered_principal_directions, principal_directions};
+use crate::metadata::Metadata;
+use crate::storage::NdArray;
+use crate::util::{l2_normalize, matrix_to_toml, toml_to_matrix, toml_to_vector, vector_to_toml};
+use crate::vocab::Vocab;
+
+/// Post-processing transform.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PostProcessing {
+    /// The mean embedding, which is subtracted when the embeddings are
+    /// centered.
+    pub mean: Option<Array1<f32>>,
+
+    /// The principal components that are removed, as rows of a matrix.
+    pub components: Array2<f32>,
+
+    /// Normalize embeddings to unit length.
+    pub normalize: bool,
+}
+
+impl PostProcessing {
+    /// Fit a post-processing transform to the rows of `data`.
+    ///
+    /// The transform centers the embeddings if `center` is true,
+    /// removes the top `n_components` principal components, and
+    /// normalizes the embeddings if `normalize` is true.
+    pub fn fit(
+        data: ArrayView2<f32>,
+        center: bool,
+        n_components: usize,
+        normalize: bool,
+    ) -> Result<Self, Error> {
+        ensure!(
+            n_components <= data.cols(),
+            "Cannot remove {} components from {}-dimensional embeddings",
+            n_components,
+            data.cols()
+        );
+        ensure!(
+            data.rows() > 0 || (!center && n_components == 0),
+            "Cannot fit post-processing without embeddings"
+        );
+
+        let mean = if center {
+            Some(data.mean_axis(Axis(0)))
+        } else {
+            None
+        };
+
+        let components = if n_components == 0 {
+            Array2::zeros((0, data.cols()))
+        } else {
+            match mean {
+                Some(ref mean) => centered_principal_directions(data, mean.view(), n_components).1,
+                None => principal_directions(data, n_components).1,
+            }
+        };
+
+        Ok(PostProcessing {
+            mean,
+            components,
+            normalize,
+        })
+    }
+
+    /// Apply the transform to an embedding.
+    pub fn apply(&self, mut embedding: ArrayViewMut1<f32>) {
+        if let Some(ref mean) = self.mean {
+            embedding -= mean;
+        }
+
+        for component in self.components.outer_iter() {
+            let projection = component.dot(&embedding);
+            embedding.scaled_add(-projection, &component);
+        }
+
+        if self.normalize {
+            l2_normalize(embedding);
+        }
+    }
+
+    /// Apply the transform to the rows of a matrix.
+    pub fn apply_matrix(&self, mut embeddings: ArrayViewMut2<f32>) {
+        if let Some(ref mean) = self.mean {
+            embeddings -= mean;
+        }
+
+        if self.components.rows() != 0 {
+            let projections = embeddings.dot(&self.components.t());
+            embeddings -= &projections.dot(&self.components);
+        }
+
+        if self.normalize {
+            for embedding in embeddings.outer_iter_mut() {
+                l2_normalize(embedding);
+            }
+        }
+    }
+
+    /// Check that the transform applies to `dims`-dimensional
+    /// embeddings.
+    pub(crate) fn check_dims(&self, dims: usize) -> Result<(), Error> {
+        if let Some(ref mean) = self.mean {
+            ensure!(
+                mean.len() == dims,
+                "Post-processing mean has {} dimensions, embeddings have {} dimensions",
+                mean.len(),
+                dims
+            );
+        }
+
+        ensure!(
+            self.components.rows() == 0 || self.components.cols() == dims,
+            "Post-processing components have {} dimensions, embeddings have {} dimensions",
+            self.components.cols(),
+            dims
+        );
+
+        Ok(())
+    }
+
+    /// Read a post-processing transform from metadata.
+    pub fn from_metadata(value: &Value) -> Result<Self, Error> {
+        let mean = value.get("mean").map(toml_to_vector).transpose()?;
+        let components = toml_to_matrix(
+            value
+                .get("components")
+                .ok_or_else(|| err_msg("Missing removed components"))?,
+        )?;
+        let normalize = value
+            .get("normalize")
+            .and_then(Value::as_bool)
+            .ok_or_else(|| err_msg("Missing or invalid normalization flag"))?;
+
+        if let Some(ref mean) = mean {
+            ensure!(
+                components.rows() == 0 || components.cols() == mean.len(),
+                "Components have {} dimensions, mean has {} dimensions",
+                components.cols(),
+                mean.len()
+            );
+        }
+
+        Ok(PostProcessing {
+            mean,
+            components,
+            normalize,
+        })
+    }
+
+    /// Get the post-processing transform as metadata.
+    pub fn to_metadata(&self) -> Value {
+        let mut table = toml::value::Table::new();
+        if let Some(ref mean) = self.mean {
+            table.insert("mean".to_owned(), vector_to_toml(mean.view()));
+        }
+        table.insert(
+            "components".to_owned(),
+            matrix_to_toml(self.components.view()),
+        );
+        table.insert("normalize".to_owned(), Value::Boolean(self.normalize));
+        Value::Table(table)
+    }
+}
+
+/// Read the post-processing transform from embeddings metadata.
+///
+/// Returns `None` if the embeddings were not post-processed.
+pub(crate) fn post_processing_from_metadata(
+    metadata: Option<&Metadata>,
+) -> Result<Option<PostProcessing>, Error> {
+    metadata
+        .and_then(|metadata| metadata.0.get("post_processing"))
+        .map(PostProcessing::from_metadata)
+        .transpose()
+}
+
+/// In-place post-processing of embeddings.
+pub trait PostProcess {
+    /// Post-process the embeddings in place.
+    ///
+    /// The embeddings are centered if `center` is true, the top
+    /// `n_components` principal components are removed, and the
+    /// embeddings are normalized if `normalize` is true. Returns the
+    /// transform, which can be applied to other embeddings.
+    fn post_process(
+        &mut self,
+        center: bool,
+        n_components: usize,
+        normalize: bool,
+    ) -> Result<PostProcessing, Error>;
+}
+
+impl PostProcess for NdArray {
+    fn post_process(
+        &mut self,
+        center: bool,
+        n_components: usize,
+        normalize: bool,
+    ) -> Result<PostProcessing, Error> {
+        let post_processing = PostProcessing::fit(self.0.view(), center, n_components, normalize)?;
+        post_processing.apply_matrix(self.0.view_mut());
+        Ok(post_processing)
+    }
+}
+
+/// Post-processing of embeddings.
+///
+/// Only the embeddings of known words are transformed, subword
+/// embeddings are left untouched. The transform is stored in the
+/// metadata, so that it is also applied to embeddings that are
+/// composed from subword units.
+impl<V> PostProcess for Embeddings<V, NdArray>
+where
+    V: Vocab,
+{
+    fn post_process(
+        &mut self,
+        center: bool,
+        n_components: usize,
+        normalize: bool,
+    ) -> Result<PostProcessing, Error> {
+        ensure!(
+            self.post_processing().is_none(),
+            "Embeddings are already post-processed"
+        );
+
+        let n_words = self.vocab().len();
+        let post_processing = PostProcessing::fit(
+            self.storage().0.slice(s![..n_words, ..]),
+            center,
+            n_components,
+            normalize,
+        )?;
+
+        let mut metadata = self.metadata().cloned().unwrap_or_default();
+        metadata.insert("post_processing", post_processing.to_metadata())?;
+
+        post_processing.apply_matrix(self.storage_mut().0.slice_mut(s![..n_words, ..]));
+        self.set_metadata(Some(metadata));
+
+        Ok(post_processing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    use ndarray::{Array1, Array2, Axis};
+
+    use super::{PostProcess, PostProcessing};
+    use crate::embeddings::Embeddings;
+    use crate::io::{ReadEmbeddings, WriteEmbeddings};
+    use crate::metadata::Metadata;
+    use crate::storage::NdArray;
+    use crate::test_util::scaled_random_matrix;
+    use crate::vocab::{SubwordVocab, Vocab};
+
+    fn test_matrix(rows: usize) -> Array2<f32> {
+        scaled_random_matrix(rows, &[8., 4., 2., 1.])
+    }
+
+    fn test_subword_embeddings() -> Embeddings<SubwordVocab, NdArray> {
+        let words = vec!["this".to_owned(), "is".to_owned(), "a".to_owned()];
+        let vocab = SubwordVocab::new(words, 3, 6, 5);
+        let rows = vocab.len() + 32;
+        Embeddings::new(None, vocab, NdArray(test_matrix(rows)))
+    }
+
+    #[test]
+    fn post_process_ndarray() {
+        let mut storage = NdArray(test_matrix(100));
+        let post_processing = storage.post_process(true, 1, false).unwrap();
+
+        // Centered and the dominant component is removed.
+        for &v in storage.0.mean_axis(Axis(0)).iter() {
+            assert!(v.abs() < 1e-4);
+        }
+        let projections = storage.0.dot(&post_processing.components.row(0));
+        assert!(projections.iter().all(|v| v.abs() < 1e-4));
+        assert!(post_processing.components[[0, 0]].abs() > 0.99);
+
+        let mut storage = NdArray(test_matrix(100));
+        storage.post_process(false, 0, true).unwrap();
+        for row in storage.0.outer_iter() {
+            assert!((row.dot(&row) - 1.).abs() < 1e-5);
+        }
+
+        assert!(storage.post_process(true, 5, true).is_err());
+    }
+
+    #[test]
+    fn apply_is_consistent() {
+        let data = test_matrix(100);
+        let post_processing = PostProcessing::fit(data.view(), true, 2, true).unwrap();
+
+        let mut transformed = data.clone();
+        post_processing.apply_matrix(transformed.view_mut());
+
+        for (row, check) in data.outer_iter().zip(transformed.outer_iter()) {
+            let mut row = row.to_owned();
+            post_processing.apply(row.view_mut());
+            assert!((&row - &check).iter().all(|v| v.abs() < 1e-5));
+        }
+    }
+
+    #[test]
+    fn post_process_embeddings() {
+        let mut embeddings = test_subword_embeddings();
+        let untransformed = embeddings.storage().0.clone();
+        let check_unknown = embeddings.embedding("these").unwrap().into_owned();
+
+        let post_processing = embeddings.post_process(true, 1, true).unwrap();
+        assert!(embeddings.post_process(true, 1, true).is_err());
+
+        // Only the embeddings of known words are transformed.
+        let n_words = embeddings.vocab().len();
+        let storage = &embeddings.storage().0;
+        for (idx, (row, check)) in storage
+            .outer_iter()
+            .zip(untransformed.outer_iter())
+            .enumerate()
+        {
+            if idx < n_words {
+                assert!((row.dot(&row) - 1.).abs() < 1e-5);
+            } else {
+                assert_eq!(row, check);
+            }
+        }
+
+        // Embeddings of unknown words are transformed at query time.
+        let mut check_unknown: Array1<f32> = check_unknown;
+        post_processing.apply(check_unknown.view_mut());
+        assert_eq!(
+            embeddings.embedding("these").unwrap().into_owned(),
+            check_unknown
+        );
+
+        assert_eq!(embeddings.post_processing(), Some(&post_processing));
+    }
+
+    #[test]
+    fn post_processing_roundtrip() {
+        let mut embeddings = test_subword_embeddings();
+        let post_processing = embeddings.post_process(true, 2, false).unwrap();
+
+        let mut cursor = Cursor::new(Vec::new());
+        embeddings.write_embeddings(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let restored: Embeddings<SubwordVocab, NdArray> =
+            Embeddings::read_embeddings(&mut cursor).unwrap();
+
+        assert_eq!(restored.post_processing(), Some(&post_processing));
+        assert_eq!(
+            restored.embedding("these").unwrap().into_owned(),
+            embeddings.embedding("these").unwrap().into_owned()
+        );
+    }
+
+    #[test]
+    fn post_processing_dims_are_checked() {
+        let mut embeddings = test_subword_embeddings();
+        let post_processing = PostProcessing {
+            mean: Some(Array1::zeros(3)),
+            components: Array2::zeros((0, 0)),
+            normalize: true,
+        };
+        let mut metadata = Metadata::default();
+        metadata
+            .insert("post_processing", post_processing.to_metadata())
+            .unwrap();
+        embeddings.set_metadata(Some(metadata.clone()));
+
+        let mut cursor = Cursor::new(Vec::new());
+        embeddings.write_embeddings(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        assert!(Embeddings::<SubwordVocab, NdArray>::read_embeddings(&mut cursor).is_err());
+
+        let (_, vocab, storage) = embeddings.into_parts();
+        assert!(Embeddings::try_new(Some(metadata), vocab, storage).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! Writer for the TensorBoard Embedding Projector format.
+//!
+//! The [Embedding Projector](https://projector.tensorflow.org/) reads
+//! embeddings from two tab-separated files: a file with one vector
+//! per line (`vectors.tsv`) and a file with the corresponding words
+//! (`metadata.tsv`). If word frequencies are available, they are
+//! added as a second column of the metadata file.
+//!
+//! Since the projector runs in the browser, it is usually necessary
+//! to restrict the output to the most frequent words:
+//!
+//! ```
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use rust2vec::prelude::*;
+//! use rust2vec::projector::WriteProjector;
+//!
+//! let mut reader = BufReader::new(File::open("testdata/similarity.bin").unwrap());
+//! let embeddings = Embeddings::read_word2vec_binary(&mut reader, true).unwrap();
+//!
+//! let mut vectors = Vec::new();
+//! let mut metadata = Vec::new();
+//! embeddings
+//!     .write_projector(&mut vectors, &mut metadata, Some(10), None)
+//!     .unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use failure::Error;
+use itertools::Itertools;
+
+use crate::embeddings::Embeddings;
+use crate::storage::Storage;
+use crate::vocab::Vocab;
+
+/// Method to write `Embeddings` in the Embedding Projector format.
+///
+/// This trait defines an extension to `Embeddings` to write the word
+/// embeddings as vector and metadata TSV files.
+pub trait WriteProjector<W>
+where
+    W: Write,
+{
+    /// Write the embeddings to the given vectors and metadata writers.
+    ///
+    /// If `limit` is specified, only the embeddings of the first `limit`
+    /// words of the vocabulary are written. Since vocabularies are
+    /// typically sorted by frequency, these are the most frequent
+    /// words. If `frequencies` is specified, the metadata contains a
+    /// frequency column. The frequency cell is left empty for words
+    /// without a frequency, since frequency tables are often pruned.
+    fn write_projector(
+        &self,
+        vectors: &mut W,
+        metadata: &mut W,
+        limit: Option<usize>,
+        frequencies: Option<&HashMap<&str, u64>>,
+    ) -> Result<(), Error>;
+}
+
+impl<W, V, S> WriteProjector<W> for Embeddings<V, S>
+where
+    W: Write,
+    V: Vocab,
+    S: Storage,
+{
+    fn write_projector(
+        &self,
+        vectors: &mut W,
+        metadata: &mut W,
+        limit: Option<usize>,
+        frequencies: Option<&HashMap<&str, u64>>,
+    ) -> Result<(), Error> {
+        // The metadata file only has a header if it has more than
+        // one column.
+        if frequencies.is_some() {
+            writeln!(metadata, "word\tfrequency")?;
+        }
+
+        for (word, embed) in self.iter().take(limit.unwrap_or(usize::MAX)) {
+            let embed_str = embed.as_view().iter().map(ToString::to_string).join("\t");
+            writeln!(vectors, "{}", embed_str)?;
+
+            // Tabs and newlines would break the TSV format.
+            let escaped_word = word.replace(['\t', '\n'], " ");
+            match frequencies {
+                Some(frequencies) => match frequencies.get(word) {
+                    Some(freq) => writeln!(metadata, "{}\t{}", escaped_word, freq)?,
+                    None => writeln!(metadata, "{}\t", escaped_word)?,
+                },
+                None => writeln!(metadata, "{}", escaped_word)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ndarray::arr2;
+
+    use super::WriteProjector;
+    use crate::embeddings::Embeddings;
+    use crate::storage::NdArray;
+    use crate::vocab::SimpleVocab;
+
+    fn test_embeddings() -> Embeddings<SimpleVocab, NdArray> {
+        Embeddings::new(
+            None,
+            SimpleVocab::new(vec![
+                "the".to_owned(),
+                "New York".to_owned(),
+                "Berlin".to_owned(),
+            ]),
+            NdArray(arr2(&[[1., 2.], [3., 4.], [5., 6.]])),
+        )
+    }
+
+    #[test]
+    fn write_projector() {
+        let mut vectors = Vec::new();
+        let mut metadata = Vec::new();
+        test_embeddings()
+            .write_projector(&mut vectors, &mut metadata, None, None)
+            .unwrap();
+
+        assert_eq!(String::from_utf8(vectors).unwrap(), "1\t2\n3\t4\n5\t6\n");
+        assert_eq!(
+            String::from_utf8(metadata).unwrap(),
+            "the\nNew York\nBerlin\n"
+        );
+    }
+
+    #[test]
+    fn write_projector_limit_frequencies() {
+        let mut frequencies = HashMap::new();
+        frequencies.insert("the", 10);
+        frequencies.insert("New York", 5);
+
+        let mut vectors = Vec::new();
+        let mut metadata = Vec::new();
+        test_embeddings()
+            .write_projector(&mut vectors, &mut metadata, Some(2), Some(&frequencies))
+            .unwrap();
+
+        assert_eq!(String::from_utf8(vectors).unwrap(), "1\t2\n3\t4\n");
+        assert_eq!(
+            String::from_utf8(metadata).unwrap(),
+            "word\tfrequency\nthe\t10\nNew York\t5\n"
+        );
+    }
+
+    #[test]
+    fn write_projector_missing_frequency() {
+        let mut frequencies = HashMap::new();
+        frequencies.insert("the", 10);
+        frequencies.insert("Berlin", 3);
+
+        let mut vectors = Vec::new();
+        let mut metadata = Vec::new();
+        test_embeddings()
+            .write_projector(&mut vectors, &mut metadata, None, Some(&frequencies))
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(metadata).unwrap(),
+            "word\tfrequency\nthe\t10\nNew York\t\nBerlin\t3\n"
+        );
+    }
+}
@@ -0,0 +1,318 @@
+//! Dimensionality reduction of embeddings.
+//!
+//! This module reduces the dimensionality of embeddings using principal
+//! component analysis (PCA). The embeddings are centered and projected
+//! on their principal components.
+//!
+//! Optionally, the dominant principal components can be removed, as in
+//! the *all-but-the-top* post-processing of Mu & Viswanath (2018). The
+//! dominant components mostly encode word frequency, rather than
+//! semantics. When `n` components are removed, the embeddings are
+//! projected on the principal components *n + 1, ..., n + dims*.
+//!
+//! The principal components are computed from the embeddings of known
+//! words. Subword embeddings are projected on the same components, but
+//! are not centered: the embedding of an unknown word is the sum of
+//! its subword embeddings, so centering each subword embedding would
+//! subtract the mean once for every subword unit.
+
+use failure::{ensure, err_msg, Error};
+use ndarray::{s, Array1, Array2, ArrayView1, ArrayView2, Axis};
+use toml::Value;
+
+use crate::embeddings::Embeddings;
+use crate::linalg::centered_principal_directions;
+use crate::storage::{NdArray, StorageView};
+use crate::util::{l2_normalize, matrix_to_toml, toml_to_matrix, toml_to_vector, vector_to_toml};
+use crate::vocab::Vocab;
+
+/// Projection on principal components.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Projection {
+    /// The mean of the embeddings, which is subtracted before
+    /// projection.
+    pub mean: Array1<f32>,
+
+    /// The principal components as rows of a matrix.
+    pub components: Array2<f32>,
+
+    /// The variance of the embeddings along each component.
+    pub explained_variance: Array1<f32>,
+
+    /// The total variance of the embeddings.
+    pub total_variance: f32,
+
+    /// The number of dominant components that were removed.
+    pub n_removed: usize,
+}
+
+impl Projection {
+    /// Compute a projection on the principal components of the rows
+    /// of `data`.
+    ///
+    /// The projection has `dims` dimensions. The `n_removed` most
+    /// dominant components are skipped.
+    pub fn fit(data: ArrayView2<f32>, dims: usize, n_removed: usize) -> Result<Self, Error> {
+        ensure!(dims > 0, "The number of dimensions should be positive");
+        ensure!(
+            dims + n_removed <= data.cols(),
+            "Cannot project {}-dimensional embeddings to {} dimensions after removing {} components",
+            data.cols(),
+            dims,
+            n_removed
+        );
+        ensure!(
+            data.rows() > 0,
+            "Cannot compute projection without embeddings"
+        );
+
+        let mean = data.mean_axis(Axis(0));
+
+        let (eigenvalues, directions) =
+            centered_principal_directions(data, mean.view(), n_removed + dims);
+        let n = data.rows() as f64;
+        let total_variance = data
+            .outer_iter()
+            .map(|embed| {
+                let centered = &embed - &mean;
+                f64::from(centered.dot(&centered))
+            })
+            .sum::<f64>()
+            / n;
+
+        Ok(Projection {
+            mean,
+            components: directions.slice(s![n_removed.., ..]).to_owned(),
+            explained_variance: eigenvalues
+                .slice(s![n_removed..])
+                .mapv(|v| (v.max(0.) / n) as f32),
+            total_variance: total_variance as f32,
+            n_removed,
+        })
+    }
+
+    /// The dimensionality of projected embeddings.
+    pub fn dims(&self) -> usize {
+        self.components.rows()
+    }
+
+    /// The fraction of the total variance that is retained.
+    pub fn explained_variance_ratio(&self) -> f32 {
+        if self.total_variance == 0. {
+            return 0.;
+        }
+
+        self.explained_variance.sum() / self.total_variance
+    }
+
+    /// Project an embedding.
+    pub fn project(&self, embedding: ArrayView1<f32>) -> Array1<f32> {
+        self.components.dot(&(&embedding - &self.mean))
+    }
+
+    /// Project the rows of a matrix.
+    pub fn project_matrix(&self, embeddings: ArrayView2<f32>) -> Array2<f32> {
+        (&embeddings - &self.mean).dot(&self.components.t())
+    }
+
+    /// Read a projection from metadata.
+    pub fn from_metadata(value: &Value) -> Result<Self, Error> {
+        let get = |key| {
+            value
+                .get(key)
+                .ok_or_else(|| format!("Missing projection field: {}", key))
+                .map_err(err_msg)
+        };
+
+        let mean = toml_to_vector(get("mean")?)?;
+        let components = toml_to_matrix(get("components")?)?;
+        let explained_variance = toml_to_vector(get("explained_variance")?)?;
+        let total_variance = get("total_variance")?
+            .as_float()
+            .ok_or_else(|| err_msg("Total variance is not a float"))?
+            as f32;
+        let n_removed = get("removed_components")?
+            .as_integer()
+            .filter(|&n| n >= 0)
+            .ok_or_else(|| err_msg("Invalid number of removed components"))?
+            as usize;
+
+        ensure!(
+            components.cols() == mean.len(),
+            "Components have {} dimensions, mean has {} dimensions",
+            components.cols(),
+            mean.len()
+        );
+        ensure!(
+            components.rows() == explained_variance.len(),
+            "Number of components ({}) and explained variances ({}) differ",
+            components.rows(),
+            explained_variance.len()
+        );
+
+        Ok(Projection {
+            mean,
+            components,
+            explained_variance,
+            total_variance,
+            n_removed,
+        })
+    }
+
+    /// Get the projection as metadata.
+    ///
+    /// The projection is stored as a table with the mean, the
+    /// components, and the variance statistics.
+    pub fn to_metadata(&self) -> Value {
+        let mut table = toml::value::Table::new();
+        table.insert("mean".to_owned(), vector_to_toml(self.mean.view()));
+        table.insert(
+            "components".to_owned(),
+            matrix_to_toml(self.components.view()),
+        );
+        table.insert(
+            "explained_variance".to_owned(),
+            vector_to_toml(self.explained_variance.view()),
+        );
+        table.insert(
+            "total_variance".to_owned(),
+            Value::Float(self.total_variance as f64),
+        );
+        table.insert(
+            "removed_components".to_owned(),
+            Value::Integer(self.n_removed as i64),
+        );
+        Value::Table(table)
+    }
+}
+
+/// Dimensionality reduction of embeddings.
+pub trait Reduce {
+    /// Reduce the embeddings to `dims` dimensions using PCA.
+    ///
+    /// The `n_removed` dominant principal components are removed
+    /// (*all-but-the-top*). The projection is computed from the
+    /// embeddings of known words, which are centered before and
+    /// normalized after projection. Subword embeddings are projected
+    /// without centering. Returns the reduced embeddings and the
+    /// projection, which can be used to project other embeddings.
+    fn reduce(&self, dims: usize, n_removed: usize) -> Result<(NdArray, Projection), Error>;
+}
+
+impl<V, S> Reduce for Embeddings<V, S>
+where
+    V: Vocab,
+    S: StorageView,
+{
+    fn reduce(&self, dims: usize, n_removed: usize) -> Result<(NdArray, Projection), Error> {
+        let view = self.storage().view();
+        let n_words = self.vocab().len();
+
+        let words = view.slice(s![..n_words, ..]);
+        let projection = Projection::fit(words, dims, n_removed)?;
+
+        let mut reduced = Array2::zeros((view.rows(), dims));
+        reduced
+            .slice_mut(s![..n_words, ..])
+            .assign(&projection.project_matrix(words));
+        for embed in reduced.slice_mut(s![..n_words, ..]).outer_iter_mut() {
+            l2_normalize(embed);
+        }
+
+        // Subword embeddings are not centered, see the module
+        // documentation.
+        let subwords = view
+            .slice(s![n_words.., ..])
+            .dot(&projection.components.t());
+        reduced.slice_mut(s![n_words.., ..]).assign(&subwords);
+
+        Ok((NdArray(reduced), projection))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{arr1, arr2, s};
+
+    use super::{Projection, Reduce};
+    use crate::embeddings::Embeddings;
+    use crate::storage::{NdArray, Storage, StorageView};
+    use crate::test_util::scaled_random_matrix;
+    use crate::vocab::SubwordVocab;
+
+    /// Random word embeddings with decreasing variance along the axes.
+    ///
+    /// The subword embeddings have a large variance along the last
+    /// axis, which should not affect the projection.
+    fn test_embeddings() -> Embeddings<SubwordVocab, NdArray> {
+        let words = (0..200).map(|idx| format!("w{}", idx)).collect::<Vec<_>>();
+        let mut data = scaled_random_matrix(216, &[8., 4., 2., 1.]);
+        data.slice_mut(s![200.., 3])
+            .mapv_inplace(|v| 3. + 100. * (v - 3.));
+
+        Embeddings::new(None, SubwordVocab::new(words, 3, 6, 4), NdArray(data))
+    }
+
+    #[test]
+    fn reduce_pca() {
+        let embeddings = test_embeddings();
+        let (reduced, projection) = embeddings.reduce(2, 0).unwrap();
+
+        assert_eq!(reduced.shape(), (216, 2));
+        assert_eq!(projection.dims(), 2);
+
+        // The components correspond to the axes with most variance.
+        assert!(projection.components[[0, 0]].abs() > 0.99);
+        assert!(projection.components[[1, 1]].abs() > 0.99);
+        assert!(projection.explained_variance[0] > projection.explained_variance[1]);
+        assert!(projection.explained_variance_ratio() > 0.9);
+
+        // Reduced word embeddings are normalized.
+        for embed in reduced.view().outer_iter().take(200) {
+            assert!((embed.dot(&embed) - 1.).abs() < 1e-5);
+        }
+
+        // Projection of single embeddings is consistent.
+        let mut single = projection.project(embeddings.storage().view().row(3));
+        single /= single.dot(&single).sqrt();
+        assert!((&single - &reduced.view().row(3))
+            .iter()
+            .all(|v| v.abs() < 1e-4));
+
+        // Embeddings of unknown words are projected without centering.
+        let unknown = embeddings.embedding("w1000").unwrap().into_owned();
+        let mut check = projection.components.dot(&unknown);
+        check /= check.dot(&check).sqrt();
+        let reduced = Embeddings::new(None, embeddings.vocab().clone(), reduced);
+        let unknown = reduced.embedding("w1000").unwrap().into_owned();
+        assert!((&unknown - &check).iter().all(|v| v.abs() < 1e-4));
+    }
+
+    #[test]
+    fn reduce_all_but_the_top() {
+        let embeddings = test_embeddings();
+        let (reduced, projection) = embeddings.reduce(2, 1).unwrap();
+
+        assert_eq!(reduced.shape(), (216, 2));
+        assert_eq!(projection.n_removed, 1);
+        assert!(projection.components[[0, 1]].abs() > 0.99);
+        assert!(projection.components[[1, 2]].abs() > 0.99);
+
+        assert!(embeddings.reduce(4, 1).is_err());
+        assert!(embeddings.reduce(0, 0).is_err());
+    }
+
+    #[test]
+    fn projection_metadata_roundtrip() {
+        let projection = Projection {
+            mean: arr1(&[1., 2., 3.]),
+            components: arr2(&[[1., 0., 0.], [0., 0., 1.]]),
+            explained_variance: arr1(&[4., 1.]),
+            total_variance: 6.,
+            n_removed: 1,
+        };
+
+        let restored = Projection::from_metadata(&projection.to_metadata()).unwrap();
+        assert_eq!(restored, projection);
+    }
+}
@@ -0,0 +1,352 @@
+//! Retrofitting of embeddings to a semantic lexicon.
+//!
+//! Retrofitting (Faruqui et al., 2015) moves the embeddings of words
+//! that are related in a semantic lexicon (such as a thesaurus) closer
+//! together, while keeping them close to their original embeddings.
+//! The retrofitted embedding *q_i* of word *i* minimizes:
+//!
+//! *α ‖q_i - q̂_i‖² + Σ_j β_ij ‖q_i - q_j‖²*
+//!
+//! where *q̂_i* is the original embedding and *j* ranges over the
+//! neighbors of *i* in the lexicon. The objective is minimized
+//! iteratively using the update:
+//!
+//! *q_i = (α q̂_i + Σ_j β_ij q_j) / (α + Σ_j β_ij)*
+
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use failure::{ensure, Error};
+use ndarray::Array1;
+use toml::Value;
+
+use crate::embeddings::Embeddings;
+use crate::storage::{NdArray, StorageView};
+use crate::util::l2_normalize;
+use crate::vocab::{Vocab, WordIndex};
+
+/// Semantic lexicon.
+///
+/// The lexicon is a graph that stores the neighbors of words.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Lexicon {
+    entries: Vec<(String, Vec<String>)>,
+    indices: HashMap<String, usize>,
+}
+
+impl Lexicon {
+    /// Construct an empty lexicon.
+    pub fn new() -> Self {
+        Lexicon::default()
+    }
+
+    /// Add neighbors of a word.
+    ///
+    /// If the word is already in the lexicon, the neighbors are added
+    /// to its existing neighbors.
+    pub fn insert(&mut self, word: impl Into<String>, neighbors: impl IntoIterator<Item = String>) {
+        let word = word.into();
+        let idx = match self.indices.get(&word) {
+            Some(&idx) => idx,
+            None => {
+                self.indices.insert(word.clone(), self.entries.len());
+                self.entries.push((word, Vec::new()));
+                self.entries.len() - 1
+            }
+        };
+
+        self.entries[idx].1.extend(neighbors);
+    }
+
+    /// Get the neighbors of a word.
+    pub fn neighbors(&self, word: &str) -> Option<&[String]> {
+        self.indices
+            .get(word)
+            .map(|&idx| self.entries[idx].1.as_slice())
+    }
+
+    /// Get an iterator over words and their neighbors.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.entries
+            .iter()
+            .map(|(word, neighbors)| (word.as_str(), neighbors.as_slice()))
+    }
+
+    /// Get the number of words with neighbors.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check whether the lexicon is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Read a semantic lexicon.
+///
+/// Each line contains a word, followed by its neighbors. Words are
+/// separated by whitespace. Empty lines and lines starting with `#`
+/// are ignored.
+pub fn read_lexicon(reader: impl BufRead) -> Result<Lexicon, Error> {
+    let mut lexicon = Lexicon::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        let word = words.next().unwrap();
+        lexicon.insert(word, words.map(ToOwned::to_owned));
+    }
+
+    Ok(lexicon)
+}
+
+/// Retrofitting hyperparameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Retrofitting {
+    /// Weight of the distance to the original embedding (α).
+    pub alpha: f32,
+
+    /// Weight of the distances to the neighbors (β).
+    ///
+    /// The weight is divided by the number of neighbors of a word,
+    /// *β_ij = β / degree(i)*.
+    pub beta: f32,
+
+    /// The number of iterations.
+    pub iterations: usize,
+}
+
+impl Default for Retrofitting {
+    fn default() -> Self {
+        Retrofitting {
+            alpha: 1.,
+            beta: 1.,
+            iterations: 10,
+        }
+    }
+}
+
+impl Retrofitting {
+    /// Get the hyperparameters as metadata.
+    pub fn to_metadata(&self) -> Value {
+        let mut table = toml::value::Table::new();
+        table.insert("alpha".to_owned(), Value::Float(self.alpha as f64));
+        table.insert("beta".to_owned(), Value::Float(self.beta as f64));
+        table.insert(
+            "iterations".to_owned(),
+            Value::Integer(self.iterations as i64),
+        );
+        Value::Table(table)
+    }
+}
+
+/// Retrofitting of embeddings to a semantic lexicon.
+pub trait Retrofit<V> {
+    /// Retrofit the embeddings to `lexicon`.
+    ///
+    /// Only the embeddings of known words that have neighbors with
+    /// an embedding are changed. Lexicon words and neighbors that are
+    /// not in the vocabulary are ignored. The embeddings of known words
+    /// are normalized after retrofitting. The hyperparameters are
+    /// stored in the `retrofitting` table of the metadata.
+    fn retrofit(
+        &self,
+        lexicon: &Lexicon,
+        retrofitting: Retrofitting,
+    ) -> Result<Embeddings<V, NdArray>, Error>;
+}
+
+impl<V, S> Retrofit<V> for Embeddings<V, S>
+where
+    V: Clone + Vocab,
+    S: StorageView,
+{
+    fn retrofit(
+        &self,
+        lexicon: &Lexicon,
+        retrofitting: Retrofitting,
+    ) -> Result<Embeddings<V, NdArray>, Error> {
+        ensure!(
+            retrofitting.alpha >= 0. && retrofitting.beta >= 0.,
+            "Retrofitting weights should be non-negative"
+        );
+        ensure!(
+            retrofitting.alpha + retrofitting.beta > 0.,
+            "At least one of the retrofitting weights should be positive"
+        );
+
+        let graph = lexicon_graph(self.vocab(), lexicon);
+
+        let original = self.storage().view();
+        let mut retrofitted = original.to_owned();
+        let mut embed = Array1::zeros(original.cols());
+
+        for _ in 0..retrofitting.iterations {
+            for (idx, neighbors) in &graph {
+                let beta = retrofitting.beta / neighbors.len() as f32;
+
+                embed.assign(&original.row(*idx));
+                embed *= retrofitting.alpha;
+                for &neighbor in neighbors {
+                    embed.scaled_add(beta, &retrofitted.row(neighbor));
+                }
+                embed /= retrofitting.alpha + retrofitting.beta;
+
+                retrofitted.row_mut(*idx).assign(&embed);
+            }
+        }
+
+        for embed in retrofitted.outer_iter_mut().take(self.vocab().len()) {
+            l2_normalize(embed);
+        }
+
+        let mut metadata = self.metadata().cloned().unwrap_or_default();
+        metadata.insert("retrofitting", retrofitting.to_metadata())?;
+
+        Ok(Embeddings::new(
+            Some(metadata),
+            self.vocab().clone(),
+            NdArray(retrofitted),
+        ))
+    }
+}
+
+/// Convert the lexicon to a graph of word indices.
+///
+/// Only words and neighbors that are in the vocabulary are retained,
+/// words without neighbors are removed.
+fn lexicon_graph(vocab: &impl Vocab, lexicon: &Lexicon) -> Vec<(usize, Vec<usize>)> {
+    let word_idx = |word: &str| match vocab.idx(word) {
+        Some(WordIndex::Word(idx)) => Some(idx),
+        _ => None,
+    };
+
+    lexicon
+        .iter()
+        .filter_map(|(word, neighbors)| {
+            let idx = word_idx(word)?;
+            let neighbors = neighbors
+                .iter()
+                .filter_map(|neighbor| word_idx(neighbor))
+                .filter(|&neighbor| neighbor != idx)
+                .collect::<Vec<_>>();
+
+            if neighbors.is_empty() {
+                None
+            } else {
+                Some((idx, neighbors))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use ndarray::{arr1, arr2};
+
+    use super::{read_lexicon, Lexicon, Retrofit, Retrofitting};
+    use crate::embeddings::Embeddings;
+    use crate::storage::{NdArray, StorageView};
+    use crate::test_util::embeddings_from_rows;
+    use crate::vocab::SimpleVocab;
+
+    fn test_embeddings() -> Embeddings<SimpleVocab, NdArray> {
+        embeddings_from_rows(
+            &["happy", "glad", "sad", "table"],
+            arr2(&[[1., 0.], [0., 1.], [-1., 0.], [0., -1.]]),
+        )
+    }
+
+    #[test]
+    fn read_lexicon_test() {
+        let lexicon = read_lexicon(Cursor::new(
+            "# comment\nhappy glad cheerful\n\nglad happy\nhappy joyful\n",
+        ))
+        .unwrap();
+
+        assert_eq!(lexicon.len(), 2);
+        assert_eq!(
+            lexicon.neighbors("happy").unwrap(),
+            &[
+                "glad".to_owned(),
+                "cheerful".to_owned(),
+                "joyful".to_owned()
+            ]
+        );
+        assert_eq!(lexicon.neighbors("glad").unwrap(), &["happy".to_owned()]);
+        assert!(lexicon.neighbors("sad").is_none());
+    }
+
+    #[test]
+    fn retrofit_single_iteration() {
+        let embeddings = test_embeddings();
+        let mut lexicon = Lexicon::new();
+        lexicon.insert("happy", vec!["glad".to_owned(), "unknown".to_owned()]);
+        lexicon.insert("unknown", vec!["sad".to_owned()]);
+
+        let retrofitting = Retrofitting {
+            alpha: 1.,
+            beta: 1.,
+            iterations: 1,
+        };
+        let retrofitted = embeddings.retrofit(&lexicon, retrofitting).unwrap();
+        let view = retrofitted.storage().view();
+
+        // (α q̂ + β q_glad) / (α + β), normalized.
+        let check = arr1(&[0.5f32, 0.5]) / 0.5f32.sqrt();
+        assert!((&view.row(0) - &check).iter().all(|v| v.abs() < 1e-6));
+
+        // Words without neighbors are not changed.
+        for idx in 1..4 {
+            assert_eq!(view.row(idx), embeddings.storage().view().row(idx));
+        }
+
+        let metadata = retrofitted.metadata().unwrap();
+        assert_eq!(
+            metadata.0["retrofitting"]["iterations"].as_integer(),
+            Some(1)
+        );
+        assert_eq!(metadata.0["retrofitting"]["alpha"].as_float(), Some(1.));
+    }
+
+    #[test]
+    fn retrofit_moves_neighbors_closer() {
+        let embeddings = test_embeddings();
+        let mut lexicon = Lexicon::new();
+        lexicon.insert("happy", vec!["glad".to_owned()]);
+        lexicon.insert("glad", vec!["happy".to_owned()]);
+
+        let retrofitted = embeddings
+            .retrofit(&lexicon, Retrofitting::default())
+            .unwrap();
+        let view = retrofitted.storage().view();
+
+        let original_dist =
+            &embeddings.storage().view().row(0) - &embeddings.storage().view().row(1);
+        let dist = &view.row(0) - &view.row(1);
+        assert!(dist.dot(&dist) < original_dist.dot(&original_dist));
+
+        // With α = 0, the neighbors collapse.
+        let retrofitting = Retrofitting {
+            alpha: 0.,
+            ..Retrofitting::default()
+        };
+        let retrofitted = embeddings.retrofit(&lexicon, retrofitting).unwrap();
+        let view = retrofitted.storage().view();
+        assert_eq!(view.row(0), view.row(1));
+
+        let retrofitting = Retrofitting {
+            alpha: -1.,
+            ..Retrofitting::default()
+        };
+        assert!(embeddings.retrofit(&lexicon, retrofitting).is_err());
+    }
+}
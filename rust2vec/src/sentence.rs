@@ -0,0 +1,637 @@
+//! Sentence embeddings.
+//!
+//! This module composes sentence embeddings from word embeddings. The
+//! following pooling methods are supported:
+//!
+//! * Mean pooling: the average of the word embeddings.
+//! * Max pooling: the element-wise maximum of the word embeddings.
+//! * Smooth inverse frequency (SIF, Arora et al., 2017): a weighted
+//!   average of the word embeddings, where the weight of a word is
+//!   *a / (a + p(w))*. The projections on the first principal
+//!   direction of the sentence embeddings are removed.
+//! * Unsupervised smooth inverse frequency (uSIF, Ethayarajh, 2018): a
+//!   weighted average of the normalized word embeddings, where the weight
+//!   of a word is *a / (½a + p(w))*. The parameter *a* is estimated from
+//!   the word frequencies. The projections on the first principal
+//!   directions are removed, weighted by their explained variance.
+//!
+//! SIF and uSIF require word probabilities, which can be obtained from
+//! the embeddings metadata or from a file with word counts. The
+//! principal directions are estimated using `SentenceEmbedder::fit` and
+//! can be stored with `CommonComponents::to_metadata`, so that they can
+//! be reused with `SentenceEmbedder::set_common_components`.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use failure::{ensure, err_msg, format_err, Error};
+use ndarray::{Array1, Array2};
+use toml::Value;
+
+use crate::embeddings::Embeddings;
+use crate::linalg::principal_directions;
+use crate::metadata::Metadata;
+use crate::storage::Storage;
+use crate::util::{l2_normalize, matrix_to_toml, toml_to_matrix};
+use crate::vocab::Vocab;
+
+/// Word probabilities.
+#[derive(Clone, Debug)]
+pub struct WordFrequencies {
+    probs: HashMap<String, f32>,
+}
+
+impl WordFrequencies {
+    /// Construct word probabilities from word counts.
+    pub fn from_counts<I, S>(counts: I) -> Self
+    where
+        I: IntoIterator<Item = (S, u64)>,
+        S: Into<String>,
+    {
+        let counts = counts
+            .into_iter()
+            .map(|(word, count)| (word.into(), count))
+            .collect::<Vec<_>>();
+        let total = counts.iter().map(|&(_, count)| count).sum::<u64>().max(1) as f64;
+
+        WordFrequencies {
+            probs: counts
+                .into_iter()
+                .map(|(word, count)| (word, (count as f64 / total) as f32))
+                .collect(),
+        }
+    }
+
+    /// Get word probabilities from the frequencies in the metadata.
+    ///
+    /// Returns `None` if the metadata does not contain frequencies.
+    pub fn from_metadata(metadata: &Metadata) -> Result<Option<Self>, Error> {
+        Ok(metadata.frequencies()?.map(Self::from_counts))
+    }
+
+    /// Read word counts.
+    ///
+    /// Every line should contain a word and its count, separated by
+    /// whitespace. Since the count is the last field, words can contain
+    /// whitespace.
+    pub fn read_counts(reader: impl BufRead) -> Result<Self, Error> {
+        let mut counts = Vec::new();
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut parts = line.rsplitn(2, char::is_whitespace);
+            let (count, word) = match (parts.next(), parts.next()) {
+                (Some(count), Some(word)) => (count, word.trim_end()),
+                _ => {
+                    return Err(format_err!(
+                        "Line {} does not contain a word and a count: {}",
+                        idx + 1,
+                        line
+                    ));
+                }
+            };
+
+            let count = count
+                .parse()
+                .map_err(|_| format_err!("Cannot parse count on line {}: {}", idx + 1, count))?;
+            counts.push((word.to_owned(), count));
+        }
+
+        Ok(Self::from_counts(counts))
+    }
+
+    /// Get the number of words.
+    pub fn len(&self) -> usize {
+        self.probs.len()
+    }
+
+    /// Check whether there are no word probabilities.
+    pub fn is_empty(&self) -> bool {
+        self.probs.is_empty()
+    }
+
+    /// Get the probability of a word.
+    ///
+    /// Returns 0 for unknown words.
+    pub fn prob(&self, word: &str) -> f32 {
+        self.probs.get(word).cloned().unwrap_or(0.)
+    }
+}
+
+/// Pooling methods.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pooling {
+    /// Average of the word embeddings.
+    Mean,
+
+    /// Element-wise maximum of the word embeddings.
+    Max,
+
+    /// Smooth inverse frequency.
+    Sif {
+        /// Smoothing parameter of the word weights.
+        a: f32,
+
+        /// Number of principal directions to remove.
+        n_components: usize,
+    },
+
+    /// Unsupervised smooth inverse frequency.
+    USif {
+        /// Expected sentence length, used to estimate the smoothing
+        /// parameter of the word weights.
+        sentence_len: f32,
+
+        /// Number of principal directions to remove.
+        n_components: usize,
+    },
+}
+
+impl Pooling {
+    /// SIF with the parameters of Arora et al. (2017).
+    pub fn sif() -> Self {
+        Pooling::Sif {
+            a: 1e-3,
+            n_components: 1,
+        }
+    }
+
+    /// uSIF with the parameters of Ethayarajh (2018).
+    pub fn usif() -> Self {
+        Pooling::USif {
+            sentence_len: 11.,
+            n_components: 5,
+        }
+    }
+
+    fn n_components(&self) -> usize {
+        match *self {
+            Pooling::Mean | Pooling::Max => 0,
+            Pooling::Sif { n_components, .. } | Pooling::USif { n_components, .. } => n_components,
+        }
+    }
+}
+
+/// Common components of sentence embeddings.
+///
+/// The projections of sentence embeddings on the components are
+/// removed, scaled by the weight of the component.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommonComponents {
+    /// The components as rows of a matrix.
+    pub components: Array2<f32>,
+
+    /// The weights of the components.
+    pub weights: Array1<f32>,
+}
+
+impl CommonComponents {
+    /// Read common components from metadata.
+    pub fn from_metadata(value: &Value) -> Result<Self, Error> {
+        let components = toml_to_matrix(
+            value
+                .get("components")
+                .ok_or_else(|| err_msg("Missing components"))?,
+        )?;
+        let weights = value
+            .get("weights")
+            .and_then(Value::as_array)
+            .ok_or_else(|| err_msg("Missing component weights"))?
+            .iter()
+            .map(|v| {
+                v.as_float()
+                    .map(|v| v as f32)
+                    .ok_or_else(|| err_msg("Component weight is not a float"))
+            })
+            .collect::<Result<Array1<f32>, Error>>()?;
+
+        ensure!(
+            components.rows() == weights.len(),
+            "Number of components ({}) and weights ({}) differ",
+            components.rows(),
+            weights.len()
+        );
+
+        Ok(CommonComponents {
+            components,
+            weights,
+        })
+    }
+
+    /// Get the common components as metadata.
+    ///
+    /// The components are stored as a table with the components and
+    /// their weights.
+    pub fn to_metadata(&self) -> Value {
+        let mut table = toml::value::Table::new();
+        table.insert(
+            "components".to_owned(),
+            matrix_to_toml(self.components.view()),
+        );
+        table.insert(
+            "weights".to_owned(),
+            Value::Array(
+                self.weights
+                    .iter()
+                    .map(|&v| Value::Float(v as f64))
+                    .collect(),
+            ),
+        );
+        Value::Table(table)
+    }
+
+    /// Remove the common components from an embedding.
+    fn remove(&self, mut embed: Array1<f32>) -> Array1<f32> {
+        for (component, &weight) in self.components.outer_iter().zip(self.weights.iter()) {
+            let proj = embed.dot(&component);
+            embed.scaled_add(-weight * proj, &component);
+        }
+
+        embed
+    }
+}
+
+/// Sentence embeddings from word embeddings.
+pub struct SentenceEmbedder<'a, V, S> {
+    embeddings: &'a Embeddings<V, S>,
+    pooling: Pooling,
+    frequencies: Option<WordFrequencies>,
+    a: f32,
+    common_components: Option<CommonComponents>,
+}
+
+impl<'a, V, S> SentenceEmbedder<'a, V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    /// Construct a sentence embedder.
+    ///
+    /// SIF and uSIF pooling require word probabilities.
+    pub fn new(
+        embeddings: &'a Embeddings<V, S>,
+        pooling: Pooling,
+        frequencies: Option<WordFrequencies>,
+    ) -> Result<Self, Error> {
+        let a = match (pooling, &frequencies) {
+            (Pooling::Mean, _) | (Pooling::Max, _) => 0.,
+            (Pooling::Sif { a, .. }, Some(_)) => a,
+            (Pooling::USif { sentence_len, .. }, Some(frequencies)) => {
+                usif_a(frequencies, sentence_len)?
+            }
+            (_, None) => return Err(err_msg("SIF and uSIF pooling require word frequencies")),
+        };
+
+        Ok(SentenceEmbedder {
+            embeddings,
+            pooling,
+            frequencies,
+            a,
+            common_components: None,
+        })
+    }
+
+    /// Get the common components.
+    ///
+    /// Returns `None` if the embedder is not fitted.
+    pub fn common_components(&self) -> Option<&CommonComponents> {
+        self.common_components.as_ref()
+    }
+
+    /// Set the common components.
+    ///
+    /// This can be used to reuse the components of an embedder that was
+    /// fitted earlier.
+    pub fn set_common_components(&mut self, common_components: CommonComponents) {
+        self.common_components = Some(common_components);
+    }
+
+    /// Estimate the common components from sentences.
+    ///
+    /// The common components are the principal directions of the
+    /// sentence embeddings. This is a no-op for mean and max pooling.
+    pub fn fit<'b, T>(&mut self, sentences: &[T]) -> Result<(), Error>
+    where
+        T: AsRef<[&'b str]>,
+    {
+        let n_components = self.pooling.n_components();
+        if n_components == 0 {
+            return Ok(());
+        }
+
+        let embeds = sentences
+            .iter()
+            .filter_map(|sentence| self.pool(sentence.as_ref()))
+            .collect::<Vec<_>>();
+        ensure!(
+            !embeds.is_empty(),
+            "None of the sentences contains a word with an embedding"
+        );
+
+        let mut matrix = Array2::zeros((embeds.len(), self.embeddings.dims()));
+        for (mut row, embed) in matrix.outer_iter_mut().zip(embeds) {
+            row.assign(&embed);
+        }
+
+        let (values, components) = principal_directions(matrix.view(), n_components);
+        let weights = match self.pooling {
+            Pooling::USif { .. } => {
+                let total = values.sum();
+                values.mapv(|v| if total > 0. { (v / total) as f32 } else { 0. })
+            }
+            _ => Array1::ones(components.rows()),
+        };
+
+        self.common_components = Some(CommonComponents {
+            components,
+            weights,
+        });
+
+        Ok(())
+    }
+
+    /// Get the embedding of a sentence.
+    ///
+    /// Embeddings of unknown words are computed from subword units when
+    /// the vocabulary supports them, other unknown words are ignored.
+    /// Returns `None` if none of the words has an embedding. Common
+    /// components are only removed after fitting.
+    pub fn transform(&self, sentence: &[&str]) -> Option<Array1<f32>> {
+        let embed = self.pool(sentence)?;
+
+        Some(match self.common_components {
+            Some(ref common_components) => common_components.remove(embed),
+            None => embed,
+        })
+    }
+
+    /// Get the embeddings of sentences.
+    ///
+    /// The embeddings of sentences without known words are zero vectors.
+    pub fn transform_batch<'b, T>(&self, sentences: &[T]) -> Array2<f32>
+    where
+        T: AsRef<[&'b str]>,
+    {
+        let mut embeds = Array2::zeros((sentences.len(), self.embeddings.dims()));
+        for (mut row, sentence) in embeds.outer_iter_mut().zip(sentences) {
+            if let Some(embed) = self.transform(sentence.as_ref()) {
+                row.assign(&embed);
+            }
+        }
+
+        embeds
+    }
+
+    /// Pool word embeddings without removing common components.
+    fn pool(&self, sentence: &[&str]) -> Option<Array1<f32>> {
+        let mut pooled: Option<Array1<f32>> = None;
+        let mut n_words = 0;
+
+        for &word in sentence {
+            let embed = match self.embeddings.embedding(word) {
+                Some(embed) => embed,
+                None => continue,
+            };
+            n_words += 1;
+
+            let pooled = pooled.get_or_insert_with(|| match self.pooling {
+                Pooling::Max => Array1::from_elem(self.embeddings.dims(), f32::MIN),
+                _ => Array1::zeros(self.embeddings.dims()),
+            });
+
+            match self.pooling {
+                Pooling::Max => pooled.zip_mut_with(&embed.as_view(), |p, &v| *p = p.max(v)),
+                Pooling::USif { .. } => {
+                    let mut embed = embed.into_owned();
+                    l2_normalize(embed.view_mut());
+                    pooled.scaled_add(self.weight(word), &embed);
+                }
+                _ => pooled.scaled_add(self.weight(word), &embed.as_view()),
+            }
+        }
+
+        let mut pooled = pooled?;
+        if self.pooling != Pooling::Max {
+            pooled /= n_words as f32;
+        }
+
+        Some(pooled)
+    }
+
+    /// Get the weight of a word.
+    fn weight(&self, word: &str) -> f32 {
+        let prob = match self.frequencies {
+            Some(ref frequencies) => frequencies.prob(word),
+            None => return 1.,
+        };
+
+        match self.pooling {
+            Pooling::Sif { .. } => self.a / (self.a + prob),
+            Pooling::USif { .. } => self.a / (0.5 * self.a + prob),
+            Pooling::Mean | Pooling::Max => 1.,
+        }
+    }
+}
+
+/// Estimate the uSIF smoothing parameter.
+///
+/// The parameter is estimated from the fraction of words that are
+/// expected to occur at least once in a sentence of `sentence_len`
+/// words.
+fn usif_a(frequencies: &WordFrequencies, sentence_len: f32) -> Result<f32, Error> {
+    ensure!(!frequencies.is_empty(), "No word frequencies");
+
+    let vocab_len = frequencies.len() as f64;
+    let threshold = 1. - (1. - 1. / vocab_len).powf(sentence_len as f64);
+    let alpha = frequencies
+        .probs
+        .values()
+        .filter(|&&prob| prob as f64 > threshold)
+        .count() as f64
+        / vocab_len;
+    ensure!(
+        alpha > 0.,
+        "Cannot estimate the uSIF parameter, no word is frequent enough"
+    );
+
+    let z = 0.5 * vocab_len;
+    Ok(((1. - alpha) / (alpha * z)) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use ndarray::Array1;
+    use toml::Value;
+
+    use super::{CommonComponents, Pooling, SentenceEmbedder, WordFrequencies};
+    use crate::metadata::Metadata;
+    use crate::test_util::similarity_embeddings;
+    use crate::vocab::Vocab;
+
+    static SENTENCES: &[&[&str]] = &[
+        &["Berlin", "Potsdam", "Hamburg"],
+        &["Stuttgart", "Karlsruhe", "Mannheim", "Heidelberg"],
+        &["Berlin", "Leipzig", "Dresden"],
+        &["München", "Stuttgart"],
+        &["Bonn", "Köln", "Düsseldorf", "Dortmund"],
+        &["Rostock", "Schwerin", "Greifswald", "Berlin"],
+    ];
+
+    fn frequencies() -> WordFrequencies {
+        WordFrequencies::from_counts(vec![("Berlin", 60), ("Stuttgart", 30), ("Bonn", 10)])
+    }
+
+    fn assert_close(a: &Array1<f32>, b: &Array1<f32>) {
+        for (&va, &vb) in a.iter().zip(b.iter()) {
+            assert!((va - vb).abs() < 1e-5, "{} != {}", va, vb);
+        }
+    }
+
+    #[test]
+    fn mean_pooling() {
+        let embeddings = similarity_embeddings();
+        let embedder = SentenceEmbedder::new(&embeddings, Pooling::Mean, None).unwrap();
+
+        let embed = embedder.transform(&["Berlin", "Foo", "Potsdam"]).unwrap();
+        let check = (&embeddings.embedding("Berlin").unwrap().as_view()
+            + &embeddings.embedding("Potsdam").unwrap().as_view())
+            / 2.;
+        assert_close(&embed, &check);
+
+        assert!(embedder.transform(&["Foo", "Bar"]).is_none());
+        assert!(embedder.transform(&[]).is_none());
+    }
+
+    #[test]
+    fn max_pooling() {
+        let embeddings = similarity_embeddings();
+        let embedder = SentenceEmbedder::new(&embeddings, Pooling::Max, None).unwrap();
+
+        let embed = embedder.transform(&["Berlin", "Potsdam"]).unwrap();
+        let berlin = embeddings.embedding("Berlin").unwrap();
+        let potsdam = embeddings.embedding("Potsdam").unwrap();
+        for ((&v, &b), &p) in embed
+            .iter()
+            .zip(berlin.as_view().iter())
+            .zip(potsdam.as_view().iter())
+        {
+            assert_eq!(v, b.max(p));
+        }
+    }
+
+    #[test]
+    fn sif_weights() {
+        let embeddings = similarity_embeddings();
+        assert!(SentenceEmbedder::new(&embeddings, Pooling::sif(), None).is_err());
+
+        let embedder =
+            SentenceEmbedder::new(&embeddings, Pooling::sif(), Some(frequencies())).unwrap();
+
+        // Frequent words are down-weighted.
+        let embed = embedder.transform(&["Berlin", "Potsdam"]).unwrap();
+        let berlin = embeddings.embedding("Berlin").unwrap();
+        let potsdam = embeddings.embedding("Potsdam").unwrap();
+        let check = (&berlin.as_view() * (1e-3 / (1e-3 + 0.6)) + potsdam.as_view()) / 2.;
+        assert_close(&embed, &check);
+    }
+
+    #[test]
+    fn sif_removes_common_component() {
+        let embeddings = similarity_embeddings();
+        let mut embedder =
+            SentenceEmbedder::new(&embeddings, Pooling::sif(), Some(frequencies())).unwrap();
+        embedder.fit(SENTENCES).unwrap();
+
+        let common_components = embedder.common_components().unwrap();
+        assert_eq!(common_components.components.shape(), &[1, 100]);
+
+        let component = common_components.components.row(0);
+        assert!((component.dot(&component) - 1.).abs() < 1e-5);
+        let embeds = embedder.transform_batch(SENTENCES);
+        for embed in embeds.outer_iter() {
+            assert!(embed.dot(&component).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn usif_fit() {
+        let embeddings = similarity_embeddings();
+        let frequencies = WordFrequencies::from_counts(
+            embeddings
+                .vocab()
+                .words()
+                .iter()
+                .enumerate()
+                .map(|(idx, word)| (word.clone(), 1_000_000 / (idx as u64 + 1).pow(3))),
+        );
+
+        let mut embedder =
+            SentenceEmbedder::new(&embeddings, Pooling::usif(), Some(frequencies)).unwrap();
+        embedder.fit(SENTENCES).unwrap();
+
+        let common_components = embedder.common_components().unwrap();
+        assert_eq!(common_components.components.shape(), &[5, 100]);
+        assert!((common_components.weights.sum() - 1.).abs() < 1e-5);
+        assert!(common_components
+            .weights
+            .windows(2)
+            .into_iter()
+            .all(|w| w[0] >= w[1]));
+    }
+
+    #[test]
+    fn common_components_roundtrip() {
+        let embeddings = similarity_embeddings();
+        let mut embedder =
+            SentenceEmbedder::new(&embeddings, Pooling::sif(), Some(frequencies())).unwrap();
+        embedder.fit(SENTENCES).unwrap();
+
+        let common_components = embedder.common_components().unwrap();
+        let value = common_components.to_metadata();
+        let value = value.to_string().parse::<Value>().unwrap();
+        let restored = CommonComponents::from_metadata(&value).unwrap();
+        assert_eq!(&restored, common_components);
+
+        let mut restored_embedder =
+            SentenceEmbedder::new(&embeddings, Pooling::sif(), Some(frequencies())).unwrap();
+        restored_embedder.set_common_components(restored);
+        assert_eq!(
+            restored_embedder.transform(SENTENCES[0]),
+            embedder.transform(SENTENCES[0])
+        );
+    }
+
+    #[test]
+    fn frequencies_from_counts_file() {
+        let frequencies =
+            WordFrequencies::read_counts(Cursor::new("Berlin 3\nNew York\t1\n\n")).unwrap();
+        assert_eq!(frequencies.len(), 2);
+        assert_eq!(frequencies.prob("Berlin"), 0.75);
+        assert_eq!(frequencies.prob("New York"), 0.25);
+        assert_eq!(frequencies.prob("Foo"), 0.);
+
+        assert!(WordFrequencies::read_counts(Cursor::new("Berlin\n")).is_err());
+        assert!(WordFrequencies::read_counts(Cursor::new("Berlin many\n")).is_err());
+    }
+
+    #[test]
+    fn frequencies_from_metadata() {
+        let metadata = Metadata(
+            "[frequencies]\nBerlin = 3\nPotsdam = 1"
+                .parse::<Value>()
+                .unwrap(),
+        );
+        let frequencies = WordFrequencies::from_metadata(&metadata).unwrap().unwrap();
+        assert_eq!(frequencies.prob("Potsdam"), 0.25);
+
+        assert!(WordFrequencies::from_metadata(&Metadata::default())
+            .unwrap()
+            .is_none());
+    }
+}
@@ -2,14 +2,26 @@
 
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashSet};
+use std::ops::Range;
+use std::str::FromStr;
 
-use ndarray::{s, Array1, ArrayView1, ArrayView2};
+use failure::{format_err, Error};
+use ndarray::{s, Array1, Array2, ArrayView1, ArrayView2, Axis, Zip};
 use ordered_float::NotNan;
+use rayon::prelude::*;
 
 use crate::embeddings::Embeddings;
-use crate::storage::StorageView;
+use crate::storage::{Storage, StorageView};
 use crate::util::l2_normalize;
-use crate::vocab::Vocab;
+use crate::vocab::{Vocab, WordIndex};
+
+/// Smoothing constant of the 3CosMul objective, which avoids division
+/// by zero.
+const COS_MUL_EPSILON: f32 = 0.001;
+
+/// Number of queries that are processed in a single matrix-matrix
+/// multiplication by batch similarity queries.
+const BATCH_BLOCK_SIZE: usize = 64;
 
 /// A word with its similarity.
 ///
@@ -36,6 +48,92 @@ impl<'a> PartialOrd for WordSimilarity<'a> {
     }
 }
 
+/// Options for similarity and analogy queries.
+///
+/// The default options give the same results as `Similarity::similarity`
+/// and `Analogy::analogy`: the query words are excluded from the results
+/// and all words of the vocabulary are candidates.
+#[derive(Clone, Debug, Default)]
+pub struct QueryOptions<'a> {
+    /// Words that are excluded from the results.
+    pub skip: HashSet<&'a str>,
+
+    /// Include the query words in the results.
+    pub include_query_words: bool,
+
+    /// Restrict candidates to the words with these vocabulary indices.
+    ///
+    /// Since vocabularies are typically sorted by frequency, `0..n`
+    /// restricts the candidates to the `n` most frequent words.
+    pub candidates: Option<Range<usize>>,
+
+    /// Exclude words with a lower similarity from the results.
+    pub min_similarity: Option<f32>,
+
+    /// Only consider words in this set as candidates.
+    pub whitelist: Option<HashSet<&'a str>>,
+}
+
+impl<'a> QueryOptions<'a> {
+    /// Restrict candidates to the `n` most frequent words.
+    ///
+    /// This assumes that the vocabulary is sorted by frequency, as is
+    /// the case for embeddings trained with word2vec, GloVe, or
+    /// finalfrontier.
+    pub fn with_vocab_limit(mut self, n: usize) -> Self {
+        self.candidates = Some(0..n);
+        self
+    }
+
+    /// Check whether a word can be a candidate.
+    ///
+    /// A word is a candidate if it is in the vocabulary, its index is in
+    /// the candidate range, and it is in the whitelist (if any). Words
+    /// that are only represented through subword units are never
+    /// candidates.
+    pub fn is_candidate(&self, vocab: &impl Vocab, word: &str) -> bool {
+        let idx = match vocab.idx(word) {
+            Some(WordIndex::Word(idx)) => idx,
+            _ => return false,
+        };
+
+        let candidates = self.candidate_range(vocab.len());
+        idx >= candidates.start
+            && idx < candidates.end
+            && self
+                .whitelist
+                .as_ref()
+                .map(|whitelist| whitelist.contains(word))
+                .unwrap_or(true)
+    }
+
+    /// Get the candidate index range for a vocabulary of `vocab_len` words.
+    fn candidate_range(&self, vocab_len: usize) -> Range<usize> {
+        match self.candidates {
+            Some(ref candidates) => {
+                let end = candidates.end.min(vocab_len);
+                candidates.start.min(end)..end
+            }
+            None => 0..vocab_len,
+        }
+    }
+
+    /// Check whether a candidate should be excluded from the results.
+    fn excludes(&self, word: &str, similarity: f32, query_words: &[&str]) -> bool {
+        self.skip.contains(word)
+            || (!self.include_query_words && query_words.contains(&word))
+            || self
+                .whitelist
+                .as_ref()
+                .map(|whitelist| !whitelist.contains(word))
+                .unwrap_or(false)
+            || self
+                .min_similarity
+                .map(|min_similarity| similarity < min_similarity)
+                .unwrap_or(false)
+    }
+}
+
 /// Trait for analogy queries.
 pub trait Analogy {
     /// Perform an analogy query.
@@ -54,6 +152,42 @@ pub trait Analogy {
         word3: &str,
         limit: usize,
     ) -> Option<Vec<WordSimilarity>>;
+
+    /// Perform an analogy query using the given objective and options.
+    ///
+    /// This method returns words that answer the analogy query `word1`
+    /// is to `word2` as `word3` is to `?`, ranked using `objective`.
+    /// The candidates are selected using `options`. With
+    /// `AnalogyObjective::CosAdd` and the default options, this gives
+    /// the same results as `analogy`.
+    ///
+    /// At most, `limit` results are returned.
+    fn analogy_with_options(
+        &self,
+        word1: &str,
+        word2: &str,
+        word3: &str,
+        limit: usize,
+        objective: AnalogyObjective,
+        options: &QueryOptions,
+    ) -> Option<Vec<WordSimilarity<'_>>>;
+
+    /// Get the rank of a word in the results of an analogy query.
+    ///
+    /// This method returns the (1-based) rank that `answer` would have in
+    /// the results of `analogy_with_options`, which is computed without
+    /// sorting the candidates. `None` is returned when one of the query
+    /// words does not have an embedding or when `answer` is not a
+    /// candidate for the given options.
+    fn analogy_rank(
+        &self,
+        word1: &str,
+        word2: &str,
+        word3: &str,
+        answer: &str,
+        objective: AnalogyObjective,
+        options: &QueryOptions,
+    ) -> Option<usize>;
 }
 
 impl<V, S> Analogy for Embeddings<V, S>
@@ -72,6 +206,187 @@ where
             embeds.dot(&embed)
         })
     }
+
+    fn analogy_with_options(
+        &self,
+        word1: &str,
+        word2: &str,
+        word3: &str,
+        limit: usize,
+        objective: AnalogyObjective,
+        options: &QueryOptions,
+    ) -> Option<Vec<WordSimilarity<'_>>> {
+        let query_words = [word1, word2, word3];
+        let candidates = options.candidate_range(self.vocab().len());
+        let scores = self.analogy_scores_(word1, word2, word3, objective, candidates.clone())?;
+
+        Some(top_k(
+            &self.vocab().words()[candidates],
+            scores.view(),
+            limit,
+            |word, sim| options.excludes(word, sim, &query_words),
+        ))
+    }
+
+    fn analogy_rank(
+        &self,
+        word1: &str,
+        word2: &str,
+        word3: &str,
+        answer: &str,
+        objective: AnalogyObjective,
+        options: &QueryOptions,
+    ) -> Option<usize> {
+        let query_words = [word1, word2, word3];
+        let candidates = options.candidate_range(self.vocab().len());
+        let answer_idx = match self.vocab().idx(answer) {
+            Some(WordIndex::Word(idx)) if candidates.contains(&idx) => idx - candidates.start,
+            _ => return None,
+        };
+
+        let scores = self.analogy_scores_(word1, word2, word3, objective, candidates.clone())?;
+        let answer_score = scores[answer_idx];
+        if options.excludes(answer, answer_score, &query_words) {
+            return None;
+        }
+
+        // Count the candidates that precede the answer in the results,
+        // ties are ordered by word as in top_k.
+        let n_preceding = self.vocab().words()[candidates]
+            .iter()
+            .zip(scores.iter())
+            .filter(|&(word, &score)| {
+                (score > answer_score || (score == answer_score && word.as_str() < answer))
+                    && !options.excludes(word, score, &query_words)
+            })
+            .count();
+
+        Some(n_preceding + 1)
+    }
+}
+
+trait AnalogyPrivate {
+    /// Compute the analogy scores of the words in the vocabulary index
+    /// range `candidates`.
+    fn analogy_scores_(
+        &self,
+        word1: &str,
+        word2: &str,
+        word3: &str,
+        objective: AnalogyObjective,
+        candidates: Range<usize>,
+    ) -> Option<Array1<f32>>;
+}
+
+impl<V, S> AnalogyPrivate for Embeddings<V, S>
+where
+    V: Vocab,
+    S: StorageView,
+{
+    fn analogy_scores_(
+        &self,
+        word1: &str,
+        word2: &str,
+        word3: &str,
+        objective: AnalogyObjective,
+        candidates: Range<usize>,
+    ) -> Option<Array1<f32>> {
+        let embedding1 = self.embedding(word1)?;
+        let embedding2 = self.embedding(word2)?;
+        let embedding3 = self.embedding(word3)?;
+
+        let storage = self.storage().view();
+        // ndarray#474
+        #[allow(clippy::deref_addrof)]
+        let embeds = storage.slice(s![candidates.start..candidates.end, ..]);
+
+        let scores = match objective {
+            AnalogyObjective::CosAdd => {
+                let mut embedding =
+                    (&embedding2.as_view() - &embedding1.as_view()) + embedding3.as_view();
+                l2_normalize(embedding.view_mut());
+                embeds.dot(&embedding)
+            }
+            AnalogyObjective::CosMul => {
+                let sims1 = embeds.dot(&embedding1.as_view());
+                let sims2 = embeds.dot(&embedding2.as_view());
+                let mut sims = embeds.dot(&embedding3.as_view());
+                Zip::from(&mut sims)
+                    .and(&sims1)
+                    .and(&sims2)
+                    .apply(|sim3, &sim1, &sim2| {
+                        *sim3 = (shift_cos(sim2) * shift_cos(*sim3))
+                            / (shift_cos(sim1) + COS_MUL_EPSILON)
+                    });
+                sims
+            }
+            AnalogyObjective::PairDirection => {
+                let mut direction = &embedding2.as_view() - &embedding1.as_view();
+                l2_normalize(direction.view_mut());
+
+                // cos(x - b, d) = (x·d - b·d) / |x - b|, where
+                // |x - b|² = x·x - 2 x·b + b·b.
+                let embed3 = embedding3.as_view();
+                let embed3_dir = embed3.dot(&direction);
+                let embed3_norm = embed3.dot(&embed3);
+                let sims3 = embeds.dot(&embed3);
+                let mut sims = embeds.dot(&direction);
+                Zip::from(&mut sims)
+                    .and(embeds.genrows())
+                    .and(&sims3)
+                    .apply(|sim, embed, &sim3| {
+                        let norm = (embed.dot(&embed) - 2. * sim3 + embed3_norm).max(0.).sqrt();
+                        *sim = if norm > 0. {
+                            (*sim - embed3_dir) / norm
+                        } else {
+                            0.
+                        }
+                    });
+                sims
+            }
+        };
+
+        Some(scores)
+    }
+}
+
+/// Analogy objectives.
+///
+/// The objectives score a candidate *x* for the analogy query
+/// *a* is to *a'* as *b* is to `?`. The objectives assume that
+/// the embeddings are unit vectors, so that their dot product is
+/// the cosine similarity.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AnalogyObjective {
+    /// 3CosAdd: *cos(x, a' - a + b)*
+    CosAdd,
+
+    /// 3CosMul (Levy & Goldberg, 2014):
+    /// *cos(x, a') cos(x, b) / (cos(x, a) + ε)*
+    ///
+    /// Cosine similarities are shifted to *[0, 1]*.
+    CosMul,
+
+    /// PairDirection: *cos(x - b, a' - a)*
+    PairDirection,
+}
+
+impl FromStr for AnalogyObjective {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "3cosadd" => Ok(AnalogyObjective::CosAdd),
+            "3cosmul" => Ok(AnalogyObjective::CosMul),
+            "pairdirection" => Ok(AnalogyObjective::PairDirection),
+            unknown => Err(format_err!("Unknown analogy objective: {}", unknown)),
+        }
+    }
+}
+
+/// Shift a cosine similarity from *[-1, 1]* to *[0, 1]*.
+fn shift_cos(sim: f32) -> f32 {
+    (sim + 1.) / 2.
 }
 
 /// Trait for analogy queries with a custom similarity function.
@@ -95,6 +410,23 @@ pub trait AnalogyBy {
     ) -> Option<Vec<WordSimilarity>>
     where
         F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>;
+
+    /// Perform an analogy query using the given similarity function and
+    /// options.
+    ///
+    /// The candidates are selected using `options`. At most, `limit`
+    /// results are returned.
+    fn analogy_by_with_options<F>(
+        &self,
+        word1: &str,
+        word2: &str,
+        word3: &str,
+        limit: usize,
+        similarity: F,
+        options: &QueryOptions,
+    ) -> Option<Vec<WordSimilarity<'_>>>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>;
 }
 
 impl<V, S> AnalogyBy for Embeddings<V, S>
@@ -110,6 +442,28 @@ where
         limit: usize,
         similarity: F,
     ) -> Option<Vec<WordSimilarity>>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>,
+    {
+        self.analogy_by_with_options(
+            word1,
+            word2,
+            word3,
+            limit,
+            similarity,
+            &QueryOptions::default(),
+        )
+    }
+
+    fn analogy_by_with_options<F>(
+        &self,
+        word1: &str,
+        word2: &str,
+        word3: &str,
+        limit: usize,
+        similarity: F,
+        options: &QueryOptions,
+    ) -> Option<Vec<WordSimilarity<'_>>>
     where
         F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>,
     {
@@ -120,9 +474,96 @@ where
         let mut embedding = (&embedding2.as_view() - &embedding1.as_view()) + embedding3.as_view();
         l2_normalize(embedding.view_mut());
 
-        let skip = [word1, word2, word3].iter().cloned().collect();
+        let query_words = [word1, word2, word3];
+
+        Some(self.similarity_range_(
+            embedding.view(),
+            options.candidate_range(self.vocab().len()),
+            limit,
+            similarity,
+            |word, sim| options.excludes(word, sim, &query_words),
+        ))
+    }
+}
+
+/// Trait for similarity queries with arbitrary query embeddings.
+///
+/// In contrast to `Similarity`, the query is not a word, but any
+/// embedding, such as the centroid of the embeddings of a sentence
+/// or a vector that was mapped from another embedding space.
+pub trait EmbeddingSimilarity {
+    /// Find words that are similar to the query embedding.
+    ///
+    /// The similarity between the query and a word is defined by the dot
+    /// product of their embeddings. Words in `skip` are excluded from the
+    /// results. At most, `limit` results are returned. `None` is returned
+    /// when the dimensionality of the query is not the same as that of
+    /// the embeddings.
+    fn embedding_similarity(
+        &self,
+        query: ArrayView1<f32>,
+        skip: &HashSet<&str>,
+        limit: usize,
+    ) -> Option<Vec<WordSimilarity<'_>>>;
+}
+
+impl<V, S> EmbeddingSimilarity for Embeddings<V, S>
+where
+    V: Vocab,
+    S: StorageView,
+{
+    fn embedding_similarity(
+        &self,
+        query: ArrayView1<f32>,
+        skip: &HashSet<&str>,
+        limit: usize,
+    ) -> Option<Vec<WordSimilarity<'_>>> {
+        self.embedding_similarity_by(query, skip, limit, |embeds, embed| embeds.dot(&embed))
+    }
+}
+
+/// Trait for similarity queries with arbitrary query embeddings and a
+/// custom similarity function.
+pub trait EmbeddingSimilarityBy {
+    /// Find words that are similar to the query embedding using the given
+    /// similarity function.
+    ///
+    /// The similarity function should return, given the embeddings matrix
+    /// and the query embedding a vector of similarity scores. Words in
+    /// `skip` are excluded from the results. At most, `limit` results are
+    /// returned. `None` is returned when the dimensionality of the query
+    /// is not the same as that of the embeddings.
+    fn embedding_similarity_by<F>(
+        &self,
+        query: ArrayView1<f32>,
+        skip: &HashSet<&str>,
+        limit: usize,
+        similarity: F,
+    ) -> Option<Vec<WordSimilarity<'_>>>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>;
+}
+
+impl<V, S> EmbeddingSimilarityBy for Embeddings<V, S>
+where
+    V: Vocab,
+    S: StorageView,
+{
+    fn embedding_similarity_by<F>(
+        &self,
+        query: ArrayView1<f32>,
+        skip: &HashSet<&str>,
+        limit: usize,
+        similarity: F,
+    ) -> Option<Vec<WordSimilarity<'_>>>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>,
+    {
+        if query.len() != self.dims() {
+            return None;
+        }
 
-        Some(self.similarity_(embedding.view(), &skip, limit, similarity))
+        Some(self.similarity_(query, skip, limit, similarity))
     }
 }
 
@@ -135,6 +576,18 @@ pub trait Similarity {
     /// calling `normalize`), this is the cosine similarity. At most, `limit`
     /// results are returned.
     fn similarity(&self, word: &str, limit: usize) -> Option<Vec<WordSimilarity>>;
+
+    /// Find words that are similar to the query word.
+    ///
+    /// The similarity between two words is defined by the dot product of
+    /// the embeddings. The candidates are selected using `options`. At
+    /// most, `limit` results are returned.
+    fn similarity_with_options(
+        &self,
+        word: &str,
+        limit: usize,
+        options: &QueryOptions,
+    ) -> Option<Vec<WordSimilarity<'_>>>;
 }
 
 impl<V, S> Similarity for Embeddings<V, S>
@@ -145,6 +598,98 @@ where
     fn similarity(&self, word: &str, limit: usize) -> Option<Vec<WordSimilarity>> {
         self.similarity_by(word, limit, |embeds, embed| embeds.dot(&embed))
     }
+
+    fn similarity_with_options(
+        &self,
+        word: &str,
+        limit: usize,
+        options: &QueryOptions,
+    ) -> Option<Vec<WordSimilarity<'_>>> {
+        self.similarity_by_with_options(word, limit, |embeds, embed| embeds.dot(&embed), options)
+    }
+}
+
+/// Trait for batched similarity queries.
+///
+/// Batched queries compute the similarities of a block of query
+/// embeddings to all embeddings with a single matrix-matrix
+/// multiplication, which is considerably faster than performing
+/// queries one by one. Blocks of queries are processed in parallel.
+pub trait BatchSimilarity {
+    /// Find words that are similar to each of the query words.
+    ///
+    /// The similarity between two words is defined by the dot product
+    /// of the embeddings. The result contains an element for each query
+    /// word, which is `None` if no embedding could be found for the
+    /// word. At most, `limit` results are returned per query word.
+    fn batch_similarity(
+        &self,
+        words: &[&str],
+        limit: usize,
+    ) -> Vec<Option<Vec<WordSimilarity<'_>>>>;
+
+    /// Find words that are similar to each of the query embeddings.
+    ///
+    /// `embeds` is a matrix with a query embedding per row. The result
+    /// contains the most similar words for each row. At most, `limit`
+    /// results are returned per query embedding. `None` is returned
+    /// when the dimensionality of the query embeddings is not the same
+    /// as that of the embeddings.
+    fn similarity_by_embeddings(
+        &self,
+        embeds: ArrayView2<f32>,
+        limit: usize,
+    ) -> Option<Vec<Vec<WordSimilarity<'_>>>>;
+}
+
+impl<V, S> BatchSimilarity for Embeddings<V, S>
+where
+    V: Vocab + Sync,
+    S: StorageView + Sync,
+{
+    fn batch_similarity(
+        &self,
+        words: &[&str],
+        limit: usize,
+    ) -> Vec<Option<Vec<WordSimilarity<'_>>>> {
+        let embeds = words
+            .iter()
+            .map(|word| self.embedding(word))
+            .collect::<Vec<_>>();
+
+        let found = embeds
+            .iter()
+            .zip(words)
+            .filter_map(|(embed, &word)| embed.as_ref().map(|embed| (word, embed)))
+            .collect::<Vec<_>>();
+
+        let mut query_matrix = Array2::zeros((found.len(), self.dims()));
+        for (mut row, (_, embed)) in query_matrix.outer_iter_mut().zip(&found) {
+            row.assign(&embed.as_view());
+        }
+
+        let skips = found.iter().map(|&(word, _)| word).collect::<Vec<_>>();
+        let mut results = self
+            .batch_similarity_(query_matrix.view(), &skips, limit)
+            .into_iter();
+
+        embeds
+            .iter()
+            .map(|embed| embed.as_ref().and_then(|_| results.next()))
+            .collect()
+    }
+
+    fn similarity_by_embeddings(
+        &self,
+        embeds: ArrayView2<f32>,
+        limit: usize,
+    ) -> Option<Vec<Vec<WordSimilarity<'_>>>> {
+        if embeds.cols() != self.dims() {
+            return None;
+        }
+
+        Some(self.batch_similarity_(embeds, &[], limit))
+    }
 }
 
 /// Trait for similarity queries with a custom similarity function.
@@ -163,6 +708,21 @@ pub trait SimilarityBy {
     ) -> Option<Vec<WordSimilarity>>
     where
         F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>;
+
+    /// Find words that are similar to the query word using the given similarity
+    /// function and options.
+    ///
+    /// The candidates are selected using `options`. At most, `limit`
+    /// results are returned.
+    fn similarity_by_with_options<F>(
+        &self,
+        word: &str,
+        limit: usize,
+        similarity: F,
+        options: &QueryOptions,
+    ) -> Option<Vec<WordSimilarity<'_>>>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>;
 }
 
 impl<V, S> SimilarityBy for Embeddings<V, S>
@@ -176,14 +736,125 @@ where
         limit: usize,
         similarity: F,
     ) -> Option<Vec<WordSimilarity>>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>,
+    {
+        self.similarity_by_with_options(word, limit, similarity, &QueryOptions::default())
+    }
+
+    fn similarity_by_with_options<F>(
+        &self,
+        word: &str,
+        limit: usize,
+        similarity: F,
+        options: &QueryOptions,
+    ) -> Option<Vec<WordSimilarity<'_>>>
     where
         F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>,
     {
         let embed = self.embedding(word)?;
-        let mut skip = HashSet::new();
-        skip.insert(word);
 
-        Some(self.similarity_(embed.as_view(), &skip, limit, similarity))
+        Some(self.similarity_range_(
+            embed.as_view(),
+            options.candidate_range(self.vocab().len()),
+            limit,
+            similarity,
+            |candidate, sim| options.excludes(candidate, sim, &[word]),
+        ))
+    }
+}
+
+/// Trait for odd-one-out queries.
+pub trait DoesntMatch {
+    /// Find the word that does not match the other words.
+    ///
+    /// This method returns the word of which the embedding is least
+    /// similar to the mean of the (normalized) embeddings of `words`.
+    /// Words without an embedding are ignored. `None` is returned when
+    /// none of the words has an embedding.
+    fn doesnt_match<'b>(&self, words: &[&'b str]) -> Option<&'b str>;
+}
+
+impl<V, S> DoesntMatch for Embeddings<V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    fn doesnt_match<'b>(&self, words: &[&'b str]) -> Option<&'b str> {
+        let mut known_words = Vec::with_capacity(words.len());
+        let mut embeds = Array2::zeros((words.len(), self.dims()));
+        for &word in words {
+            if let Some(embed) = self.embedding(word) {
+                let mut row = embeds.row_mut(known_words.len());
+                row.assign(&embed.as_view());
+                l2_normalize(row);
+                known_words.push(word);
+            }
+        }
+
+        let embeds = embeds.slice(s![..known_words.len(), ..]);
+        let mut mean = embeds.sum_axis(Axis(0));
+        l2_normalize(mean.view_mut());
+
+        let sims = embeds.dot(&mean);
+        known_words
+            .into_iter()
+            .zip(sims.iter())
+            .min_by_key(|&(_, &sim)| NotNan::new(sim).expect("Encountered NaN"))
+            .map(|(word, _)| word)
+    }
+}
+
+trait BatchSimilarityPrivate {
+    /// Perform similarity queries for a matrix of query embeddings.
+    ///
+    /// If `skip` is non-empty, it should contain a word for each query
+    /// that is excluded from the results of that query.
+    fn batch_similarity_(
+        &self,
+        embeds: ArrayView2<f32>,
+        skip: &[&str],
+        limit: usize,
+    ) -> Vec<Vec<WordSimilarity<'_>>>;
+}
+
+impl<V, S> BatchSimilarityPrivate for Embeddings<V, S>
+where
+    V: Vocab + Sync,
+    S: StorageView + Sync,
+{
+    fn batch_similarity_(
+        &self,
+        embeds: ArrayView2<f32>,
+        skip: &[&str],
+        limit: usize,
+    ) -> Vec<Vec<WordSimilarity<'_>>> {
+        let storage = self.storage().view();
+        // ndarray#474
+        #[allow(clippy::deref_addrof)]
+        let storage = storage.slice(s![0..self.vocab().len(), ..]);
+        let words = self.vocab().words();
+
+        let blocks = embeds
+            .axis_chunks_iter(Axis(0), BATCH_BLOCK_SIZE)
+            .enumerate()
+            .collect::<Vec<_>>();
+
+        let block_results = blocks
+            .into_par_iter()
+            .map(|(block_idx, block)| {
+                let sims = block.dot(&storage.t());
+                sims.outer_iter()
+                    .enumerate()
+                    .map(|(idx, sims)| {
+                        let skip_word = skip.get(block_idx * BATCH_BLOCK_SIZE + idx).cloned();
+                        top_k(words, sims, limit, |word, _| Some(word) == skip_word)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        block_results.into_iter().flatten().collect()
     }
 }
 
@@ -197,6 +868,21 @@ trait SimilarityPrivate {
     ) -> Vec<WordSimilarity>
     where
         F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>;
+
+    /// Find the words in the vocabulary index range `candidates` that
+    /// are the most similar to `embed`, excluding words for which `skip`
+    /// returns `true`.
+    fn similarity_range_<F, P>(
+        &self,
+        embed: ArrayView1<f32>,
+        candidates: Range<usize>,
+        limit: usize,
+        similarity: F,
+        skip: P,
+    ) -> Vec<WordSimilarity<'_>>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>,
+        P: Fn(&str, f32) -> bool;
 }
 
 impl<V, S> SimilarityPrivate for Embeddings<V, S>
@@ -209,54 +895,98 @@ where
         embed: ArrayView1<f32>,
         skip: &HashSet<&str>,
         limit: usize,
-        mut similarity: F,
+        similarity: F,
     ) -> Vec<WordSimilarity>
     where
         F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>,
+    {
+        self.similarity_range_(
+            embed,
+            0..self.vocab().len(),
+            limit,
+            similarity,
+            |word, _| skip.contains(word),
+        )
+    }
+
+    fn similarity_range_<F, P>(
+        &self,
+        embed: ArrayView1<f32>,
+        candidates: Range<usize>,
+        limit: usize,
+        mut similarity: F,
+        skip: P,
+    ) -> Vec<WordSimilarity<'_>>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>,
+        P: Fn(&str, f32) -> bool,
     {
         // ndarray#474
         #[allow(clippy::deref_addrof)]
         let sims = similarity(
-            self.storage().view().slice(s![0..self.vocab().len(), ..]),
+            self.storage()
+                .view()
+                .slice(s![candidates.start..candidates.end, ..]),
             embed.view(),
         );
 
-        let mut results = BinaryHeap::with_capacity(limit);
-        for (idx, &sim) in sims.iter().enumerate() {
-            let word = &self.vocab().words()[idx];
+        top_k(&self.vocab().words()[candidates], sims.view(), limit, skip)
+    }
+}
 
-            // Don't add words that we are explicitly asked to skip.
-            if skip.contains(word.as_str()) {
-                continue;
-            }
+/// Select the `limit` words with the highest similarities.
+///
+/// Words for which `skip` returns `true` given the word and its
+/// similarity are excluded.
+pub(crate) fn top_k<'a, F>(
+    words: &'a [String],
+    sims: ArrayView1<f32>,
+    limit: usize,
+    skip: F,
+) -> Vec<WordSimilarity<'a>>
+where
+    F: Fn(&str, f32) -> bool,
+{
+    let mut results = BinaryHeap::with_capacity(limit);
+    for (word, &sim) in words.iter().zip(sims.iter()) {
+        // Don't add words that we are explicitly asked to skip.
+        if skip(word, sim) {
+            continue;
+        }
+
+        let word_similarity = WordSimilarity {
+            word,
+            similarity: NotNan::new(sim).expect("Encountered NaN"),
+        };
 
-            let word_similarity = WordSimilarity {
-                word,
-                similarity: NotNan::new(sim).expect("Encountered NaN"),
-            };
-
-            if results.len() < limit {
-                results.push(word_similarity);
-            } else {
-                let mut peek = results.peek_mut().expect("Cannot peek non-empty heap");
-                if word_similarity < *peek {
-                    *peek = word_similarity
-                }
+        if results.len() < limit {
+            results.push(word_similarity);
+        } else {
+            let mut peek = results.peek_mut().expect("Cannot peek non-empty heap");
+            if word_similarity < *peek {
+                *peek = word_similarity
             }
         }
-
-        results.into_sorted_vec()
     }
+
+    results.into_sorted_vec()
 }
 
 #[cfg(test)]
 mod tests {
 
+    use std::collections::HashSet;
     use std::fs::File;
     use std::io::BufReader;
 
+    use ndarray::{stack, Array1, Array2, Axis};
+
     use crate::embeddings::Embeddings;
-    use crate::similarity::{Analogy, Similarity};
+    use crate::similarity::{
+        Analogy, AnalogyBy, AnalogyObjective, BatchSimilarity, DoesntMatch, EmbeddingSimilarity,
+        QueryOptions, Similarity, SimilarityBy, WordSimilarity,
+    };
+    use crate::vocab::Vocab;
     use crate::word2vec::ReadWord2Vec;
 
     static SIMILARITY_ORDER_STUTTGART_10: &'static [&'static str] = &[
@@ -403,6 +1133,434 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_batch_similarity() {
+        let f = File::open("testdata/similarity.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_word2vec_binary(&mut reader, true).unwrap();
+
+        // Use more queries than fit in a single block.
+        let mut words = vec!["Berlin", "Unknown", "Stuttgart"];
+        words.extend(embeddings.vocab().words().iter().map(String::as_str));
+
+        let results = embeddings.batch_similarity(&words, 10);
+        assert_eq!(results.len(), words.len());
+        assert!(results[1].is_none());
+        for (word, result) in words.iter().zip(results) {
+            let check = embeddings.similarity(word, 10);
+            assert_eq!(result.is_some(), check.is_some());
+            for (ws, check_ws) in result.iter().flatten().zip(check.iter().flatten()) {
+                assert_eq!(ws.word, check_ws.word);
+                assert!((*ws.similarity - *check_ws.similarity).abs() < 1e-5);
+            }
+        }
+
+        let result = embeddings.batch_similarity(&["Stuttgart"], 10);
+        let result = result[0].as_ref().unwrap();
+        for (idx, word_similarity) in result.iter().enumerate() {
+            assert_eq!(SIMILARITY_ORDER_STUTTGART_10[idx], word_similarity.word)
+        }
+    }
+
+    #[test]
+    fn test_similarity_by_embeddings() {
+        let f = File::open("testdata/similarity.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_word2vec_binary(&mut reader, true).unwrap();
+
+        let berlin = embeddings.embedding("Berlin").unwrap();
+        let stuttgart = embeddings.embedding("Stuttgart").unwrap();
+        let queries = stack(
+            Axis(0),
+            &[
+                berlin.as_view().insert_axis(Axis(0)),
+                stuttgart.as_view().insert_axis(Axis(0)),
+            ],
+        )
+        .unwrap();
+
+        let results = embeddings
+            .similarity_by_embeddings(queries.view(), 11)
+            .unwrap();
+        assert_eq!(results.len(), 2);
+
+        // The query words are not skipped.
+        assert_eq!(results[0][0].word, "Berlin");
+        for (idx, word_similarity) in results[0][1..].iter().enumerate() {
+            assert_eq!(SIMILARITY_ORDER[idx], word_similarity.word)
+        }
+
+        assert_eq!(results[1][0].word, "Stuttgart");
+        for (idx, word_similarity) in results[1][1..].iter().enumerate() {
+            assert_eq!(SIMILARITY_ORDER_STUTTGART_10[idx], word_similarity.word)
+        }
+
+        // Queries with a different dimensionality are rejected.
+        let queries = Array2::zeros((2, embeddings.dims() + 1));
+        assert!(embeddings
+            .similarity_by_embeddings(queries.view(), 11)
+            .is_none());
+    }
+
+    #[test]
+    fn test_embedding_similarity() {
+        let f = File::open("testdata/similarity.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_word2vec_binary(&mut reader, true).unwrap();
+
+        let berlin = embeddings.embedding("Berlin").unwrap();
+        let mut skip = HashSet::new();
+        skip.insert("Berlin");
+
+        let result = embeddings
+            .embedding_similarity(berlin.as_view(), &skip, 40)
+            .unwrap();
+        assert_eq!(40, result.len());
+        for (idx, word_similarity) in result.iter().enumerate() {
+            assert_eq!(SIMILARITY_ORDER[idx], word_similarity.word)
+        }
+
+        // Skip additional words.
+        skip.insert("Potsdam");
+        skip.insert("Leipzig");
+        let result = embeddings
+            .embedding_similarity(berlin.as_view(), &skip, 3)
+            .unwrap();
+        let words = result.iter().map(|ws| ws.word).collect::<Vec<_>>();
+        assert_eq!(words, &["Hamburg", "Dresden", "München"]);
+
+        // Queries must have the dimensionality of the embeddings.
+        let query = Array1::zeros(embeddings.dims() + 1);
+        assert!(embeddings
+            .embedding_similarity(query.view(), &skip, 3)
+            .is_none());
+    }
+
+    fn check_analogy_objective<F>(objective: AnalogyObjective, score: F)
+    where
+        F: Fn(f32, f32, f32, f32) -> f32,
+    {
+        let f = File::open("testdata/analogy.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_word2vec_binary(&mut reader, true).unwrap();
+
+        let result = embeddings
+            .analogy_with_options(
+                "Paris",
+                "Frankreich",
+                "Berlin",
+                10,
+                objective,
+                &QueryOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(10, result.len());
+
+        let paris = embeddings.embedding("Paris").unwrap();
+        let frankreich = embeddings.embedding("Frankreich").unwrap();
+        let berlin = embeddings.embedding("Berlin").unwrap();
+        let direction = &frankreich.as_view() - &paris.as_view();
+        let direction_norm = direction.dot(&direction).sqrt();
+
+        for word_similarity in result {
+            let embed = embeddings.embedding(word_similarity.word).unwrap();
+            let offset = &embed.as_view() - &berlin.as_view();
+            let pair_direction =
+                offset.dot(&direction) / (offset.dot(&offset).sqrt() * direction_norm);
+            let check = score(
+                embed.as_view().dot(&paris.as_view()),
+                embed.as_view().dot(&frankreich.as_view()),
+                embed.as_view().dot(&berlin.as_view()),
+                pair_direction,
+            );
+            assert!((*word_similarity.similarity - check).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_analogy_cos_add() {
+        let f = File::open("testdata/analogy.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_word2vec_binary(&mut reader, true).unwrap();
+
+        let result = embeddings
+            .analogy_with_options(
+                "Paris",
+                "Frankreich",
+                "Berlin",
+                40,
+                AnalogyObjective::CosAdd,
+                &QueryOptions::default(),
+            )
+            .unwrap();
+        for (idx, word_similarity) in result.iter().enumerate() {
+            assert_eq!(ANALOGY_ORDER[idx], word_similarity.word)
+        }
+    }
+
+    #[test]
+    fn test_analogy_cos_mul() {
+        check_analogy_objective(AnalogyObjective::CosMul, |sim1, sim2, sim3, _| {
+            ((sim2 + 1.) / 2. * (sim3 + 1.) / 2.) / ((sim1 + 1.) / 2. + 0.001)
+        });
+    }
+
+    #[test]
+    fn test_analogy_pair_direction() {
+        check_analogy_objective(AnalogyObjective::PairDirection, |_, _, _, pair| pair);
+    }
+
+    #[test]
+    fn test_analogy_objective_from_str() {
+        assert_eq!(
+            "3cosmul".parse::<AnalogyObjective>().unwrap(),
+            AnalogyObjective::CosMul
+        );
+        assert!("3cosdiv".parse::<AnalogyObjective>().is_err());
+    }
+
+    #[test]
+    fn test_similarity_with_options() {
+        let f = File::open("testdata/similarity.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_word2vec_binary(&mut reader, true).unwrap();
+
+        // Default options are equivalent to a plain similarity query.
+        let result = embeddings
+            .similarity_with_options("Berlin", 40, &QueryOptions::default())
+            .unwrap();
+        for (idx, word_similarity) in result.iter().enumerate() {
+            assert_eq!(SIMILARITY_ORDER[idx], word_similarity.word)
+        }
+
+        let mut options = QueryOptions {
+            include_query_words: true,
+            ..QueryOptions::default()
+        };
+        options.skip.insert("Potsdam");
+        let result = embeddings
+            .similarity_with_options("Berlin", 3, &options)
+            .unwrap();
+        let words = result.iter().map(|ws| ws.word).collect::<Vec<_>>();
+        assert_eq!(words, &["Berlin", "Hamburg", "Leipzig"]);
+
+        let mut whitelist = HashSet::new();
+        whitelist.insert("Bonn");
+        whitelist.insert("Weimar");
+        whitelist.insert("Berlin-Spandau");
+        let options = QueryOptions {
+            whitelist: Some(whitelist),
+            min_similarity: Some(result[2].similarity.into_inner() - 0.1),
+            ..QueryOptions::default()
+        };
+        let result = embeddings
+            .similarity_with_options("Berlin", 10, &options)
+            .unwrap();
+        let words = result.iter().map(|ws| ws.word).collect::<Vec<_>>();
+        assert_eq!(words, &["Bonn", "Weimar"]);
+    }
+
+    #[test]
+    fn test_similarity_candidates() {
+        let f = File::open("testdata/similarity.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_word2vec_binary(&mut reader, true).unwrap();
+
+        let n_candidates = 20;
+        let options = QueryOptions {
+            candidates: Some(0..n_candidates),
+            ..QueryOptions::default()
+        };
+        let result = embeddings
+            .similarity_with_options("Berlin", 40, &options)
+            .unwrap();
+
+        let words = embeddings.vocab().words();
+        let check = SIMILARITY_ORDER
+            .iter()
+            .filter(|&&word| words[..n_candidates].iter().any(|w| w == word))
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(result.iter().map(|ws| ws.word).collect::<Vec<_>>(), check);
+
+        // Ranges outside the vocabulary are clamped.
+        let options = QueryOptions {
+            candidates: Some(words.len()..words.len() + 10),
+            ..QueryOptions::default()
+        };
+        assert!(embeddings
+            .similarity_with_options("Berlin", 10, &options)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_doesnt_match() {
+        let f = File::open("testdata/analogy.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_word2vec_binary(&mut reader, true).unwrap();
+
+        assert_eq!(
+            embeddings.doesnt_match(&["Polen", "Dänemark", "Hannover", "Schweden", "Russland"]),
+            Some("Hannover")
+        );
+
+        // Unknown words are ignored.
+        assert_eq!(
+            embeddings.doesnt_match(&["Polen", "Foo", "Dänemark", "Hannover", "Schweden"]),
+            Some("Hannover")
+        );
+        assert_eq!(embeddings.doesnt_match(&["Foo", "Bar"]), None);
+        assert_eq!(embeddings.doesnt_match(&[]), None);
+    }
+
+    #[test]
+    fn test_vocab_limit() {
+        let f = File::open("testdata/similarity.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_word2vec_binary(&mut reader, true).unwrap();
+
+        let words = embeddings.vocab().words();
+        let options = QueryOptions::default().with_vocab_limit(10);
+        assert!(options.is_candidate(embeddings.vocab(), &words[0]));
+        assert!(options.is_candidate(embeddings.vocab(), &words[9]));
+        assert!(!options.is_candidate(embeddings.vocab(), &words[10]));
+        assert!(!options.is_candidate(embeddings.vocab(), "Foobar"));
+
+        let result = embeddings
+            .similarity_with_options(&words[0], 40, &options)
+            .unwrap();
+        assert_eq!(result.len(), 9);
+        assert!(result
+            .iter()
+            .all(|ws| options.is_candidate(embeddings.vocab(), ws.word)));
+
+        let mut whitelist = HashSet::new();
+        whitelist.insert(words[1].as_str());
+        let options = QueryOptions {
+            whitelist: Some(whitelist),
+            ..QueryOptions::default().with_vocab_limit(10)
+        };
+        assert!(options.is_candidate(embeddings.vocab(), &words[1]));
+        assert!(!options.is_candidate(embeddings.vocab(), &words[2]));
+    }
+
+    #[test]
+    fn test_analogy_with_options() {
+        let f = File::open("testdata/analogy.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_word2vec_binary(&mut reader, true).unwrap();
+
+        let mut options = QueryOptions::default();
+        options.skip.insert("Deutschland");
+        let result = embeddings
+            .analogy_with_options(
+                "Paris",
+                "Frankreich",
+                "Berlin",
+                39,
+                AnalogyObjective::CosAdd,
+                &options,
+            )
+            .unwrap();
+        for (idx, word_similarity) in result.iter().enumerate() {
+            assert_eq!(ANALOGY_ORDER[idx + 1], word_similarity.word)
+        }
+    }
+
+    #[test]
+    fn test_analogy_rank() {
+        let f = File::open("testdata/analogy.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_word2vec_binary(&mut reader, true).unwrap();
+
+        let mut options = QueryOptions::default();
+        options.skip.insert("Deutschland");
+
+        for &objective in &[
+            AnalogyObjective::CosAdd,
+            AnalogyObjective::CosMul,
+            AnalogyObjective::PairDirection,
+        ] {
+            let result = embeddings
+                .analogy_with_options(
+                    "Paris",
+                    "Frankreich",
+                    "Berlin",
+                    embeddings.vocab().len(),
+                    objective,
+                    &options,
+                )
+                .unwrap();
+            for (idx, word_similarity) in result.iter().enumerate() {
+                assert_eq!(
+                    embeddings.analogy_rank(
+                        "Paris",
+                        "Frankreich",
+                        "Berlin",
+                        word_similarity.word,
+                        objective,
+                        &options
+                    ),
+                    Some(idx + 1)
+                );
+            }
+
+            // Excluded words and unknown words are not ranked.
+            for answer in &["Deutschland", "Berlin", "Foobar"] {
+                assert_eq!(
+                    embeddings.analogy_rank(
+                        "Paris",
+                        "Frankreich",
+                        "Berlin",
+                        answer,
+                        objective,
+                        &options
+                    ),
+                    None
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_by_with_options() {
+        let f = File::open("testdata/analogy.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_word2vec_binary(&mut reader, true).unwrap();
+
+        let options = QueryOptions::default().with_vocab_limit(20);
+        let check_candidates = |results: Vec<WordSimilarity>| {
+            assert!(!results.is_empty());
+            for word_similarity in results {
+                assert!(options.is_candidate(embeddings.vocab(), word_similarity.word));
+            }
+        };
+
+        check_candidates(
+            embeddings
+                .analogy_by_with_options(
+                    "Paris",
+                    "Frankreich",
+                    "Berlin",
+                    10,
+                    |embeds, embed| embeds.dot(&embed),
+                    &options,
+                )
+                .unwrap(),
+        );
+        check_candidates(
+            embeddings
+                .similarity_by_with_options(
+                    "Berlin",
+                    10,
+                    |embeds, embed| embeds.dot(&embed),
+                    &options,
+                )
+                .unwrap(),
+        );
+    }
+
     #[test]
     fn test_analogy() {
         let f = File::open("testdata/analogy.bin").unwrap();
@@ -418,5 +1576,4 @@ mod tests {
             assert_eq!(ANALOGY_ORDER[idx], word_similarity.word)
         }
     }
-
 }
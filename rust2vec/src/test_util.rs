@@ -0,0 +1,59 @@
+//! Fixtures that are shared by unit tests.
+
+use std::fs::File;
+use std::io::BufReader;
+
+use ndarray::Array2;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+use crate::embeddings::Embeddings;
+use crate::storage::NdArray;
+use crate::util::l2_normalize;
+use crate::vocab::SimpleVocab;
+use crate::word2vec::ReadWord2Vec;
+
+/// Construct embeddings from words and a matrix with their embeddings.
+pub fn embeddings_from_rows(
+    words: &[&str],
+    storage: Array2<f32>,
+) -> Embeddings<SimpleVocab, NdArray> {
+    let words = words.iter().map(|&w| w.to_owned()).collect::<Vec<_>>();
+    Embeddings::new(None, SimpleVocab::new(words), NdArray(storage))
+}
+
+/// Random unit vector embeddings of the words *w0, w1, ...*
+pub fn random_embeddings(
+    rng: &mut impl Rng,
+    n_words: usize,
+    dims: usize,
+) -> Embeddings<SimpleVocab, NdArray> {
+    let words = (0..n_words)
+        .map(|idx| format!("w{}", idx))
+        .collect::<Vec<_>>();
+    let mut storage = Array2::from_shape_fn((n_words, dims), |_| rng.gen_range(-1., 1.));
+    for embed in storage.outer_iter_mut() {
+        l2_normalize(embed);
+    }
+
+    Embeddings::new(None, SimpleVocab::new(words), NdArray(storage))
+}
+
+/// Random matrix with a different variance along each column.
+///
+/// The values of column *i* are drawn uniformly from
+/// *[3 - scales[i], 3 + scales[i])*. The random number generator is
+/// seeded with a fixed seed, so the matrix is the same in every call.
+pub fn scaled_random_matrix(rows: usize, scales: &[f32]) -> Array2<f32> {
+    let mut rng = XorShiftRng::seed_from_u64(42);
+    Array2::from_shape_fn((rows, scales.len()), |(_, col)| {
+        3. + scales[col] * rng.gen_range(-1f32, 1.)
+    })
+}
+
+/// Read the embeddings from `testdata/similarity.bin`.
+pub fn similarity_embeddings() -> Embeddings<SimpleVocab, NdArray> {
+    let f = File::open("testdata/similarity.bin").unwrap();
+    let mut reader = BufReader::new(f);
+    Embeddings::read_word2vec_binary(&mut reader, true).unwrap()
+}
@@ -1,9 +1,12 @@
 use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use ndarray::arr1;
 
 use crate::embeddings::Embeddings;
 use crate::vocab::Vocab;
-use crate::word2vec::{ReadWord2Vec, WriteWord2Vec};
+use crate::word2vec::{ReadWord2Vec, TokenDecoding, WriteWord2Vec};
 
 #[test]
 fn test_read_word2vec_binary() {
@@ -30,3 +33,110 @@ fn test_word2vec_binary_roundtrip() {
 
     assert_eq!(check, output);
 }
+
+fn word2vec_bytes(words: &[&[u8]], newlines: bool) -> Vec<u8> {
+    let mut data = format!("{} 2\n", words.len()).into_bytes();
+    for (idx, word) in words.iter().enumerate() {
+        data.extend_from_slice(word);
+        data.push(b' ');
+        data.write_f32::<LittleEndian>(idx as f32).unwrap();
+        data.write_f32::<LittleEndian>(1.).unwrap();
+        if newlines {
+            data.push(b'\n');
+        }
+    }
+    data
+}
+
+#[test]
+fn test_read_word2vec_binary_without_newlines() {
+    let data = word2vec_bytes(&[b"foo", b"bar"], false);
+    let embeddings = Embeddings::read_word2vec_binary(&mut Cursor::new(data), false).unwrap();
+    assert_eq!(embeddings.vocab().words(), &["foo", "bar"]);
+    assert_eq!(
+        embeddings.embedding("bar").unwrap().as_view(),
+        arr1(&[1., 1.])
+    );
+}
+
+#[test]
+fn test_read_word2vec_binary_trailing_blank_row() {
+    let mut data = word2vec_bytes(&[b"foo", b"bar"], true);
+    data[0] = b'3';
+    data.push(b'\n');
+    let embeddings = Embeddings::read_word2vec_binary(&mut Cursor::new(data), false).unwrap();
+    assert_eq!(embeddings.vocab().words(), &["foo", "bar"]);
+}
+
+#[test]
+fn test_read_word2vec_binary_invalid_utf8() {
+    let data = word2vec_bytes(&[b"caf\xe9", b"bar", b"caf\xe8"], true);
+
+    let err = Embeddings::read_word2vec_binary(&mut Cursor::new(data.clone()), false)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("byte offset 4"));
+
+    let (embeddings, n_invalid) = Embeddings::read_word2vec_binary_decoding(
+        &mut Cursor::new(data.clone()),
+        false,
+        TokenDecoding::Skip,
+    )
+    .unwrap();
+    assert_eq!(n_invalid, 2);
+    assert_eq!(embeddings.vocab().words(), &["bar"]);
+    assert_eq!(
+        embeddings.embedding("bar").unwrap().as_view(),
+        arr1(&[1., 1.])
+    );
+
+    let (embeddings, n_invalid) = Embeddings::read_word2vec_binary_decoding(
+        &mut Cursor::new(data.clone()),
+        false,
+        "latin1".parse().unwrap(),
+    )
+    .unwrap();
+    assert_eq!(n_invalid, 2);
+    assert_eq!(embeddings.vocab().words(), &["café", "bar", "cafè"]);
+
+    // Lossy decoding maps both invalid tokens to the same word, the
+    // second occurrence is skipped.
+    let (embeddings, n_invalid) = Embeddings::read_word2vec_binary_decoding(
+        &mut Cursor::new(data),
+        false,
+        TokenDecoding::Lossy,
+    )
+    .unwrap();
+    assert_eq!(n_invalid, 2);
+    assert_eq!(embeddings.vocab().words(), &["caf\u{fffd}", "bar"]);
+}
+
+#[test]
+fn test_read_word2vec_binary_decoded_word_in_vocab() {
+    // The lossy decoding of the second token is equal to the first token.
+    let data = word2vec_bytes(&["caf\u{fffd}".as_bytes(), b"caf\xe9", b"bar"], true);
+
+    let (embeddings, n_invalid) = Embeddings::read_word2vec_binary_decoding(
+        &mut Cursor::new(data),
+        false,
+        TokenDecoding::Lossy,
+    )
+    .unwrap();
+    assert_eq!(n_invalid, 1);
+    assert_eq!(embeddings.vocab().words(), &["caf\u{fffd}", "bar"]);
+    assert_eq!(embeddings.storage().0.shape(), &[2, 2]);
+    assert_eq!(
+        embeddings.embedding("bar").unwrap().as_view(),
+        arr1(&[2., 1.])
+    );
+}
+
+#[test]
+fn test_read_word2vec_binary_truncated() {
+    let mut data = word2vec_bytes(&[b"foo", b"bar"], true);
+    data.truncate(data.len() - 3);
+    let err = Embeddings::read_word2vec_binary(&mut Cursor::new(data), false)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("byte offset 21"));
+}
@@ -11,6 +11,9 @@
 //!    embedding matrix. This format is used by word2vec's text
 //!    output.
 //!
+//! Both readers transparently decompress gzip, xz, and zstd-compressed
+//! input (see the `compression` module).
+//!
 //! For example:
 //!
 //! ```
@@ -29,18 +32,177 @@
 //! // Look up an embedding.
 //! let embedding = embeddings.embedding("Berlin");
 //! ```
+//!
+//! Tokens that contain whitespace cannot be stored in the default
+//! format. The `*_dialect` methods read and write variants of the
+//! text formats that are described by `TextDialect`, such as
+//! tab-separated embeddings or embeddings with escaped tokens.
 
 use std::io::{BufRead, Write};
 
-use failure::{ensure, err_msg, Error, ResultExt};
+use failure::{bail, ensure, format_err, Error, ResultExt};
 use itertools::Itertools;
 use ndarray::Array2;
 
+use crate::compression::Decompress;
 use crate::embeddings::Embeddings;
 use crate::storage::{NdArray, Storage};
 use crate::util::l2_normalize;
 use crate::vocab::{SimpleVocab, Vocab};
 
+/// Field separator of the text formats.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Separator {
+    Space,
+    Tab,
+}
+
+impl Separator {
+    fn as_char(self) -> char {
+        match self {
+            Separator::Space => ' ',
+            Separator::Tab => '\t',
+        }
+    }
+}
+
+/// Dialect of the text formats.
+///
+/// The default dialect separates fields by spaces, does not escape
+/// tokens, and allows any amount of whitespace between fields.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TextDialect {
+    /// The separator between the token and the vector components.
+    pub separator: Separator,
+
+    /// Escape tokens.
+    ///
+    /// Backslashes, spaces, tabs, and newlines in tokens are written
+    /// as `\\`, `\s`, `\t`, and `\n` respectively.
+    pub escape: bool,
+
+    /// Strict reading and writing.
+    ///
+    /// In strict mode, fields must be separated by exactly one
+    /// separator and every line must have the same number of vector
+    /// components. When writing, tokens must not contain the
+    /// separator or a newline.
+    pub strict: bool,
+}
+
+impl Default for TextDialect {
+    fn default() -> Self {
+        TextDialect {
+            separator: Separator::Space,
+            escape: false,
+            strict: false,
+        }
+    }
+}
+
+impl TextDialect {
+    /// Split a line in the token and its vector components.
+    fn split_line(self, line: &str) -> (&str, Vec<&str>) {
+        let (token, components) = match self.separator {
+            Separator::Space if self.strict => split_once(line, ' '),
+            Separator::Space => {
+                let line = line.trim_start();
+                match line.find(char::is_whitespace) {
+                    Some(idx) => (&line[..idx], &line[idx..]),
+                    None => (line, ""),
+                }
+            }
+            Separator::Tab => split_once(line, '\t'),
+        };
+
+        let components = if self.strict {
+            if components.is_empty() {
+                Vec::new()
+            } else {
+                components.split(self.separator.as_char()).collect()
+            }
+        } else {
+            components.split_whitespace().collect()
+        };
+
+        (token, components)
+    }
+
+    /// Prepare a token for writing.
+    fn format_token(self, token: &str) -> Result<String, Error> {
+        if self.escape {
+            return Ok(escape_token(token));
+        }
+
+        if self.strict {
+            ensure!(
+                !token.contains(self.separator.as_char()) && !token.contains('\n'),
+                "Token contains the separator or a newline: {}",
+                token
+            );
+        }
+
+        Ok(token.to_owned())
+    }
+
+    /// Restore a token that was read.
+    fn parse_token(self, token: &str) -> Result<String, Error> {
+        if self.escape {
+            unescape_token(token)
+        } else if self.strict {
+            Ok(token.to_owned())
+        } else {
+            Ok(token.trim().to_owned())
+        }
+    }
+}
+
+fn split_once(line: &str, separator: char) -> (&str, &str) {
+    match line.find(separator) {
+        Some(idx) => (&line[..idx], &line[idx + separator.len_utf8()..]),
+        None => (line, ""),
+    }
+}
+
+fn escape_token(token: &str) -> String {
+    let mut escaped = String::with_capacity(token.len());
+
+    for c in token.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ' ' => escaped.push_str("\\s"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+fn unescape_token(token: &str) -> Result<String, Error> {
+    let mut unescaped = String::with_capacity(token.len());
+
+    let mut chars = token.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('\\') => unescaped.push('\\'),
+            Some('s') => unescaped.push(' '),
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(c) => bail!("Unknown escape sequence: \\{}", c),
+            None => bail!("Incomplete escape sequence at the end of: {}", token),
+        }
+    }
+
+    Ok(unescaped)
+}
+
 /// Method to construct `Embeddings` from a text file.
 ///
 /// This trait defines an extension to `Embeddings` to read the word embeddings
@@ -55,6 +217,13 @@ where
 {
     /// Read the embeddings from the given buffered reader.
     fn read_text(reader: &mut R, normalize: bool) -> Result<Self, Error>;
+
+    /// Read the embeddings in the given dialect from the buffered reader.
+    fn read_text_dialect(
+        reader: &mut R,
+        normalize: bool,
+        dialect: TextDialect,
+    ) -> Result<Self, Error>;
 }
 
 impl<R> ReadText<R> for Embeddings<SimpleVocab, NdArray>
@@ -62,7 +231,16 @@ where
     R: BufRead,
 {
     fn read_text(reader: &mut R, normalize: bool) -> Result<Self, Error> {
-        read_embeds(reader, None, normalize)
+        Self::read_text_dialect(reader, normalize, TextDialect::default())
+    }
+
+    fn read_text_dialect(
+        reader: &mut R,
+        normalize: bool,
+        dialect: TextDialect,
+    ) -> Result<Self, Error> {
+        let mut reader = Decompress::new(reader)?;
+        read_embeds(&mut reader, None, normalize, dialect, 0)
     }
 }
 
@@ -85,6 +263,13 @@ where
 {
     /// Read the embeddings from the given buffered reader.
     fn read_text_dims(reader: &mut R, normalize: bool) -> Result<Self, Error>;
+
+    /// Read the embeddings in the given dialect from the buffered reader.
+    fn read_text_dims_dialect(
+        reader: &mut R,
+        normalize: bool,
+        dialect: TextDialect,
+    ) -> Result<Self, Error>;
 }
 
 impl<R> ReadTextDims<R> for Embeddings<SimpleVocab, NdArray>
@@ -92,6 +277,16 @@ where
     R: BufRead,
 {
     fn read_text_dims(reader: &mut R, normalize: bool) -> Result<Self, Error> {
+        Self::read_text_dims_dialect(reader, normalize, TextDialect::default())
+    }
+
+    fn read_text_dims_dialect(
+        reader: &mut R,
+        normalize: bool,
+        dialect: TextDialect,
+    ) -> Result<Self, Error> {
+        let mut reader = Decompress::new(reader)?;
+
         let mut dims = String::new();
         reader.read_line(&mut dims)?;
 
@@ -107,14 +302,26 @@ where
             .parse::<usize>()
             .context("Cannot parse vocabulary size")?;
 
-        read_embeds(reader, Some((vocab_len, embed_len)), normalize)
+        read_embeds(
+            &mut reader,
+            Some((vocab_len, embed_len)),
+            normalize,
+            dialect,
+            1,
+        )
     }
 }
 
+/// Read embeddings in text format.
+///
+/// `line_offset` is the number of lines that were read before the
+/// embeddings, it is used for error messages.
 fn read_embeds<R>(
     reader: &mut R,
     shape: Option<(usize, usize)>,
     normalize: bool,
+    dialect: TextDialect,
+    line_offset: usize,
 ) -> Result<Embeddings<SimpleVocab, NdArray>, Error>
 where
     R: BufRead,
@@ -128,15 +335,40 @@ where
         (Vec::new(), Vec::new())
     };
 
-    for line in reader.lines() {
+    let mut line_dims = shape.map(|(_, dims)| dims);
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line_no = line_offset + idx + 1;
         let line = line?;
-        let mut parts = line.split_whitespace();
 
-        let word = parts.next().ok_or_else(|| err_msg("Empty line"))?.trim();
-        words.push(word.to_owned());
+        let (word, components) = dialect.split_line(&line);
+        ensure!(!word.is_empty(), "Line {}: empty token", line_no);
+        words.push(
+            dialect
+                .parse_token(word)
+                .with_context(|e| format!("Line {}: {}", line_no, e))?,
+        );
+
+        if dialect.strict {
+            let dims = *line_dims.get_or_insert(components.len());
+            ensure!(
+                components.len() == dims,
+                "Line {}: expected {} components, got: {}",
+                line_no,
+                dims,
+                components.len()
+            );
+        }
 
-        for part in parts {
-            data.push(part.parse()?);
+        for component in components {
+            data.push(component.parse().map_err(|e| {
+                format_err!(
+                    "Line {}: cannot parse vector component '{}': {}",
+                    line_no,
+                    component,
+                    e
+                )
+            })?);
         }
     }
 
@@ -155,6 +387,7 @@ where
         );
         (n_words, dims)
     } else {
+        ensure!(!words.is_empty(), "Embeddings file without embeddings");
         let dims = data.len() / words.len();
         (words.len(), dims)
     };
@@ -192,6 +425,9 @@ where
 {
     /// Read the embeddings from the given buffered reader.
     fn write_text(&self, writer: &mut W) -> Result<(), Error>;
+
+    /// Write the embeddings in the given dialect to the writer.
+    fn write_text_dialect(&self, writer: &mut W, dialect: TextDialect) -> Result<(), Error>;
 }
 
 impl<W, V, S> WriteText<W> for Embeddings<V, S>
@@ -202,9 +438,25 @@ where
 {
     /// Write the embeddings to the given writer.
     fn write_text(&self, write: &mut W) -> Result<(), Error> {
+        self.write_text_dialect(write, TextDialect::default())
+    }
+
+    fn write_text_dialect(&self, write: &mut W, dialect: TextDialect) -> Result<(), Error> {
+        let separator = dialect.separator.as_char().to_string();
+
         for (word, embed) in self.iter() {
-            let embed_str = embed.as_view().iter().map(ToString::to_string).join(" ");
-            writeln!(write, "{} {}", word, embed_str)?;
+            let embed_str = embed
+                .as_view()
+                .iter()
+                .map(ToString::to_string)
+                .join(&separator);
+            writeln!(
+                write,
+                "{}{}{}",
+                dialect.format_token(word)?,
+                separator,
+                embed_str
+            )?;
         }
 
         Ok(())
@@ -224,6 +476,9 @@ where
 {
     /// Write the embeddings to the given writer.
     fn write_text_dims(&self, writer: &mut W) -> Result<(), Error>;
+
+    /// Write the embeddings in the given dialect to the writer.
+    fn write_text_dims_dialect(&self, writer: &mut W, dialect: TextDialect) -> Result<(), Error>;
 }
 
 impl<W, V, S> WriteTextDims<W> for Embeddings<V, S>
@@ -233,22 +488,28 @@ where
     S: Storage,
 {
     fn write_text_dims(&self, write: &mut W) -> Result<(), Error> {
+        self.write_text_dims_dialect(write, TextDialect::default())
+    }
+
+    fn write_text_dims_dialect(&self, write: &mut W, dialect: TextDialect) -> Result<(), Error> {
         writeln!(write, "{} {}", self.vocab().len(), self.dims())?;
-        self.write_text(write)
+        self.write_text_dialect(write, dialect)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use std::fs::File;
-    use std::io::{BufReader, Read, Seek, SeekFrom};
+    use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+
+    use ndarray::arr2;
 
     use crate::embeddings::Embeddings;
     use crate::storage::{NdArray, StorageView};
     use crate::vocab::{SimpleVocab, Vocab};
     use crate::word2vec::ReadWord2Vec;
 
-    use super::{ReadText, ReadTextDims, WriteText, WriteTextDims};
+    use super::{ReadText, ReadTextDims, Separator, TextDialect, WriteText, WriteTextDims};
 
     fn read_word2vec() -> Embeddings<SimpleVocab, NdArray> {
         let f = File::open("testdata/similarity.bin").unwrap();
@@ -256,6 +517,98 @@ mod tests {
         Embeddings::read_word2vec_binary(&mut reader, false).unwrap()
     }
 
+    fn whitespace_embeddings() -> Embeddings<SimpleVocab, NdArray> {
+        Embeddings::new(
+            None,
+            SimpleVocab::new(vec![
+                "New York".to_owned(),
+                "tab\tnew\nline".to_owned(),
+                "back\\slash".to_owned(),
+            ]),
+            NdArray(arr2(&[[1., 2.], [3., 4.], [5., 6.]])),
+        )
+    }
+
+    fn dialect_roundtrip(dialect: TextDialect) {
+        let check_embeds = whitespace_embeddings();
+
+        let mut output = Vec::new();
+        check_embeds
+            .write_text_dims_dialect(&mut output, dialect)
+            .unwrap();
+
+        let embeds =
+            Embeddings::read_text_dims_dialect(&mut Cursor::new(output), false, dialect).unwrap();
+        assert_eq!(embeds.vocab().words(), check_embeds.vocab().words());
+        assert_eq!(embeds.storage().view(), check_embeds.storage().view());
+    }
+
+    #[test]
+    fn escaped_roundtrip() {
+        dialect_roundtrip(TextDialect {
+            escape: true,
+            ..TextDialect::default()
+        });
+
+        dialect_roundtrip(TextDialect {
+            escape: true,
+            strict: true,
+            ..TextDialect::default()
+        });
+    }
+
+    #[test]
+    fn tab_separated_roundtrip() {
+        let dialect = TextDialect {
+            separator: Separator::Tab,
+            escape: false,
+            strict: true,
+        };
+
+        let embeds = Embeddings::read_text_dialect(
+            &mut Cursor::new("New York\t1\t2\nBerlin\t3\t4\n"),
+            false,
+            dialect,
+        )
+        .unwrap();
+        assert_eq!(embeds.vocab().words(), &["New York", "Berlin"]);
+
+        let mut output = Vec::new();
+        embeds.write_text_dialect(&mut output, dialect).unwrap();
+        assert_eq!(output, b"New York\t1\t2\nBerlin\t3\t4\n");
+    }
+
+    #[test]
+    fn strict_reports_line() {
+        let dialect = TextDialect {
+            strict: true,
+            ..TextDialect::default()
+        };
+
+        let err = Embeddings::read_text_dims_dialect(
+            &mut Cursor::new("2 2\nNew York 1 2\nBerlin 3 4\n"),
+            false,
+            dialect,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.to_string(), "Line 2: expected 2 components, got: 3");
+
+        let err = whitespace_embeddings()
+            .write_text_dialect(&mut Vec::new(), dialect)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("New York"));
+    }
+
+    #[test]
+    fn invalid_component_reports_line() {
+        let err = Embeddings::read_text(&mut Cursor::new("Berlin 1 2\nNew York 1 2\n"), false)
+            .err()
+            .unwrap();
+        assert!(err.to_string().starts_with("Line 2: "));
+    }
+
     #[test]
     fn read_text() {
         let f = File::open("testdata/similarity.nodims").unwrap();
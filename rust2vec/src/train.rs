@@ -0,0 +1,1153 @@
+//! Training of word embeddings.
+//!
+//! This module trains word2vec embeddings (Mikolov et al., 2013) from a
+//! tokenized corpus. The corpus is a text file with one sentence per
+//! line, where tokens are separated by whitespace. Both the skip-gram
+//! and the continuous bag-of-words (CBOW) models are supported, and can
+//! be trained with negative sampling or hierarchical softmax.
+//!
+//! Subword models (Bojanowski et al., 2017) are trained with
+//! `train_subword`. In these models, a word is represented by the
+//! average of its embedding and the embeddings of its character n-grams.
+//! The n-grams are extracted and hashed in the same manner as
+//! `SubwordVocab`, so that the embeddings of unknown words can be
+//! composed from the trained n-gram embeddings.
+//!
+//! Training uses lock-free parallel SGD (Hogwild, Recht et al., 2011):
+//! the corpus is split in one part per thread and all threads update the
+//! parameters without synchronization. Frequent words are subsampled and
+//! the learning rate decays linearly over the course of training.
+//!
+//! ```no_run
+//! use rust2vec::train::{train_word2vec, Model, TrainConfig};
+//!
+//! let config = TrainConfig {
+//!     model: Model::Cbow,
+//!     n_threads: 4,
+//!     ..TrainConfig::default()
+//! };
+//!
+//! let embeddings = train_word2vec("corpus.txt", &config).unwrap();
+//! ```
+//!
+//! The `*_using` variants of the training functions take the random
+//! number generator that is used for training. With a seeded generator
+//! and a single thread, training is reproducible.
+//!
+//! The hyperparameters are stored in the `hyperparameters` table of the
+//! metadata and the word counts in the `frequencies` table. For subword
+//! models, the hyperparameters also include the n-gram lengths and the
+//! number of buckets.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use failure::{ensure, Error};
+use ndarray::{Array1, Array2, ArrayView1, ArrayViewMut1};
+use rand::{FromEntropy, Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use toml::Value;
+
+use crate::embeddings::Embeddings;
+use crate::metadata::Metadata;
+use crate::storage::NdArray;
+use crate::util::l2_normalize;
+use crate::vocab::{SimpleVocab, SubwordVocab, Vocab};
+
+/// Sigmoid inputs are clamped to this range.
+const MAX_SIGMOID: f32 = 6.0;
+
+/// Minimum fraction of the initial learning rate.
+const MIN_LEARNING_RATE: f32 = 1e-4;
+
+/// Training model.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Model {
+    /// Predict context words from the focus word.
+    SkipGram,
+
+    /// Predict the focus word from the context words.
+    Cbow,
+}
+
+impl Model {
+    fn as_str(self) -> &'static str {
+        match self {
+            Model::SkipGram => "skipgram",
+            Model::Cbow => "cbow",
+        }
+    }
+}
+
+/// Training loss.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Loss {
+    /// Logistic loss of the predicted word and sampled negative words.
+    NegativeSampling,
+
+    /// Logistic losses along the path of the predicted word in a Huffman
+    /// tree of the vocabulary.
+    HierarchicalSoftmax,
+}
+
+impl Loss {
+    fn as_str(self) -> &'static str {
+        match self {
+            Loss::NegativeSampling => "ns",
+            Loss::HierarchicalSoftmax => "hs",
+        }
+    }
+}
+
+/// Training hyperparameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrainConfig {
+    /// Training model.
+    pub model: Model,
+
+    /// Training loss.
+    pub loss: Loss,
+
+    /// Embedding dimensionality.
+    pub dims: usize,
+
+    /// Maximum number of context words on either side of the focus word.
+    ///
+    /// The size of the context is sampled uniformly from *[1, context_size]*
+    /// for every focus word.
+    pub context_size: usize,
+
+    /// Number of negative samples per predicted word.
+    pub negative_samples: usize,
+
+    /// Number of passes over the corpus.
+    pub epochs: usize,
+
+    /// Words that occur less frequently are discarded.
+    pub min_count: usize,
+
+    /// Subsampling threshold.
+    ///
+    /// A word with relative frequency *f* is kept with probability
+    /// *(√(f/t) + 1) t/f*. Subsampling is disabled when the threshold
+    /// is zero.
+    pub subsample_threshold: f32,
+
+    /// Initial learning rate.
+    pub learning_rate: f32,
+
+    /// Number of training threads.
+    pub n_threads: usize,
+}
+
+impl Default for TrainConfig {
+    fn default() -> Self {
+        TrainConfig {
+            model: Model::SkipGram,
+            loss: Loss::NegativeSampling,
+            dims: 100,
+            context_size: 5,
+            negative_samples: 5,
+            epochs: 5,
+            min_count: 5,
+            subsample_threshold: 1e-3,
+            learning_rate: 0.025,
+            n_threads: 1,
+        }
+    }
+}
+
+impl TrainConfig {
+    /// Convert the hyperparameters to a TOML table.
+    ///
+    /// The number of threads is not stored, since it does not affect the
+    /// model.
+    pub fn to_metadata(&self) -> Value {
+        let mut table = toml::value::Table::new();
+        table.insert(
+            "model".to_owned(),
+            Value::String(self.model.as_str().to_owned()),
+        );
+        table.insert(
+            "loss".to_owned(),
+            Value::String(self.loss.as_str().to_owned()),
+        );
+        table.insert("dims".to_owned(), Value::Integer(self.dims as i64));
+        table.insert(
+            "context_size".to_owned(),
+            Value::Integer(self.context_size as i64),
+        );
+        if self.loss == Loss::NegativeSampling {
+            table.insert(
+                "negative_samples".to_owned(),
+                Value::Integer(self.negative_samples as i64),
+            );
+        }
+        table.insert("epochs".to_owned(), Value::Integer(self.epochs as i64));
+        table.insert(
+            "min_count".to_owned(),
+            Value::Integer(self.min_count as i64),
+        );
+        table.insert(
+            "subsample_threshold".to_owned(),
+            Value::Float(self.subsample_threshold.into()),
+        );
+        table.insert(
+            "learning_rate".to_owned(),
+            Value::Float(self.learning_rate.into()),
+        );
+
+        Value::Table(table)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        ensure!(self.dims > 0, "Dimensionality should be at least 1");
+        ensure!(self.context_size > 0, "Context size should be at least 1");
+        ensure!(self.epochs > 0, "Number of epochs should be at least 1");
+        ensure!(self.n_threads > 0, "Number of threads should be at least 1");
+        ensure!(
+            self.learning_rate > 0.,
+            "Learning rate should be positive, was: {}",
+            self.learning_rate
+        );
+        ensure!(
+            self.subsample_threshold >= 0.,
+            "Subsampling threshold should not be negative, was: {}",
+            self.subsample_threshold
+        );
+
+        Ok(())
+    }
+}
+
+/// Subword hyperparameters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SubwordConfig {
+    /// Minimum n-gram length.
+    pub min_n: u32,
+
+    /// Maximum n-gram length.
+    pub max_n: u32,
+
+    /// The n-grams are hashed into *2^buckets_exp* buckets.
+    pub buckets_exp: u32,
+}
+
+impl Default for SubwordConfig {
+    fn default() -> Self {
+        SubwordConfig {
+            min_n: 3,
+            max_n: 6,
+            buckets_exp: 21,
+        }
+    }
+}
+
+impl SubwordConfig {
+    /// Convert the hyperparameters to a TOML table.
+    pub fn to_metadata(&self) -> Value {
+        let mut table = toml::value::Table::new();
+        table.insert("min_n".to_owned(), Value::Integer(self.min_n.into()));
+        table.insert("max_n".to_owned(), Value::Integer(self.max_n.into()));
+        table.insert(
+            "buckets_exp".to_owned(),
+            Value::Integer(self.buckets_exp.into()),
+        );
+
+        Value::Table(table)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        ensure!(self.min_n > 0, "Minimum n-gram length should be at least 1");
+        ensure!(
+            self.min_n <= self.max_n,
+            "Minimum n-gram length ({}) should not exceed the maximum n-gram length ({})",
+            self.min_n,
+            self.max_n
+        );
+        ensure!(
+            self.buckets_exp < 32,
+            "Bucket exponent should be smaller than 32, was: {}",
+            self.buckets_exp
+        );
+
+        Ok(())
+    }
+}
+
+/// Train word2vec embeddings.
+///
+/// `corpus` is a text file with one sentence per line and tokens that
+/// are separated by whitespace. The corpus is read once to construct the
+/// vocabulary and once per epoch for training.
+pub fn train_word2vec(
+    corpus: impl AsRef<Path>,
+    config: &TrainConfig,
+) -> Result<Embeddings<SimpleVocab, NdArray>, Error> {
+    train_word2vec_using(corpus, config, &mut XorShiftRng::from_entropy())
+}
+
+/// Train word2vec embeddings using the provided RNG.
+///
+/// The RNG is used to initialize the embeddings and to seed the RNGs of
+/// the training threads. Training with multiple threads is not
+/// reproducible, since the order of their updates varies between runs.
+pub fn train_word2vec_using<R>(
+    corpus: impl AsRef<Path>,
+    config: &TrainConfig,
+    rng: &mut R,
+) -> Result<Embeddings<SimpleVocab, NdArray>, Error>
+where
+    R: Rng,
+{
+    config.validate()?;
+
+    let corpus = corpus.as_ref();
+    let counts = WordCounts::count(corpus, config.min_count)?;
+
+    let inputs = (0..counts.len()).map(|idx| vec![idx]).collect();
+    let trainer = Trainer::new(config, &counts, counts.len(), inputs, rng);
+    trainer.train(corpus, rng)?;
+
+    let embeddings = trainer.into_embeddings();
+    Ok(Embeddings::new(
+        Some(counts.metadata(config.to_metadata())),
+        SimpleVocab::new(counts.words),
+        NdArray(embeddings),
+    ))
+}
+
+/// Train subword embeddings.
+///
+/// The corpus format is the same as for `train_word2vec`. The storage
+/// contains a row for every word, followed by *2^buckets_exp* n-gram
+/// rows. The row of a word is the normalized average of its word and
+/// n-gram embeddings.
+pub fn train_subword(
+    corpus: impl AsRef<Path>,
+    config: &TrainConfig,
+    subword_config: &SubwordConfig,
+) -> Result<Embeddings<SubwordVocab, NdArray>, Error> {
+    train_subword_using(
+        corpus,
+        config,
+        subword_config,
+        &mut XorShiftRng::from_entropy(),
+    )
+}
+
+/// Train subword embeddings using the provided RNG.
+///
+/// See `train_word2vec_using` for the use of the RNG.
+pub fn train_subword_using<R>(
+    corpus: impl AsRef<Path>,
+    config: &TrainConfig,
+    subword_config: &SubwordConfig,
+    rng: &mut R,
+) -> Result<Embeddings<SubwordVocab, NdArray>, Error>
+where
+    R: Rng,
+{
+    config.validate()?;
+    subword_config.validate()?;
+
+    let corpus = corpus.as_ref();
+    let counts = WordCounts::count(corpus, config.min_count)?;
+
+    let vocab = SubwordVocab::new(
+        counts.words.clone(),
+        subword_config.min_n,
+        subword_config.max_n,
+        subword_config.buckets_exp,
+    );
+    let inputs = counts
+        .words
+        .iter()
+        .enumerate()
+        .map(|(idx, word)| {
+            let mut rows = vec![idx];
+            rows.extend(vocab.subword_indices(word).unwrap_or_default());
+            rows
+        })
+        .collect();
+
+    let n_input_rows = vocab.len() + (1 << subword_config.buckets_exp);
+    let trainer = Trainer::new(config, &counts, n_input_rows, inputs, rng);
+    trainer.train(corpus, rng)?;
+
+    let mut hyperparameters = config.to_metadata();
+    if let (Some(table), Value::Table(subword_table)) =
+        (hyperparameters.as_table_mut(), subword_config.to_metadata())
+    {
+        table.extend(subword_table);
+    }
+
+    let embeddings = trainer.into_embeddings();
+    Ok(Embeddings::new(
+        Some(counts.metadata(hyperparameters)),
+        vocab,
+        NdArray(embeddings),
+    ))
+}
+
+/// Words of a corpus and their counts.
+///
+/// Words are sorted by descending frequency.
+struct WordCounts {
+    words: Vec<String>,
+    counts: Vec<u64>,
+    indices: HashMap<String, usize>,
+}
+
+impl WordCounts {
+    /// Count the words in a corpus, discarding infrequent words.
+    fn count(corpus: &Path, min_count: usize) -> Result<Self, Error> {
+        let mut counts = HashMap::new();
+
+        let mut reader = BufReader::new(File::open(corpus)?);
+        let mut line = String::new();
+        while reader.read_line(&mut line)? != 0 {
+            for token in line.split_whitespace() {
+                match counts.get_mut(token) {
+                    Some(count) => *count += 1,
+                    None => {
+                        counts.insert(token.to_owned(), 1u64);
+                    }
+                }
+            }
+            line.clear();
+        }
+
+        let mut counts = counts
+            .into_iter()
+            .filter(|&(_, count)| count >= min_count as u64)
+            .collect::<Vec<_>>();
+        counts.sort_by(|(word1, count1), (word2, count2)| {
+            count2.cmp(count1).then_with(|| word1.cmp(word2))
+        });
+        ensure!(
+            !counts.is_empty(),
+            "Corpus does not contain words that occur at least {} times",
+            min_count
+        );
+
+        let (words, counts): (Vec<_>, Vec<_>) = counts.into_iter().unzip();
+        let indices = words
+            .iter()
+            .enumerate()
+            .map(|(idx, word)| (word.clone(), idx))
+            .collect();
+
+        Ok(WordCounts {
+            words,
+            counts,
+            indices,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Metadata with the hyperparameters and word frequencies.
+    fn metadata(&self, hyperparameters: Value) -> Metadata {
+        let frequencies = self
+            .words
+            .iter()
+            .cloned()
+            .zip(
+                self.counts
+                    .iter()
+                    .map(|&count| Value::Integer(count as i64)),
+            )
+            .collect();
+
+        let mut table = toml::value::Table::new();
+        table.insert("hyperparameters".to_owned(), hyperparameters);
+        table.insert("frequencies".to_owned(), Value::Table(frequencies));
+
+        Metadata(Value::Table(table))
+    }
+
+    fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+/// Matrix that is shared between threads without synchronization.
+///
+/// Hogwild SGD relies on updates of different threads rarely touching
+/// the same parameters, so that occasional lost updates do not affect
+/// the model. Rows are accessed through a raw pointer to the matrix
+/// data, so that no references to the matrix are created while it is
+/// shared.
+struct HogwildArray2 {
+    array: Array2<f32>,
+    data: *mut f32,
+}
+
+// Safety: row views are only handed out by `row` and `row_mut`, which
+// are unsafe and leave it to the caller to accept the data races of
+// Hogwild SGD. The views stay within the allocation of `array`, since
+// row indices are checked and `array` cannot be resized or dropped
+// while it is shared.
+unsafe impl Sync for HogwildArray2 {}
+
+impl HogwildArray2 {
+    fn new(mut array: Array2<f32>) -> Self {
+        assert!(
+            array.is_standard_layout(),
+            "Hogwild matrix should be in standard layout"
+        );
+
+        let data = array.as_mut_ptr();
+        HogwildArray2 { array, data }
+    }
+
+    fn into_inner(self) -> Array2<f32> {
+        self.array
+    }
+
+    fn row_ptr(&self, idx: usize) -> *mut f32 {
+        assert!(idx < self.array.rows(), "Row index out of bounds");
+        // The offset is within the allocation, since idx < rows.
+        unsafe { self.data.add(idx * self.array.cols()) }
+    }
+
+    /// Get a view of a row.
+    ///
+    /// # Safety
+    ///
+    /// Other threads may write to the row while the view is alive, so
+    /// that the view can observe partial updates. The caller should only
+    /// use the view for reading parameters in a Hogwild update, where
+    /// such races are tolerated.
+    unsafe fn row(&self, idx: usize) -> ArrayView1<'_, f32> {
+        ArrayView1::from_shape_ptr(self.array.cols(), self.row_ptr(idx))
+    }
+
+    /// Get a mutable view of a row.
+    ///
+    /// # Safety
+    ///
+    /// The view aliases views of the same row that are held by other
+    /// threads, so concurrent updates of the row can be lost. The caller
+    /// should only use the view for a Hogwild update and drop it
+    /// afterwards.
+    unsafe fn row_mut(&self, idx: usize) -> ArrayViewMut1<'_, f32> {
+        ArrayViewMut1::from_shape_ptr(self.array.cols(), self.row_ptr(idx))
+    }
+}
+
+/// Huffman coding of the vocabulary for hierarchical softmax.
+struct HuffmanTree {
+    /// Branch that is taken at every inner node on the path to a word.
+    codes: Vec<Vec<bool>>,
+
+    /// Inner nodes on the path to a word.
+    points: Vec<Vec<usize>>,
+}
+
+impl HuffmanTree {
+    fn new(counts: &[u64]) -> Self {
+        let n_words = counts.len();
+
+        // Leaves have indices 0..n_words, inner nodes follow.
+        let mut parents = vec![0; 2 * n_words];
+        let mut branches = vec![false; 2 * n_words];
+        let mut queue = counts
+            .iter()
+            .enumerate()
+            .map(|(idx, &count)| Reverse((count, idx)))
+            .collect::<BinaryHeap<_>>();
+
+        let mut next_node = n_words;
+        while queue.len() > 1 {
+            let Reverse((count1, node1)) = queue.pop().unwrap();
+            let Reverse((count2, node2)) = queue.pop().unwrap();
+
+            parents[node1] = next_node;
+            parents[node2] = next_node;
+            branches[node2] = true;
+            queue.push(Reverse((count1 + count2, next_node)));
+
+            next_node += 1;
+        }
+
+        let root = next_node - 1;
+        let mut codes = Vec::with_capacity(n_words);
+        let mut points = Vec::with_capacity(n_words);
+        for leaf in 0..n_words {
+            let mut code = Vec::new();
+            let mut point = Vec::new();
+
+            let mut node = leaf;
+            while node != root {
+                code.push(branches[node]);
+                node = parents[node];
+                point.push(node - n_words);
+            }
+
+            code.reverse();
+            point.reverse();
+            codes.push(code);
+            points.push(point);
+        }
+
+        HuffmanTree { codes, points }
+    }
+}
+
+/// Word2vec trainer.
+///
+/// The trainer is generic in the input representation of a word: every
+/// word is represented by the average of one or more rows of the input
+/// matrix.
+struct Trainer<'a> {
+    config: &'a TrainConfig,
+    indices: &'a HashMap<String, usize>,
+    input: HogwildArray2,
+    output: HogwildArray2,
+    inputs: Vec<Vec<usize>>,
+    keep_probs: Vec<f32>,
+    negatives: Vec<f64>,
+    huffman: Option<HuffmanTree>,
+    n_processed: AtomicUsize,
+    n_total: usize,
+}
+
+impl<'a> Trainer<'a> {
+    /// Construct a trainer.
+    ///
+    /// `inputs` contains the input matrix rows of every word. The input
+    /// matrix is initialized using `rng`.
+    fn new<R>(
+        config: &'a TrainConfig,
+        counts: &'a WordCounts,
+        n_input_rows: usize,
+        inputs: Vec<Vec<usize>>,
+        rng: &mut R,
+    ) -> Self
+    where
+        R: Rng,
+    {
+        let bound = 0.5 / config.dims as f32;
+        let input = Array2::from_shape_fn((n_input_rows, config.dims), |_| {
+            rng.gen_range(-bound, bound)
+        });
+
+        let (n_outputs, huffman, negatives) = match config.loss {
+            Loss::NegativeSampling => (counts.len(), None, unigram_distribution(&counts.counts)),
+            Loss::HierarchicalSoftmax => (
+                counts.len().saturating_sub(1).max(1),
+                Some(HuffmanTree::new(&counts.counts)),
+                Vec::new(),
+            ),
+        };
+
+        let total = counts.total();
+
+        Trainer {
+            config,
+            indices: &counts.indices,
+            input: HogwildArray2::new(input),
+            output: HogwildArray2::new(Array2::zeros((n_outputs, config.dims))),
+            inputs,
+            keep_probs: keep_probabilities(&counts.counts, config.subsample_threshold),
+            negatives,
+            huffman,
+            n_processed: AtomicUsize::new(0),
+            n_total: total as usize * config.epochs,
+        }
+    }
+
+    /// Train on a corpus, using one corpus part per thread.
+    ///
+    /// The RNG of every thread is seeded using `rng`.
+    fn train<R>(&self, corpus: &Path, rng: &mut R) -> Result<(), Error>
+    where
+        R: Rng,
+    {
+        let n_parts = self.config.n_threads;
+        let pool = ThreadPoolBuilder::new().num_threads(n_parts).build()?;
+
+        let rngs = (0..n_parts)
+            .map(|_| XorShiftRng::from_rng(&mut *rng))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        pool.install(|| {
+            rngs.into_par_iter()
+                .enumerate()
+                .map(|(part, mut rng)| {
+                    for _ in 0..self.config.epochs {
+                        self.train_part(corpus, part, n_parts, &mut rng)?;
+                    }
+                    Ok(())
+                })
+                .collect::<Result<(), Error>>()
+        })
+    }
+
+    /// Train on the sentences that start in a part of the corpus.
+    fn train_part<R>(
+        &self,
+        corpus: &Path,
+        part: usize,
+        n_parts: usize,
+        rng: &mut R,
+    ) -> Result<(), Error>
+    where
+        R: Rng,
+    {
+        let mut f = File::open(corpus)?;
+        let len = f.metadata()?.len();
+        let start = len * part as u64 / n_parts as u64;
+        let end = len * (part as u64 + 1) / n_parts as u64;
+
+        // Skip to the first sentence that starts in this part.
+        let mut pos = start.saturating_sub(1);
+        f.seek(SeekFrom::Start(pos))?;
+        let mut reader = BufReader::new(f);
+        let mut line = Vec::new();
+        if start != 0 {
+            pos += reader.read_until(b'\n', &mut line)? as u64;
+        }
+
+        let mut hidden = Array1::zeros(self.config.dims);
+        let mut grad = Array1::zeros(self.config.dims);
+        let mut sentence = Vec::new();
+        while pos < end {
+            line.clear();
+            let n_read = reader.read_until(b'\n', &mut line)?;
+            if n_read == 0 {
+                break;
+            }
+            pos += n_read as u64;
+
+            let mut n_tokens = 0;
+            sentence.clear();
+            for token in String::from_utf8_lossy(&line).split_whitespace() {
+                if let Some(&idx) = self.indices.get(token) {
+                    n_tokens += 1;
+                    if rng.gen::<f32>() < self.keep_probs[idx] {
+                        sentence.push(idx);
+                    }
+                }
+            }
+
+            let n_processed = self.n_processed.fetch_add(n_tokens, Ordering::Relaxed);
+            let lr = self.learning_rate(n_processed);
+            self.train_sentence(&sentence, lr, rng, &mut hidden, &mut grad);
+        }
+
+        Ok(())
+    }
+
+    /// Linearly decaying learning rate.
+    fn learning_rate(&self, n_processed: usize) -> f32 {
+        let progress = n_processed as f32 / self.n_total.max(1) as f32;
+        self.config.learning_rate * (1. - progress).max(MIN_LEARNING_RATE)
+    }
+
+    fn train_sentence<R>(
+        &self,
+        sentence: &[usize],
+        lr: f32,
+        rng: &mut R,
+        hidden: &mut Array1<f32>,
+        grad: &mut Array1<f32>,
+    ) where
+        R: Rng,
+    {
+        let mut context = Vec::new();
+
+        for (pos, &focus) in sentence.iter().enumerate() {
+            let context_size = rng.gen_range(1, self.config.context_size + 1);
+            let start = pos.saturating_sub(context_size);
+            let end = (pos + context_size + 1).min(sentence.len());
+
+            match self.config.model {
+                Model::SkipGram => {
+                    for (_, &word) in (start..end)
+                        .zip(&sentence[start..end])
+                        .filter(|&(idx, _)| idx != pos)
+                    {
+                        self.hidden(&self.inputs[focus], hidden);
+                        grad.fill(0.);
+                        self.predict(hidden.view(), word, lr, rng, grad);
+                        self.update_inputs(&self.inputs[focus], grad.view());
+                    }
+                }
+                Model::Cbow => {
+                    context.clear();
+                    for (_, &word) in (start..end)
+                        .zip(&sentence[start..end])
+                        .filter(|&(idx, _)| idx != pos)
+                    {
+                        context.extend_from_slice(&self.inputs[word]);
+                    }
+                    if context.is_empty() {
+                        continue;
+                    }
+
+                    self.hidden(&context, hidden);
+                    grad.fill(0.);
+                    self.predict(hidden.view(), focus, lr, rng, grad);
+                    self.update_inputs(&context, grad.view());
+                }
+            }
+        }
+    }
+
+    /// Compute the average of input rows.
+    fn hidden(&self, rows: &[usize], hidden: &mut Array1<f32>) {
+        hidden.fill(0.);
+        for &row in rows {
+            // Safety: Hogwild read, the view is dropped after the sum.
+            let input = unsafe { self.input.row(row) };
+            *hidden += &input;
+        }
+        *hidden /= rows.len() as f32;
+    }
+
+    /// Update the output parameters to predict a word.
+    ///
+    /// The gradient with respect to the hidden representation is
+    /// accumulated in `grad`.
+    fn predict<R>(
+        &self,
+        hidden: ArrayView1<f32>,
+        word: usize,
+        lr: f32,
+        rng: &mut R,
+        grad: &mut Array1<f32>,
+    ) where
+        R: Rng,
+    {
+        match self.huffman {
+            Some(ref huffman) => {
+                for (&code, &point) in huffman.codes[word].iter().zip(&huffman.points[word]) {
+                    let label = if code { 0. } else { 1. };
+                    self.logistic_update(hidden, point, label, lr, grad);
+                }
+            }
+            None => {
+                self.logistic_update(hidden, word, 1., lr, grad);
+                for _ in 0..self.config.negative_samples {
+                    let negative = self.negative(rng);
+                    if negative != word {
+                        self.logistic_update(hidden, negative, 0., lr, grad);
+                    }
+                }
+            }
+        }
+    }
+
+    fn logistic_update(
+        &self,
+        hidden: ArrayView1<f32>,
+        output: usize,
+        label: f32,
+        lr: f32,
+        grad: &mut Array1<f32>,
+    ) {
+        // Safety: Hogwild update, concurrent updates of the same output
+        // row may be lost. The view is dropped at the end of the update.
+        let mut output = unsafe { self.output.row_mut(output) };
+        let g = (label - sigmoid(hidden.dot(&output))) * lr;
+        grad.scaled_add(g, &output);
+        output.scaled_add(g, &hidden);
+    }
+
+    fn update_inputs(&self, rows: &[usize], grad: ArrayView1<f32>) {
+        for &row in rows {
+            // Safety: Hogwild update, concurrent updates of the same input
+            // row may be lost.
+            unsafe { self.input.row_mut(row) }.scaled_add(1., &grad);
+        }
+    }
+
+    /// Sample a negative from the unigram distribution.
+    fn negative<R>(&self, rng: &mut R) -> usize
+    where
+        R: Rng,
+    {
+        let p = rng.gen::<f64>();
+        match self
+            .negatives
+            .binary_search_by(|cumulative| cumulative.partial_cmp(&p).unwrap())
+        {
+            Ok(idx) | Err(idx) => idx.min(self.negatives.len() - 1),
+        }
+    }
+
+    /// Get the embedding matrix.
+    ///
+    /// The embedding of a word is the normalized average of its input
+    /// rows. The remaining rows of the input matrix are retained as-is.
+    fn into_embeddings(self) -> Array2<f32> {
+        let mut embeddings = self.input.into_inner();
+
+        let word_embeddings = self
+            .inputs
+            .iter()
+            .map(|rows| {
+                let mut embedding = Array1::zeros(embeddings.cols());
+                for &row in rows {
+                    embedding += &embeddings.row(row);
+                }
+                embedding
+            })
+            .collect::<Vec<_>>();
+
+        for (idx, mut embedding) in word_embeddings.into_iter().enumerate() {
+            l2_normalize(embedding.view_mut());
+            embeddings.row_mut(idx).assign(&embedding);
+        }
+
+        embeddings
+    }
+}
+
+/// Probabilities of keeping words after subsampling.
+fn keep_probabilities(counts: &[u64], threshold: f32) -> Vec<f32> {
+    if threshold == 0. {
+        return vec![1.; counts.len()];
+    }
+
+    let total = counts.iter().sum::<u64>() as f32;
+    counts
+        .iter()
+        .map(|&count| {
+            let ratio = threshold / (count as f32 / total);
+            (ratio.sqrt() + ratio).min(1.)
+        })
+        .collect()
+}
+
+/// Cumulative unigram distribution raised to the power 3/4.
+fn unigram_distribution(counts: &[u64]) -> Vec<f64> {
+    let mut cumulative = counts
+        .iter()
+        .map(|&count| (count as f64).powf(0.75))
+        .collect::<Vec<_>>();
+
+    let mut sum = 0.;
+    for p in &mut cumulative {
+        sum += *p;
+        *p = sum;
+    }
+
+    for p in &mut cumulative {
+        *p /= sum;
+    }
+
+    cumulative
+}
+
+fn sigmoid(x: f32) -> f32 {
+    let x = x.clamp(-MAX_SIGMOID, MAX_SIGMOID);
+    1. / (1. + (-x).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{BufWriter, Write};
+    use std::path::PathBuf;
+
+    use ndarray::{Array1, Axis};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use super::{
+        train_subword, train_word2vec, train_word2vec_using, HuffmanTree, Loss, Model,
+        SubwordConfig, TrainConfig,
+    };
+    use crate::embeddings::Embeddings;
+    use crate::storage::NdArray;
+    use crate::util::l2_normalize;
+    use crate::vocab::{Vocab, WordIndex};
+
+    /// Write a corpus with two disjoint topics.
+    fn topic_corpus(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("rust2vec-{}-{}.txt", name, std::process::id()));
+        let mut writer = BufWriter::new(File::create(&path).unwrap());
+        for sentence in 0..1000 {
+            let topic = if sentence % 2 == 0 { "a" } else { "b" };
+            let words = (0..10)
+                .map(|word| format!("{}{}", topic, (sentence * 7 + word * 3) % 10))
+                .collect::<Vec<_>>();
+            writeln!(writer, "{}", words.join(" ")).unwrap();
+        }
+
+        path
+    }
+
+    /// Average similarity within and between topics.
+    fn topic_similarities<V>(embeddings: &Embeddings<V, NdArray>) -> (f32, f32)
+    where
+        V: Vocab,
+    {
+        let (mut same_topic, mut other_topic) = (0., 0.);
+        for i in 0..10 {
+            let a = embeddings.embedding(&format!("a{}", i)).unwrap();
+            for j in 0..10 {
+                let b = embeddings.embedding(&format!("b{}", j)).unwrap();
+                other_topic += a.as_view().dot(&b.as_view());
+                if i != j {
+                    let a2 = embeddings.embedding(&format!("a{}", j)).unwrap();
+                    same_topic += a.as_view().dot(&a2.as_view());
+                }
+            }
+        }
+
+        (same_topic / 90., other_topic / 100.)
+    }
+
+    fn check_training(name: &str, model: Model, loss: Loss) {
+        let corpus = topic_corpus(name);
+        let config = TrainConfig {
+            model,
+            loss,
+            dims: 10,
+            min_count: 1,
+            subsample_threshold: 0.,
+            n_threads: 2,
+            ..TrainConfig::default()
+        };
+        let embeddings = train_word2vec(&corpus, &config).unwrap();
+        fs::remove_file(&corpus).unwrap();
+
+        assert_eq!(embeddings.vocab().len(), 20);
+        assert_eq!(embeddings.storage().0.len_of(Axis(1)), 10);
+
+        let (same_topic, other_topic) = topic_similarities(&embeddings);
+        assert!(
+            same_topic > other_topic,
+            "{} <= {}",
+            same_topic,
+            other_topic
+        );
+
+        let metadata = embeddings.metadata().unwrap();
+        assert_eq!(metadata.frequencies().unwrap().unwrap()["a0"], 500);
+        assert_eq!(
+            metadata.0["hyperparameters"]["model"].as_str(),
+            Some(model.as_str())
+        );
+    }
+
+    #[test]
+    fn train_cbow_negative_sampling() {
+        check_training("cbow-ns", Model::Cbow, Loss::NegativeSampling);
+    }
+
+    #[test]
+    fn train_skipgram_hierarchical_softmax() {
+        check_training("skipgram-hs", Model::SkipGram, Loss::HierarchicalSoftmax);
+    }
+
+    #[test]
+    fn train_skipgram_negative_sampling() {
+        check_training("skipgram-ns", Model::SkipGram, Loss::NegativeSampling);
+    }
+
+    #[test]
+    fn train_with_seed_is_reproducible() {
+        let corpus = topic_corpus("seed");
+        let config = TrainConfig {
+            dims: 10,
+            epochs: 1,
+            min_count: 1,
+            n_threads: 1,
+            ..TrainConfig::default()
+        };
+        let train = |seed| {
+            train_word2vec_using(&corpus, &config, &mut XorShiftRng::seed_from_u64(seed))
+                .unwrap()
+                .storage()
+                .0
+                .clone()
+        };
+
+        let embeddings = train(42);
+        assert_eq!(train(42), embeddings);
+        assert_ne!(train(43), embeddings);
+        fs::remove_file(&corpus).unwrap();
+    }
+
+    #[test]
+    fn train_subword_skipgram() {
+        let corpus = topic_corpus("subword");
+        let config = TrainConfig {
+            dims: 10,
+            min_count: 1,
+            subsample_threshold: 0.,
+            n_threads: 2,
+            ..TrainConfig::default()
+        };
+        let subword_config = SubwordConfig {
+            min_n: 2,
+            max_n: 3,
+            buckets_exp: 10,
+        };
+        let embeddings = train_subword(&corpus, &config, &subword_config).unwrap();
+        fs::remove_file(&corpus).unwrap();
+
+        assert_eq!(embeddings.vocab().len(), 20);
+        assert_eq!(embeddings.storage().0.shape(), &[20 + 1024, 10]);
+
+        let (same_topic, other_topic) = topic_similarities(&embeddings);
+        assert!(
+            same_topic > other_topic,
+            "{} <= {}",
+            same_topic,
+            other_topic
+        );
+
+        // Unknown words are composed from the trained n-gram embeddings.
+        let indices = match embeddings.vocab().idx("a00") {
+            Some(WordIndex::Subword(indices)) => indices,
+            _ => panic!("Expected subword indices"),
+        };
+        let mut check = indices
+            .iter()
+            .map(|&idx| embeddings.storage().0.row(idx).to_owned())
+            .fold(Array1::zeros(10), |sum, row| sum + row);
+        l2_normalize(check.view_mut());
+        let embedding = embeddings.embedding("a00").unwrap();
+        assert!(embedding.as_view().all_close(&check, 1e-6));
+
+        let hyperparameters = &embeddings.metadata().unwrap().0["hyperparameters"];
+        assert_eq!(hyperparameters["min_n"].as_integer(), Some(2));
+        assert_eq!(hyperparameters["max_n"].as_integer(), Some(3));
+        assert_eq!(hyperparameters["buckets_exp"].as_integer(), Some(10));
+        assert_eq!(hyperparameters["model"].as_str(), Some("skipgram"));
+    }
+
+    #[test]
+    fn huffman_codes_are_prefix_free() {
+        let tree = HuffmanTree::new(&[10, 8, 5, 2, 1]);
+
+        // The most frequent word has the shortest code.
+        assert_eq!(tree.codes[0].len(), 1);
+        assert_eq!(tree.codes[4].len(), 4);
+
+        for (idx, code) in tree.codes.iter().enumerate() {
+            assert_eq!(code.len(), tree.points[idx].len());
+            for (other_idx, other) in tree.codes.iter().enumerate() {
+                if idx != other_idx {
+                    assert!(!other.starts_with(code));
+                }
+            }
+        }
+    }
+}
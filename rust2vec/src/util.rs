@@ -1,4 +1,6 @@
-use ndarray::ArrayViewMut1;
+use failure::{err_msg, Error};
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2, ArrayViewMut1};
+use toml::Value;
 
 pub fn l2_normalize(mut v: ArrayViewMut1<f32>) -> f32 {
     let norm = v.dot(&v).sqrt();
@@ -9,3 +11,60 @@ pub fn l2_normalize(mut v: ArrayViewMut1<f32>) -> f32 {
 
     norm
 }
+
+/// Convert a vector to a TOML array.
+pub fn vector_to_toml(v: ArrayView1<f32>) -> Value {
+    Value::Array(v.iter().map(|&v| Value::Float(v as f64)).collect())
+}
+
+/// Convert a TOML array to a vector.
+pub fn toml_to_vector(value: &Value) -> Result<Array1<f32>, Error> {
+    value
+        .as_array()
+        .ok_or_else(|| err_msg("Vector is not an array"))?
+        .iter()
+        .map(|v| {
+            v.as_float()
+                .map(|v| v as f32)
+                .ok_or_else(|| err_msg("Vector contains a non-float value"))
+        })
+        .collect()
+}
+
+/// Convert a matrix to a TOML array of rows.
+pub fn matrix_to_toml(m: ArrayView2<f32>) -> Value {
+    Value::Array(
+        m.outer_iter()
+            .map(|row| Value::Array(row.iter().map(|&v| Value::Float(v as f64)).collect()))
+            .collect(),
+    )
+}
+
+/// Convert a TOML array of rows to a matrix.
+pub fn toml_to_matrix(value: &Value) -> Result<Array2<f32>, Error> {
+    let rows = value
+        .as_array()
+        .ok_or_else(|| err_msg("Matrix is not an array of rows"))?;
+    let n_cols = rows
+        .first()
+        .and_then(Value::as_array)
+        .map(Vec::len)
+        .unwrap_or(0);
+
+    let mut data = Vec::with_capacity(rows.len() * n_cols);
+    for row in rows {
+        let row = row
+            .as_array()
+            .filter(|row| row.len() == n_cols)
+            .ok_or_else(|| err_msg("Matrix rows should be arrays of the same length"))?;
+        for v in row {
+            data.push(
+                v.as_float()
+                    .ok_or_else(|| err_msg("Matrix contains a non-float value"))?
+                    as f32,
+            );
+        }
+    }
+
+    Ok(Array2::from_shape_vec((rows.len(), n_cols), data)?)
+}
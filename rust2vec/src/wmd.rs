@@ -0,0 +1,540 @@
+//! Word Mover's Distance.
+//!
+//! The Word Mover's Distance (WMD, Kusner et al., 2015) between two
+//! documents is the minimum cumulative distance that the embeddings of
+//! the words of one document need to travel to reach the embeddings of
+//! the words of the other document. Each document is represented as a
+//! normalized bag of words, the distance between two words is the
+//! Euclidean distance between their embeddings.
+//!
+//! Computing the WMD requires solving a transportation problem, which
+//! is expensive for long documents. The relaxed WMD (RWMD) drops one
+//! of the two flow constraints of the transportation problem and is a
+//! cheap lower bound of the WMD. `WordMoversDistance::wmd_nearest` uses
+//! this bound to prune documents in nearest neighbor queries.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use ndarray::{Array1, Array2, ArrayView2};
+use ordered_float::NotNan;
+
+use crate::embeddings::Embeddings;
+use crate::storage::Storage;
+use crate::vocab::Vocab;
+
+/// Trait for Word Mover's Distance computations.
+pub trait WordMoversDistance {
+    /// Compute the Word Mover's Distance between two documents.
+    ///
+    /// The distance is computed using an exact transportation problem
+    /// solver. Its complexity is polynomial in the number of distinct
+    /// words, so it is intended for short documents. Tokens without an
+    /// embedding are ignored. Returns `None` if one of the documents
+    /// does not contain a token with an embedding.
+    fn wmd(&self, doc1: &[&str], doc2: &[&str]) -> Option<f32>;
+
+    /// Compute the relaxed Word Mover's Distance between two documents.
+    ///
+    /// The relaxed distance is a lower bound of the Word Mover's
+    /// Distance. Tokens are handled as in `wmd`.
+    fn relaxed_wmd(&self, doc1: &[&str], doc2: &[&str]) -> Option<f32>;
+
+    /// Find the documents with the smallest Word Mover's Distance.
+    ///
+    /// Returns the indices of at most `limit` documents with their
+    /// distances to `query`, sorted by increasing distance. Documents
+    /// are visited in the order of their relaxed distances, such that
+    /// the exact distance only needs to be computed for documents of
+    /// which the relaxed distance does not exceed the distances of the
+    /// nearest documents found so far.
+    fn wmd_nearest<'b, T>(
+        &self,
+        query: &[&str],
+        documents: &[T],
+        limit: usize,
+    ) -> Vec<(usize, f32)>
+    where
+        T: AsRef<[&'b str]>;
+}
+
+impl<V, S> WordMoversDistance for Embeddings<V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    fn wmd(&self, doc1: &[&str], doc2: &[&str]) -> Option<f32> {
+        let doc1 = Document::new(self, doc1)?;
+        let doc2 = Document::new(self, doc2)?;
+        Some(doc1.wmd(&doc2))
+    }
+
+    fn relaxed_wmd(&self, doc1: &[&str], doc2: &[&str]) -> Option<f32> {
+        let doc1 = Document::new(self, doc1)?;
+        let doc2 = Document::new(self, doc2)?;
+        Some(doc1.relaxed_wmd(&doc2))
+    }
+
+    fn wmd_nearest<'b, T>(&self, query: &[&str], documents: &[T], limit: usize) -> Vec<(usize, f32)>
+    where
+        T: AsRef<[&'b str]>,
+    {
+        let query = match Document::new(self, query) {
+            Some(query) => query,
+            None => return Vec::new(),
+        };
+
+        let mut candidates = documents
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, tokens)| {
+                let doc = Document::new(self, tokens.as_ref())?;
+                let bound = query.relaxed_wmd(&doc);
+                Some((idx, doc, bound))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(Ordering::Equal));
+
+        // Max-heap of the nearest documents found so far.
+        let mut nearest: BinaryHeap<(NotNan<f32>, usize)> = BinaryHeap::with_capacity(limit + 1);
+        for (idx, doc, bound) in candidates {
+            // Stop when the lower bound exceeds the largest distance of
+            // the nearest documents.
+            if nearest.len() == limit {
+                match nearest.peek() {
+                    Some(&(worst, _)) if bound < worst.into_inner() => (),
+                    _ => break,
+                }
+            }
+
+            let dist = NotNan::new(query.wmd(&doc)).expect("Encountered NaN");
+            nearest.push((dist, idx));
+            if nearest.len() > limit {
+                nearest.pop();
+            }
+        }
+
+        nearest
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(dist, idx)| (idx, dist.into_inner()))
+            .collect()
+    }
+}
+
+/// Normalized bag-of-words representation of a document.
+struct Document {
+    /// Embeddings of the distinct words.
+    embeds: Array2<f32>,
+
+    /// Word counts.
+    counts: Vec<u64>,
+}
+
+impl Document {
+    fn new<V, S>(embeddings: &Embeddings<V, S>, tokens: &[&str]) -> Option<Self>
+    where
+        V: Vocab,
+        S: Storage,
+    {
+        let mut word_indices = HashMap::new();
+        let mut rows = Vec::new();
+        let mut counts = Vec::new();
+
+        for &token in tokens {
+            if let Some(&idx) = word_indices.get(token) {
+                counts[idx] += 1;
+                continue;
+            }
+
+            if let Some(embed) = embeddings.embedding(token) {
+                word_indices.insert(token, rows.len());
+                rows.push(embed.into_owned());
+                counts.push(1);
+            }
+        }
+
+        if rows.is_empty() {
+            return None;
+        }
+
+        let mut embeds = Array2::zeros((rows.len(), embeddings.dims()));
+        for (mut row, embed) in embeds.outer_iter_mut().zip(rows) {
+            row.assign(&embed);
+        }
+
+        Some(Document { embeds, counts })
+    }
+
+    /// Get the normalized word weights.
+    fn weights(&self) -> Array1<f32> {
+        let total = self.counts.iter().sum::<u64>() as f32;
+        self.counts
+            .iter()
+            .map(|&count| count as f32 / total)
+            .collect()
+    }
+
+    /// Euclidean distances between the words of two documents.
+    fn distances(&self, other: &Document) -> Array2<f32> {
+        let norms = self
+            .embeds
+            .outer_iter()
+            .map(|e| e.dot(&e))
+            .collect::<Vec<_>>();
+        let other_norms = other
+            .embeds
+            .outer_iter()
+            .map(|e| e.dot(&e))
+            .collect::<Vec<_>>();
+
+        let mut dists = self.embeds.dot(&other.embeds.t());
+        for ((i, j), dist) in dists.indexed_iter_mut() {
+            *dist = (norms[i] + other_norms[j] - 2. * *dist).max(0.).sqrt();
+        }
+
+        dists
+    }
+
+    fn relaxed_wmd(&self, other: &Document) -> f32 {
+        let dists = self.distances(other);
+
+        let min_dists = |dists: ArrayView2<f32>| {
+            dists
+                .outer_iter()
+                .map(|row| row.iter().cloned().fold(f32::INFINITY, f32::min))
+                .collect::<Array1<f32>>()
+        };
+
+        // Every word moves to its nearest word in the other document.
+        let dist1 = min_dists(dists.view()).dot(&self.weights());
+        let dist2 = min_dists(dists.t()).dot(&other.weights());
+
+        dist1.max(dist2)
+    }
+
+    fn wmd(&self, other: &Document) -> f32 {
+        let dists = self.distances(other);
+
+        // Scale the weights to integers, such that the flows can be
+        // computed exactly.
+        let total = self.counts.iter().sum::<u64>();
+        let other_total = other.counts.iter().sum::<u64>();
+        let supplies = self
+            .counts
+            .iter()
+            .map(|&count| count * other_total)
+            .collect::<Vec<_>>();
+        let demands = other
+            .counts
+            .iter()
+            .map(|&count| count * total)
+            .collect::<Vec<_>>();
+
+        let cost = transport(&dists.mapv(f64::from), supplies, demands);
+        (cost / (total * other_total) as f64) as f32
+    }
+}
+
+/// Solve a balanced transportation problem.
+///
+/// Returns the minimum cost of transporting `supplies` to `demands`,
+/// where `costs[[i, j]]` is the cost of transporting one unit from
+/// supply `i` to demand `j`. The problem is solved as a min-cost flow
+/// problem using successive shortest paths. Dijkstra's algorithm is
+/// applied to reduced costs, to handle the negative costs of residual
+/// edges.
+fn transport(costs: &Array2<f64>, mut supplies: Vec<u64>, mut demands: Vec<u64>) -> f64 {
+    let (n_supplies, n_demands) = costs.dim();
+    let n_nodes = n_supplies + n_demands;
+
+    let mut flows = Array2::<u64>::zeros((n_supplies, n_demands));
+
+    // Node potentials: supplies are nodes 0..n_supplies, demands are
+    // nodes n_supplies..n_nodes.
+    let mut potentials = vec![0f64; n_nodes];
+
+    loop {
+        // Dijkstra from the (implicit) source node, which is connected to
+        // supplies with remaining supply.
+        let mut dists = vec![f64::INFINITY; n_nodes];
+        let mut preds = vec![None; n_nodes];
+        let mut visited = vec![false; n_nodes];
+
+        for (i, &supply) in supplies.iter().enumerate() {
+            if supply > 0 {
+                dists[i] = (-potentials[i]).max(0.);
+            }
+        }
+
+        while let Some(node) = (0..n_nodes)
+            .filter(|&node| !visited[node] && dists[node].is_finite())
+            .min_by(|&a, &b| dists[a].partial_cmp(&dists[b]).unwrap_or(Ordering::Equal))
+        {
+            visited[node] = true;
+
+            if node < n_supplies {
+                // Forward edges from a supply to all demands.
+                for j in 0..n_demands {
+                    let target = n_supplies + j;
+                    let reduced =
+                        (costs[[node, j]] + potentials[node] - potentials[target]).max(0.);
+                    if dists[node] + reduced < dists[target] {
+                        dists[target] = dists[node] + reduced;
+                        preds[target] = Some(node);
+                    }
+                }
+            } else {
+                // Residual edges from a demand to the supplies that send
+                // flow to it.
+                let j = node - n_supplies;
+                for i in 0..n_supplies {
+                    if flows[[i, j]] == 0 {
+                        continue;
+                    }
+
+                    let reduced = (-costs[[i, j]] + potentials[node] - potentials[i]).max(0.);
+                    if dists[node] + reduced < dists[i] {
+                        dists[i] = dists[node] + reduced;
+                        preds[i] = Some(node);
+                    }
+                }
+            }
+        }
+
+        // Pick the demand with remaining demand that has the cheapest path.
+        let target = (0..n_demands)
+            .filter(|&j| demands[j] > 0 && dists[n_supplies + j].is_finite())
+            .min_by(|&a, &b| {
+                let cost_a = dists[n_supplies + a] + potentials[n_supplies + a];
+                let cost_b = dists[n_supplies + b] + potentials[n_supplies + b];
+                cost_a.partial_cmp(&cost_b).unwrap_or(Ordering::Equal)
+            });
+        let target = match target {
+            Some(j) => n_supplies + j,
+            None => break,
+        };
+
+        // Find the path and its bottleneck.
+        let mut path = vec![target];
+        while let Some(pred) = preds[*path.last().unwrap()] {
+            path.push(pred);
+        }
+        path.reverse();
+
+        let mut amount = supplies[path[0]].min(demands[target - n_supplies]);
+        for edge in path.windows(2) {
+            if edge[0] >= n_supplies {
+                // Residual edge, bounded by the current flow.
+                amount = amount.min(flows[[edge[1], edge[0] - n_supplies]]);
+            }
+        }
+
+        for edge in path.windows(2) {
+            if edge[0] < n_supplies {
+                flows[[edge[0], edge[1] - n_supplies]] += amount;
+            } else {
+                flows[[edge[1], edge[0] - n_supplies]] -= amount;
+            }
+        }
+        supplies[path[0]] -= amount;
+        demands[target - n_supplies] -= amount;
+
+        for (potential, &dist) in potentials.iter_mut().zip(&dists) {
+            if dist.is_finite() {
+                *potential += dist;
+            }
+        }
+    }
+
+    flows
+        .indexed_iter()
+        .map(|((i, j), &flow)| flow as f64 * costs[[i, j]])
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{arr2, Array2};
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    use super::{transport, WordMoversDistance};
+    use crate::embeddings::Embeddings;
+    use crate::storage::NdArray;
+    use crate::test_util::{embeddings_from_rows, similarity_embeddings};
+    use crate::vocab::SimpleVocab;
+
+    fn toy_embeddings() -> Embeddings<SimpleVocab, NdArray> {
+        embeddings_from_rows(
+            &["a", "b", "c", "d"],
+            arr2(&[[0., 0.], [1., 0.], [0., 1.], [3., 0.]]),
+        )
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    /// Solve a transportation problem by trying every assignment of
+    /// supply units to demand units.
+    fn brute_force_transport(costs: &Array2<f64>, supplies: &[u64], demands: &[u64]) -> f64 {
+        fn units(amounts: &[u64]) -> Vec<usize> {
+            amounts
+                .iter()
+                .enumerate()
+                .flat_map(|(idx, &amount)| vec![idx; amount as usize])
+                .collect()
+        }
+
+        fn assign(
+            costs: &Array2<f64>,
+            sources: &[usize],
+            sinks: &[usize],
+            used: &mut [bool],
+        ) -> f64 {
+            let (&source, rest) = match sources.split_first() {
+                Some(split) => split,
+                None => return 0.,
+            };
+
+            let mut best = f64::INFINITY;
+            for (idx, &sink) in sinks.iter().enumerate() {
+                if used[idx] {
+                    continue;
+                }
+
+                used[idx] = true;
+                best = best.min(costs[[source, sink]] + assign(costs, rest, sinks, used));
+                used[idx] = false;
+            }
+
+            best
+        }
+
+        let sinks = units(demands);
+        assign(
+            costs,
+            &units(supplies),
+            &sinks,
+            &mut vec![false; sinks.len()],
+        )
+    }
+
+    /// Random amounts that sum to `total`, every amount is at least one.
+    fn random_amounts(rng: &mut XorShiftRng, n: usize, total: usize) -> Vec<u64> {
+        let mut amounts = vec![1; n];
+        for _ in n..total {
+            amounts[rng.gen_range(0, n)] += 1;
+        }
+        amounts
+    }
+
+    #[test]
+    fn wmd_toy() {
+        let embeddings = toy_embeddings();
+
+        // a -> c: 1/2, a -> d: 1/6, b -> d: 1/3
+        assert_close(
+            embeddings.wmd(&["a", "a", "b"], &["c", "d"]).unwrap(),
+            0.5 + 0.5 + 2. / 3.,
+        );
+        assert_close(
+            embeddings
+                .relaxed_wmd(&["a", "a", "b"], &["c", "d"])
+                .unwrap(),
+            1.5,
+        );
+
+        // The distance is symmetric.
+        assert_close(
+            embeddings.wmd(&["c", "d"], &["a", "b", "a"]).unwrap(),
+            0.5 + 0.5 + 2. / 3.,
+        );
+
+        // Unknown tokens are ignored.
+        assert_close(embeddings.wmd(&["a", "x"], &["b"]).unwrap(), 1.);
+        assert!(embeddings.wmd(&["x"], &["b"]).is_none());
+        assert!(embeddings.relaxed_wmd(&["a"], &[]).is_none());
+    }
+
+    #[test]
+    fn transport_reroutes_flow() {
+        // The greedy assignment of the first supply to the first demand
+        // has to be undone to find the optimum.
+        let costs = arr2(&[[1., 2.], [1., 10.]]);
+        assert_eq!(transport(&costs, vec![1, 1], vec![1, 1]), 3.);
+
+        let costs = Array2::from_shape_fn((3, 3), |(i, j)| ((i * 7 + j * 3) % 5) as f64);
+        // Optimal assignment: (0, 0), (1, 1), (2, 2) with costs 0, 0, 0.
+        assert_eq!(transport(&costs, vec![2, 2, 2], vec![2, 2, 2]), 0.);
+    }
+
+    #[test]
+    fn transport_matches_brute_force() {
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        for _ in 0..200 {
+            let n_supplies = rng.gen_range(1, 4);
+            let n_demands = rng.gen_range(1, 4);
+            let total = rng.gen_range(n_supplies.max(n_demands), 7);
+            let supplies = random_amounts(&mut rng, n_supplies, total);
+            let demands = random_amounts(&mut rng, n_demands, total);
+            let costs = Array2::from_shape_fn((n_supplies, n_demands), |_| rng.gen_range(0., 10.));
+
+            let cost = transport(&costs, supplies.clone(), demands.clone());
+            let check = brute_force_transport(&costs, &supplies, &demands);
+            assert!(
+                (cost - check).abs() < 1e-9,
+                "{} != {}, costs: {}, supplies: {:?}, demands: {:?}",
+                cost,
+                check,
+                costs,
+                supplies,
+                demands
+            );
+        }
+    }
+
+    #[test]
+    fn wmd_bounds() {
+        let embeddings = similarity_embeddings();
+
+        let doc1 = ["Berlin", "Potsdam", "Hamburg", "Berlin"];
+        let doc2 = ["Stuttgart", "Karlsruhe", "Mannheim"];
+
+        assert_close(embeddings.wmd(&doc1, &doc1).unwrap(), 0.);
+        assert_close(embeddings.relaxed_wmd(&doc1, &doc1).unwrap(), 0.);
+
+        let wmd = embeddings.wmd(&doc1, &doc2).unwrap();
+        assert!(wmd > 0.);
+        assert!(embeddings.relaxed_wmd(&doc1, &doc2).unwrap() <= wmd + 1e-5);
+    }
+
+    #[test]
+    fn wmd_nearest() {
+        let embeddings = similarity_embeddings();
+
+        let query = ["Berlin", "Potsdam"];
+        let documents: &[&[&str]] = &[
+            &["Stuttgart", "Karlsruhe"],
+            &["Berlin", "Potsdam"],
+            &["Foo"],
+            &["Berlin", "Hamburg", "Leipzig"],
+            &["München", "Dresden"],
+        ];
+
+        let mut check = documents
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, doc)| embeddings.wmd(&query, doc).map(|dist| (idx, dist)))
+            .collect::<Vec<_>>();
+        check.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        check.truncate(3);
+
+        let nearest = embeddings.wmd_nearest(&query, documents, 3);
+        assert_eq!(nearest, check);
+        assert_eq!(nearest[0].0, 1);
+
+        assert!(embeddings.wmd_nearest(&query, documents, 0).is_empty());
+    }
+}
@@ -20,30 +20,91 @@
 //! let embedding = embeddings.embedding("Berlin");
 //! ```
 
-use std::io::{BufRead, Write};
+use std::collections::HashSet;
+use std::io::{self, BufRead, Read, Write};
 use std::mem;
 use std::slice::from_raw_parts_mut;
+use std::str::FromStr;
 
 use byteorder::{LittleEndian, WriteBytesExt};
-use failure::{err_msg, Error};
+use encoding_rs::Encoding;
+use failure::{bail, err_msg, format_err, Error, ResultExt};
 use ndarray::{Array2, Axis};
 
+use crate::compression::Decompress;
 use crate::embeddings::Embeddings;
 use crate::storage::{NdArray, Storage};
 use crate::util::l2_normalize;
 use crate::vocab::{SimpleVocab, Vocab};
 
+/// Decoding of tokens that are not valid UTF-8.
+///
+/// Some word2vec files, such as the Google News vectors, contain
+/// tokens that are not valid UTF-8. This enum determines how such
+/// tokens are handled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TokenDecoding {
+    /// Fail when a token is not valid UTF-8.
+    Strict,
+
+    /// Replace invalid UTF-8 sequences by the replacement character.
+    Lossy,
+
+    /// Skip embeddings of tokens that are not valid UTF-8.
+    Skip,
+
+    /// Decode tokens that are not valid UTF-8 using an encoding.
+    Encoding(&'static Encoding),
+}
+
+impl FromStr for TokenDecoding {
+    type Err = Error;
+
+    /// Parse a token decoding.
+    ///
+    /// Besides `strict`, `lossy`, and `skip`, any encoding label from
+    /// the [Encoding Standard](https://encoding.spec.whatwg.org/#names-and-labels),
+    /// such as `latin1`, is accepted.
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "strict" => Ok(TokenDecoding::Strict),
+            "lossy" => Ok(TokenDecoding::Lossy),
+            "skip" => Ok(TokenDecoding::Skip),
+            label => Encoding::for_label(label.as_bytes())
+                .map(TokenDecoding::Encoding)
+                .ok_or_else(|| format_err!("Unknown token decoding: {}", label)),
+        }
+    }
+}
+
 /// Method to construct `Embeddings` from a word2vec binary file.
 ///
 /// This trait defines an extension to `Embeddings` to read the word embeddings
 /// from a file in word2vec binary format.
+///
+/// Embeddings may or may not be followed by a newline and the file may
+/// end with a blank row. If the file is malformed, the error contains
+/// the byte offset (in the decompressed data) of the problem.
 pub trait ReadWord2Vec<R>
 where
     Self: Sized,
     R: BufRead,
 {
     /// Read the embeddings from the given buffered reader.
+    ///
+    /// Reading fails if a token is not valid UTF-8.
     fn read_word2vec_binary(reader: &mut R, normalize: bool) -> Result<Self, Error>;
+
+    /// Read the embeddings from the given buffered reader.
+    ///
+    /// Tokens that are not valid UTF-8 are handled according to
+    /// `decoding`. Returns the embeddings together with the number of
+    /// tokens that were not valid UTF-8.
+    fn read_word2vec_binary_decoding(
+        reader: &mut R,
+        normalize: bool,
+        decoding: TokenDecoding,
+    ) -> Result<(Self, usize), Error>;
 }
 
 impl<R> ReadWord2Vec<R> for Embeddings<SimpleVocab, NdArray>
@@ -51,26 +112,74 @@ where
     R: BufRead,
 {
     fn read_word2vec_binary(reader: &mut R, normalize: bool) -> Result<Self, Error> {
-        let n_words = read_number(reader, b' ')?;
-        let embed_len = read_number(reader, b'\n')?;
+        Self::read_word2vec_binary_decoding(reader, normalize, TokenDecoding::Strict)
+            .map(|(embeddings, _)| embeddings)
+    }
+
+    fn read_word2vec_binary_decoding(
+        reader: &mut R,
+        normalize: bool,
+        decoding: TokenDecoding,
+    ) -> Result<(Self, usize), Error> {
+        let reader = &mut OffsetReader::new(Decompress::new(reader)?);
+
+        let n_words = read_number(reader, b' ').context("Cannot read vocabulary size")?;
+        let embed_len = read_number(reader, b'\n').context("Cannot read embedding size")?;
 
         let mut matrix = Array2::zeros((n_words, embed_len));
         let mut words = Vec::with_capacity(n_words);
+        let mut seen_words = HashSet::new();
+        let mut n_invalid = 0;
 
         for idx in 0..n_words {
-            let word = read_string(reader, b' ')?;
-            let word = word.trim();
-            words.push(word.to_owned());
+            // Rows may or may not be separated by a newline.
+            skip_whitespace(reader)?;
+
+            // Some files have a trailing blank row that is included in
+            // the vocabulary size.
+            if idx == n_words - 1 && reader.fill_buf()?.is_empty() {
+                break;
+            }
 
-            let mut embedding = matrix.index_axis_mut(Axis(0), idx);
+            let offset = reader.offset();
+            let token = read_token(reader)?;
+
+            let word = match String::from_utf8(token) {
+                Ok(word) => Some(word),
+                Err(err) => {
+                    n_invalid += 1;
+                    decode_token(err.as_bytes(), decoding, offset)?
+                }
+            }
+            .map(|word| word.trim().to_owned())
+            // Decoding could result in a word that is already in the
+            // vocabulary, only the first occurrence is retained.
+            .filter(|word| seen_words.insert(word.clone()));
+
+            let mut embedding = matrix.index_axis_mut(Axis(0), words.len());
 
             {
-                let mut embedding_raw = match embedding.as_slice_mut() {
+                let embedding_raw = match embedding.as_slice_mut() {
                     Some(s) => unsafe { typed_to_bytes(s) },
                     None => return Err(err_msg("Matrix not contiguous")),
                 };
-                reader.read_exact(&mut embedding_raw)?;
+
+                let offset = reader.offset();
+                reader.read_exact(embedding_raw).with_context(|e| {
+                    format!("Cannot read embedding at byte offset {}: {}", offset, e)
+                })?;
             }
+
+            if let Some(word) = word {
+                words.push(word);
+            }
+        }
+
+        // Remove the rows of skipped and blank tokens.
+        if words.len() != n_words {
+            let mut data = matrix.into_raw_vec();
+            data.truncate(words.len() * embed_len);
+            matrix = Array2::from_shape_vec((words.len(), embed_len), data)?;
         }
 
         if normalize {
@@ -79,24 +188,119 @@ where
             }
         }
 
-        Ok(Embeddings::new(
-            None,
-            SimpleVocab::new(words),
-            NdArray(matrix),
+        Ok((
+            Embeddings::new(None, SimpleVocab::new(words), NdArray(matrix)),
+            n_invalid,
         ))
     }
 }
 
-fn read_number(reader: &mut BufRead, delim: u8) -> Result<usize, Error> {
-    let field_str = read_string(reader, delim)?;
-    Ok(field_str.parse()?)
+/// Reader that keeps track of the current byte offset.
+struct OffsetReader<R> {
+    inner: R,
+    offset: u64,
+}
+
+impl<R> OffsetReader<R> {
+    fn new(inner: R) -> Self {
+        OffsetReader { inner, offset: 0 }
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl<R> Read for OffsetReader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n_read = self.inner.read(buf)?;
+        self.offset += n_read as u64;
+        Ok(n_read)
+    }
+}
+
+impl<R> BufRead for OffsetReader<R>
+where
+    R: BufRead,
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.offset += amt as u64;
+        self.inner.consume(amt)
+    }
+}
+
+fn decode_token(
+    token: &[u8],
+    decoding: TokenDecoding,
+    offset: u64,
+) -> Result<Option<String>, Error> {
+    match decoding {
+        TokenDecoding::Strict => Err(format_err!(
+            "Token at byte offset {} is not valid UTF-8: {}",
+            offset,
+            String::from_utf8_lossy(token)
+        )),
+        TokenDecoding::Lossy => Ok(Some(String::from_utf8_lossy(token).into_owned())),
+        TokenDecoding::Skip => Ok(None),
+        TokenDecoding::Encoding(encoding) => Ok(Some(
+            encoding.decode_without_bom_handling(token).0.into_owned(),
+        )),
+    }
 }
 
-fn read_string(reader: &mut BufRead, delim: u8) -> Result<String, Error> {
+fn read_number(reader: &mut OffsetReader<impl BufRead>, delim: u8) -> Result<usize, Error> {
+    let offset = reader.offset();
     let mut buf = Vec::new();
     reader.read_until(delim, &mut buf)?;
     buf.pop();
-    Ok(String::from_utf8(buf)?)
+
+    let field_str = String::from_utf8_lossy(&buf);
+    field_str.trim().parse().map_err(|e| {
+        format_err!(
+            "Cannot parse number at byte offset {}: {} ({})",
+            offset,
+            field_str,
+            e
+        )
+    })
+}
+
+/// Read a token, which is terminated by a space.
+fn read_token(reader: &mut OffsetReader<impl BufRead>) -> Result<Vec<u8>, Error> {
+    let offset = reader.offset();
+    let mut token = Vec::new();
+    reader.read_until(b' ', &mut token)?;
+
+    if token.pop() != Some(b' ') {
+        bail!("Unexpected end of file in token at byte offset {}", offset);
+    }
+
+    Ok(token)
+}
+
+fn skip_whitespace(reader: &mut impl BufRead) -> Result<(), Error> {
+    loop {
+        let (n_whitespace, buf_len) = {
+            let buf = reader.fill_buf()?;
+            (
+                buf.iter().take_while(|b| b.is_ascii_whitespace()).count(),
+                buf.len(),
+            )
+        };
+
+        reader.consume(n_whitespace);
+
+        if n_whitespace < buf_len || buf_len == 0 {
+            return Ok(());
+        }
+    }
 }
 
 unsafe fn typed_to_bytes<T>(slice: &mut [T]) -> &mut [u8] {